@@ -0,0 +1,102 @@
+use ffirs_commands::{cmd_manager::CmdManager, cmd_tree::CmdTree};
+use rustyline::{
+    completion::Completer, highlight::Highlighter, hint::Hinter, validate::Validator, Context,
+    Helper,
+};
+use std::sync::Arc;
+
+/// Completes the literals of the command tree as they are typed.
+pub struct CmdHelper {
+    tree: Arc<CmdTree>,
+}
+
+impl CmdHelper {
+    pub fn new(tree: Arc<CmdTree>) -> Self {
+        Self { tree }
+    }
+}
+
+/// Where the word being typed at the end of `line` starts, and the words it can become.
+/// The words before are split like commands are, nothing is completed inside quotes.
+pub fn complete_line(tree: &CmdTree, line: &str) -> (usize, Vec<String>) {
+    let start = line
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let frags = match CmdManager::split_fragments(line[..start].to_owned()) {
+        Ok(frags) => frags,
+        Err(_) => return (start, Vec::new()),
+    };
+
+    (start, CmdManager::complete(tree, &frags, &line[start..]))
+}
+
+impl Completer for CmdHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete_line(&self.tree, &line[..pos]))
+    }
+}
+
+impl Hinter for CmdHelper {}
+
+impl Highlighter for CmdHelper {}
+
+impl Validator for CmdHelper {}
+
+impl Helper for CmdHelper {}
+
+#[cfg(test)]
+mod tests {
+    use crate::completion::complete_line;
+    use ffirs_commands::{
+        cmd_args::CmdArgs, cmd_creator::CmdCreator, cmd_handler::CmdHandler, cmd_tree::CmdNode,
+        context::CmdContext, error::CmdResult,
+    };
+    use serenity::async_trait;
+
+    struct Noop;
+
+    #[async_trait]
+    impl CmdHandler for Noop {
+        async fn execute(&self, _: &dyn CmdContext, _: &mut CmdArgs) -> CmdResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn test_complete_line() {
+        let tree = CmdNode::root(vec![
+            CmdCreator::create_cmd("prefix set <prefix: Word>", Noop).unwrap(),
+            CmdCreator::create_cmd("prefix reset", Noop).unwrap(),
+            CmdCreator::create_cmd("ping", Noop).unwrap(),
+            CmdCreator::create_cmd("tag <name: Word> show", Noop).unwrap(),
+        ]);
+
+        assert_eq!(
+            complete_line(&tree, "p"),
+            (0, vec!["ping".into(), "prefix".into()])
+        );
+        assert_eq!(complete_line(&tree, "prefix  r"), (8, vec!["reset".into()]));
+        assert_eq!(
+            complete_line(&tree, "prefix "),
+            (7, vec!["reset".into(), "set".into()])
+        );
+        assert_eq!(complete_line(&tree, "prefix set "), (11, vec![]));
+        assert_eq!(complete_line(&tree, "pong "), (5, vec![]));
+
+        // Quoted arguments are a single fragment
+        assert_eq!(
+            complete_line(&tree, "tag 'my tag' s"),
+            (13, vec!["show".into()])
+        );
+        assert_eq!(complete_line(&tree, "tag 'my ta"), (8, vec![]));
+    }
+}
@@ -0,0 +1,202 @@
+//! Runs the commands of the bot from a terminal, without Discord:
+//! every line is a command sent by a fake user in a fake guild, replies are printed.
+
+mod completion;
+
+use anyhow::Result;
+use completion::CmdHelper;
+use ffirs_commands::{
+    clock::SystemClock,
+    cmd_manager::CmdManager,
+    context::{CmdContext, CmdUser},
+    cooldown::Cooldowns,
+    diagnostic::Diagnostic,
+    perms::PermResolver,
+};
+use ffirs_core::{
+    awaiter::{Awaiters, IncomingMessage},
+    commands::{help_command, CoreModule},
+    config::LiveConfig,
+    locale::core_locales,
+    module_manager::ModuleManager,
+    prefix::{PrefixResolver, StoragePrefixStore},
+    reminders::ReminderModule,
+    scheduler::Scheduler,
+    shutdown::Shutdown,
+    state::SharedState,
+    storage::Database,
+    toggles::Toggles,
+    transport::{Messenger, TerminalContext, TerminalMessenger},
+};
+use log::{error, warn, LevelFilter};
+use rustyline::{error::ReadlineError, Editor};
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use simplelog::{Config, SimpleLogger};
+use std::sync::Arc;
+
+/// The user typing, owner of the bot.
+const USER: UserId = UserId(1);
+const BOT: UserId = UserId(2);
+const GUILD: GuildId = GuildId(3);
+const CHANNEL: ChannelId = ChannelId(4);
+const HISTORY_PATH: &str = ".ffirs_history";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    SimpleLogger::init(LevelFilter::Warn, Config::default())?;
+
+    // Nothing typed here is kept once the program exits
+    let config = ffirs_core::config::Config {
+        owners: vec![USER.0],
+        database: String::from(":memory:"),
+        ..Default::default()
+    };
+    let config = Arc::new(LiveConfig::new("ffirs.toml", config));
+    let current = config.get();
+
+    let database = Arc::new(Database::in_memory()?);
+    let prefixes = Arc::new(
+        PrefixResolver::new(vec![current.prefix.clone()])
+            .with_store(Box::new(StoragePrefixStore::new(database.clone()))),
+    );
+    let toggles = Arc::new(Toggles::new());
+    let manager = Arc::new(
+        CmdManager::with_default_mappers()
+            .with_middleware(PermResolver::with_owners(vec![USER]))
+            .with_middleware(toggles.clone())
+            .with_middleware(Cooldowns::default()),
+    );
+    let locales = Arc::new(core_locales()?);
+    let shutdown = Arc::new(Shutdown::default());
+    let scheduler = Arc::new(Scheduler::new(database.clone(), Arc::new(SystemClock))?);
+    let awaiters = Arc::new(Awaiters::new());
+
+    let mut modules = ModuleManager::new()
+        .with_module(CoreModule::new(
+            config.clone(),
+            prefixes.clone(),
+            locales.clone(),
+            toggles.clone(),
+            shutdown.clone(),
+        ))
+        .with_module(ReminderModule::new());
+    modules.check_settings(&current)?;
+    modules.configure(&current);
+    database.migrate(&modules.migrations()?)?;
+
+    let state = SharedState::new();
+    state.insert(config.clone()).await;
+    state.insert(database.clone()).await;
+    state.insert(locales.clone()).await;
+    state.insert(shutdown.clone()).await;
+    state.insert(scheduler.clone()).await;
+    state.insert(awaiters.clone()).await;
+    let messenger: Arc<dyn Messenger> = Arc::new(TerminalMessenger::default());
+    state.insert(messenger).await;
+
+    let (mut tree, _) = modules.startup(&state).await?;
+    let help = help_command(locales.clone(), &tree.children)?;
+    tree.children.push(help);
+    locales.apply_aliases(&mut tree);
+    toggles.declare(&modules, &tree);
+    let tree = Arc::new(tree);
+    let modules = Arc::new(modules);
+
+    {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move { scheduler.run(&shutdown).await });
+    }
+
+    let mut editor = Editor::<CmdHelper>::new();
+    editor.set_helper(Some(CmdHelper::new(tree.clone())));
+    // There is no history the first time
+    let _ = editor.load_history(HISTORY_PATH);
+
+    let user = CmdUser {
+        id: USER,
+        name: String::from("developer"),
+        bot: false,
+    };
+    let mut next_id = 0;
+    let mut running = None;
+
+    loop {
+        // The next line is read once the running command is done or asks for it
+        if let Some(command) = running.as_mut() {
+            let done = tokio::select! {
+                _ = command => true,
+                _ = awaiters.prompted() => false,
+            };
+            if done {
+                running = None;
+            }
+        }
+        if shutdown.is_shutting_down() {
+            break;
+        }
+
+        // Reading blocks until a line is typed, the commands keep running meanwhile
+        let (line, returned) = tokio::task::spawn_blocking(move || {
+            let line = editor.readline("> ");
+            (line, editor)
+        })
+        .await?;
+        editor = returned;
+        let line = match line {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line);
+
+        next_id += 1;
+        // Answers to a prompt of the running command aren't commands
+        let message = IncomingMessage {
+            id: MessageId(next_id),
+            author: USER,
+            channel: CHANNEL,
+            content: line.to_owned(),
+        };
+        if awaiters.route_message(&message) {
+            continue;
+        }
+
+        // Commands work with or without a prefix
+        let raw = prefixes
+            .strip(line, Some(GUILD), BOT)
+            .unwrap_or(line)
+            .to_owned();
+        let ctx = TerminalContext::new(user.clone(), line)
+            .with_guild(GUILD)
+            .with_channel(CHANNEL)
+            .with_message_id(MessageId(next_id))
+            .with_data(state.data().clone());
+        let (manager, tree, locales) = (manager.clone(), tree.clone(), locales.clone());
+        let modules = modules.clone();
+        running = Some(tokio::spawn(async move {
+            modules.message(&ctx).await;
+            if let Err(err) = manager.dispatch(&tree, &ctx, &raw).await {
+                if Diagnostic::is_internal(&err) {
+                    error!("{}", err);
+                }
+
+                let locale = locales.locale_of(USER, Some(GUILD));
+                let diagnostic = Diagnostic::new(&err, &raw, &tree, &locales, &locale);
+                if let Err(err) = ctx.send_embed(&diagnostic.embed()).await {
+                    warn!("Failed to report a command error: {}", err);
+                }
+            }
+        }));
+    }
+
+    if let Err(err) = editor.save_history(HISTORY_PATH) {
+        warn!("Failed to save the history: {}", err);
+    }
+    shutdown.run(&modules).await;
+
+    Ok(())
+}
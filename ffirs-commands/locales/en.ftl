@@ -0,0 +1,25 @@
+# Errors shown to users, see `Locales::error`.
+error-parsing = Command parsing error at [{ $start }..{ $end }]: { $message }
+error-not-found = Command not found: { $name }
+error-no-path-found = No matching path found
+error-missing-perm = Missing permission, required level: { $level }
+error-missing-permissions = Missing Discord permissions: { $missing }
+error-on-cooldown = Command on cooldown, retry in { $seconds }s
+error-out-of-range = Value { $value } is out of range, expected { $range }
+error-missing-context-arg = Missing { $kind } for argument { $name }
+error-guild-only = This command can only be used in a guild
+error-discord-only = This command can only be used on Discord
+error-unknown-locale = Unknown locale { $locale }, available: { $available }
+error-internal = Something went wrong while running this command.
+
+context-arg-attachment = attachment
+context-arg-replied-message = replied message
+
+# Diagnostics
+diagnostic-usage = Usage
+diagnostic-hint = Hint
+hint-unknown-command = Unknown command `{ $command }`
+hint-unexpected = Unexpected `{ $fragment }`, check the usage
+hint-missing-arguments = Missing arguments
+hint-attach-file = Attach a file to your message
+hint-reply-message = Reply to a message with this command
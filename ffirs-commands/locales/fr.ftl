@@ -0,0 +1,25 @@
+# Erreurs affichées aux utilisateurs, voir `Locales::error`.
+error-parsing = Erreur de lecture de la commande à [{ $start }..{ $end }] : { $message }
+error-not-found = Commande introuvable : { $name }
+error-no-path-found = Aucune commande ne correspond
+error-missing-perm = Permission insuffisante, niveau requis : { $level }
+error-missing-permissions = Permissions Discord manquantes : { $missing }
+error-on-cooldown = Commande en recharge, réessayez dans { $seconds } s
+error-out-of-range = La valeur { $value } est hors limites, attendu { $range }
+error-missing-context-arg = Il manque { $kind } pour l'argument { $name }
+error-guild-only = Cette commande ne peut être utilisée que dans un serveur
+error-discord-only = Cette commande ne peut être utilisée que sur Discord
+error-unknown-locale = Langue inconnue { $locale }, disponibles : { $available }
+error-internal = Une erreur est survenue pendant l'exécution de cette commande.
+
+context-arg-attachment = une pièce jointe
+context-arg-replied-message = un message auquel répondre
+
+# Diagnostics
+diagnostic-usage = Utilisation
+diagnostic-hint = Astuce
+hint-unknown-command = Commande inconnue `{ $command }`
+hint-unexpected = `{ $fragment }` inattendu, vérifiez l'utilisation
+hint-missing-arguments = Arguments manquants
+hint-attach-file = Joignez un fichier à votre message
+hint-reply-message = Répondez à un message avec cette commande
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Mutex;
+
+/// Source of the current time, swapped for a `MockClock` in tests.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The real wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now = *now + duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{Clock, MockClock};
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    pub fn test_mock_clock() {
+        let clock = MockClock::new(Utc.ymd(2020, 6, 1).and_hms(12, 0, 0));
+
+        assert_eq!(clock.now(), Utc.ymd(2020, 6, 1).and_hms(12, 0, 0));
+        clock.advance(Duration::minutes(90));
+        assert_eq!(clock.now(), Utc.ymd(2020, 6, 1).and_hms(13, 30, 0));
+        clock.set(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0));
+        assert_eq!(clock.now(), Utc.ymd(2021, 1, 1).and_hms(0, 0, 0));
+    }
+}
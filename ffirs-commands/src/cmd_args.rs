@@ -0,0 +1,95 @@
+use crate::mappers::MapperOutput;
+
+/// Mapped arguments of a command, in the order of its path.
+/// Literals are left out, only typed and pseudo-arguments are kept.
+#[derive(Debug, Default)]
+pub struct CmdArgs {
+    values: Vec<(Option<String>, MapperOutput)>,
+}
+
+impl CmdArgs {
+    pub fn new() -> Self {
+        Self { values: Vec::new() }
+    }
+
+    pub(crate) fn push(&mut self, name: Option<String>, value: MapperOutput) {
+        self.values.push((name, value));
+    }
+
+    /// Get a named argument, `None` if it doesn't exist or isn't a `T`.
+    pub fn get<T: 'static>(&self, name: &str) -> Option<&T> {
+        self.values
+            .iter()
+            .find(|(n, _)| n.as_deref() == Some(name))
+            .and_then(|(_, value)| value.downcast_ref())
+    }
+
+    /// Get an argument by position, `None` if it doesn't exist or isn't a `T`.
+    pub fn nth<T: 'static>(&self, index: usize) -> Option<&T> {
+        self.values.get(index)?.1.downcast_ref()
+    }
+
+    /// Take ownership of a named argument.
+    pub fn take<T: 'static>(&mut self, name: &str) -> Option<T> {
+        let index = self
+            .values
+            .iter()
+            .position(|(n, value)| n.as_deref() == Some(name) && value.is::<T>())?;
+
+        let (_, value) = self.values.remove(index);
+        value.downcast().ok().map(|value| *value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd_args::CmdArgs;
+
+    fn make_args() -> CmdArgs {
+        let mut args = CmdArgs::new();
+        args.push(Some("n".into()), Box::new(42_u64));
+        args.push(None, Box::new(-1_i64));
+        args.push(Some("text".into()), Box::new(String::from("hey")));
+        args
+    }
+
+    #[test]
+    pub fn test_args_get() {
+        let args = make_args();
+
+        assert_eq!(args.len(), 3);
+        assert_eq!(args.get::<u64>("n"), Some(&42));
+        assert_eq!(args.get::<String>("text"), Some(&String::from("hey")));
+
+        assert_eq!(args.get::<i64>("n"), None);
+        assert_eq!(args.get::<u64>("nope"), None);
+    }
+
+    #[test]
+    pub fn test_args_nth() {
+        let args = make_args();
+
+        assert_eq!(args.nth::<u64>(0), Some(&42));
+        assert_eq!(args.nth::<i64>(1), Some(&-1));
+        assert_eq!(args.nth::<i64>(0), None);
+        assert_eq!(args.nth::<u64>(3), None);
+    }
+
+    #[test]
+    pub fn test_args_take() {
+        let mut args = make_args();
+
+        assert_eq!(args.take::<u64>("text"), None);
+        assert_eq!(args.take::<String>("text"), Some(String::from("hey")));
+        assert_eq!(args.take::<String>("text"), None);
+        assert_eq!(args.len(), 2);
+    }
+}
@@ -1,8 +1,13 @@
+use crate::cmd_handler::CmdHandler;
 use crate::cmd_tree::CmdNode;
 use crate::cmd_tree_builder::CmdTreeBuilderBranched;
 use crate::error::{CmdError, CmdResult};
 use crate::matchers::{
-    ExactMatcher, FragMatcher, SignedMatcher, UnsignedMatcher, UserMentionMatcher,
+    AttachmentMatcher, BoolMatcher, ChannelMentionMatcher, ChoiceMatcher, ColourMatcher,
+    DateTimeMatcher, DurationMatcher, ExactMatcher, FloatMatcher, FragMatcher,
+    LocalDateTimeMatcher, MessageLinkMatcher, RangeMatcher, RegexMatcher, RepliedMessageMatcher,
+    RestMatcher, SignedMatcher, SnowflakeMatcher, UnsignedMatcher, UrlMatcher, UserMentionMatcher,
+    WordMatcher,
 };
 
 pub(self) struct CmdCreatorBranchIter {
@@ -18,12 +23,156 @@ impl CmdCreatorBranchIter {
         }
     }
 
+    /// Byte length of the typed part `typed` starts with, without its `<` and `>`.
+    /// A `>` only ends it outside of parentheses and of a regex between slashes,
+    /// in which `\/` doesn't end the regex.
+    fn find_end_of_typed(typed: &str) -> Option<usize> {
+        let mut depth = 0usize;
+        let mut in_regex = false;
+        let mut escaped = false;
+        // After the separator, until the type starts
+        let mut before_type = false;
+
+        for (i, c) in typed.char_indices().skip(1) {
+            if in_regex {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == '/' {
+                    in_regex = false;
+                }
+                continue;
+            }
+
+            match c {
+                ':' if depth == 0 => before_type = true,
+                '/' if before_type => in_regex = true,
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                '>' if depth == 0 => return Some(i - 1),
+                _ => {}
+            }
+            if c != ':' && !c.is_whitespace() {
+                before_type = false;
+            }
+        }
+
+        None
+    }
+
+    /// The pattern of a regex between slashes, where `\/` is a slash.
+    fn unescape_slashes(pattern: &str) -> String {
+        let mut unescaped = String::with_capacity(pattern.len());
+        let mut chars = pattern.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('/') => unescaped.push('/'),
+                // Escapes of the regex itself, `\\` included
+                Some(next) => {
+                    unescaped.push(c);
+                    unescaped.push(next);
+                }
+                None => unescaped.push(c),
+            }
+        }
+
+        unescaped
+    }
+
     #[inline]
     fn make_matcher(ty: &str) -> CmdResult<Box<dyn FragMatcher>> {
-        match ty {
-            "Unsigned" => Ok(Box::new(UnsignedMatcher)),
-            "Signed" => Ok(Box::new(SignedMatcher)),
-            "UserMention" => Ok(Box::new(UserMentionMatcher::default())),
+        // A regex between slashes: `/[A-Z]+-\d+/`
+        if ty.len() >= 2 && ty.starts_with('/') && ty.ends_with('/') {
+            let pattern = Self::unescape_slashes(&ty[1..ty.len() - 1]);
+            return match RegexMatcher::new(&pattern) {
+                Ok(matcher) => Ok(Box::new(matcher)),
+                Err(err) => Err(CmdError::CreatorInvalidArgument {
+                    ty: "Regex".into(),
+                    argument: err.to_string(),
+                }),
+            };
+        }
+
+        // Some types take an argument between parenthesis: `Unsigned(1..=100)`
+        let (ty, argument) = match ty.find('(') {
+            Some(open) if ty.ends_with(')') => {
+                (ty[..open].trim(), Some(&ty[open + 1..ty.len() - 1]))
+            }
+            _ => (ty, None),
+        };
+
+        let invalid_argument = |argument: &str| CmdError::CreatorInvalidArgument {
+            ty: ty.into(),
+            argument: argument.into(),
+        };
+
+        match (ty, argument) {
+            ("Unsigned", None) => Ok(Box::new(UnsignedMatcher)),
+            ("Signed", None) => Ok(Box::new(SignedMatcher)),
+            ("Unsigned", Some(range)) | ("Signed", Some(range)) => {
+                let inner: Box<dyn FragMatcher> = if ty == "Unsigned" {
+                    Box::new(UnsignedMatcher)
+                } else {
+                    Box::new(SignedMatcher)
+                };
+                let range =
+                    RangeMatcher::parse_range(range).ok_or_else(|| invalid_argument(range))?;
+                Ok(Box::new(RangeMatcher::new_raw(inner, range)))
+            }
+            ("Word", None) => Ok(Box::new(WordMatcher)),
+            ("Rest", None) => Ok(Box::new(RestMatcher)),
+            ("Float", None) => Ok(Box::new(FloatMatcher)),
+            ("Bool", None) => Ok(Box::new(BoolMatcher)),
+            ("Choice", Some(choices)) => {
+                let choices = choices
+                    .split('|')
+                    .map(|choice| choice.trim().to_owned())
+                    .collect::<Vec<_>>();
+                if choices.iter().any(String::is_empty) {
+                    return Err(invalid_argument(argument.unwrap()));
+                }
+                Ok(Box::new(ChoiceMatcher::new(choices)))
+            }
+            ("UserMention", None) => Ok(Box::new(UserMentionMatcher::default())),
+            ("ChannelMention", None) => Ok(Box::new(ChannelMentionMatcher::default())),
+            ("Duration", None) => Ok(Box::new(DurationMatcher)),
+            ("DateTime", None) => Ok(Box::new(DateTimeMatcher::default())),
+            ("LocalDateTime", None) => Ok(Box::new(LocalDateTimeMatcher::default())),
+            ("MessageLink", None) => Ok(Box::new(MessageLinkMatcher)),
+            ("Url", None) => Ok(Box::new(UrlMatcher::default())),
+            ("Url", Some(schemes)) => {
+                let schemes = schemes.split('|').map(str::trim).collect::<Vec<_>>();
+                if schemes.iter().any(|scheme| scheme.is_empty()) {
+                    return Err(invalid_argument(argument.unwrap()));
+                }
+                Ok(Box::new(UrlMatcher::with_schemes(&schemes)))
+            }
+            ("Colour", None) | ("Color", None) => Ok(Box::new(ColourMatcher)),
+            ("Snowflake", None) => Ok(Box::new(SnowflakeMatcher)),
+            ("Attachment", None) => Ok(Box::new(AttachmentMatcher)),
+            ("RepliedMessage", None) => Ok(Box::new(RepliedMessageMatcher)),
+            ("Word", Some(argument))
+            | ("Rest", Some(argument))
+            | ("Float", Some(argument))
+            | ("Bool", Some(argument))
+            | ("UserMention", Some(argument))
+            | ("ChannelMention", Some(argument))
+            | ("Duration", Some(argument))
+            | ("DateTime", Some(argument))
+            | ("LocalDateTime", Some(argument))
+            | ("MessageLink", Some(argument))
+            | ("Colour", Some(argument))
+            | ("Color", Some(argument))
+            | ("Snowflake", Some(argument))
+            | ("Attachment", Some(argument))
+            | ("RepliedMessage", Some(argument)) => Err(invalid_argument(argument)),
             _ => Err(CmdError::CreatorUnknownMatcher { ty: ty.into() }),
         }
     }
@@ -50,11 +199,8 @@ impl Iterator for CmdCreatorBranchIter {
                 Some(Ok((Box::new(ExactMatcher::new(lit)), None)))
             } else {
                 // We have a typed part: <name: Type>
-                let segment = remaining
-                    .chars()
-                    .skip(1)
-                    .take_while(|&c| c != '>')
-                    .collect::<String>();
+                let end = Self::find_end_of_typed(remaining).unwrap_or(remaining.len() - 1);
+                let segment = remaining[1..1 + end].to_owned();
 
                 self.current_position += segment.len() + 3;
 
@@ -85,8 +231,19 @@ pub struct CmdCreator;
 
 impl CmdCreator {
     pub fn create_cmd_branch(format: &str) -> CmdResult<CmdNode> {
+        Self::make_builder(format)?.build()
+    }
+
+    /// Same as `create_cmd_branch` but the last node executes `handler`.
+    pub fn create_cmd<H: CmdHandler + 'static>(format: &str, handler: H) -> CmdResult<CmdNode> {
+        let mut builder = Self::make_builder(format)?;
+        builder.handler(handler)?;
+        builder.build()
+    }
+
+    fn make_builder(format: &str) -> CmdResult<CmdTreeBuilderBranched> {
         let mut builder = CmdTreeBuilderBranched::new();
-        let mut iter = CmdCreatorBranchIter::new(format.into());
+        let iter = CmdCreatorBranchIter::new(format.into());
 
         for res in iter {
             let (matcher, name) = res?;
@@ -98,14 +255,20 @@ impl CmdCreator {
             }
         }
 
-        builder.build()
+        Ok(builder)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::cmd_creator::CmdCreatorBranchIter;
-    use crate::matchers::{ExactMatcher, FragMatcher, SignedMatcher, UserMentionMatcher};
+    use crate::error::CmdError;
+    use crate::matchers::{
+        AttachmentMatcher, BoolMatcher, ChoiceMatcher, ColourMatcher, ContextArg, DateTimeMatcher,
+        DurationMatcher, ExactMatcher, FloatMatcher, FragMatcher, LocalDateTimeMatcher,
+        MessageLinkMatcher, RangeMatcher, RegexCaptures, RegexMatcher, RepliedMessageMatcher,
+        RestMatcher, SignedMatcher, SnowflakeMatcher, UrlMatcher, UserMentionMatcher, WordMatcher,
+    };
     use std::any::TypeId;
 
     fn downcast_pattern<T: FragMatcher>(node: &dyn FragMatcher) -> &T {
@@ -154,13 +317,13 @@ mod tests {
         let next = iter.next();
         assert!(next.is_some());
         let next = next.unwrap().unwrap();
-        assert_eq!(next.0.type_id(), TypeId::of::<SignedMatcher>());
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<SignedMatcher>());
         assert_eq!(&next.1.unwrap(), "a");
 
         let next = iter.next();
         assert!(next.is_some());
         let next = next.unwrap().unwrap();
-        assert_eq!(next.0.type_id(), TypeId::of::<UserMentionMatcher>());
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<UserMentionMatcher>());
         assert_eq!(&next.1.unwrap(), "b");
 
         assert!(iter.next().is_none());
@@ -181,4 +344,219 @@ mod tests {
         assert!(next.is_some());
         assert!(next.unwrap().is_err());
     }
+
+    #[test]
+    pub fn test_creator_iter_time() {
+        let mut iter = CmdCreatorBranchIter::new("remind <in: Duration> <at: DateTime>".into());
+
+        assert!(iter.next().unwrap().is_ok());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<DurationMatcher>());
+        assert_eq!(&next.1.unwrap(), "in");
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<DateTimeMatcher>());
+        assert_eq!(&next.1.unwrap(), "at");
+
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    pub fn test_creator_iter_rest() {
+        let mut iter =
+            CmdCreatorBranchIter::new("remind at <when: LocalDateTime> <text: Rest>".into());
+
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_ok());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(
+            next.0.as_ref().type_id(),
+            TypeId::of::<LocalDateTimeMatcher>()
+        );
+        assert_eq!(&next.1.unwrap(), "when");
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<RestMatcher>());
+        assert!(next.0.takes_rest());
+        assert_eq!(&next.1.unwrap(), "text");
+
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    pub fn test_creator_iter_word() {
+        let mut iter = CmdCreatorBranchIter::new("prefix set <prefix: Word>".into());
+
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_ok());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<WordMatcher>());
+        assert_eq!(next.0.fragment_type_id(), TypeId::of::<String>());
+        assert_eq!(&next.1.unwrap(), "prefix");
+
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    pub fn test_creator_iter_arguments() {
+        let mut iter = CmdCreatorBranchIter::new(
+            "roll <n: Unsigned(1..=100)> <ratio: Float> <loud: Bool> <mode: Choice(fast | slow)>"
+                .into(),
+        );
+
+        assert!(iter.next().unwrap().is_ok());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<RangeMatcher>());
+        assert_eq!(next.0.fragment_type_id(), TypeId::of::<u64>());
+        assert!(next.0.check("100").is_ok());
+        assert!(next.0.check("101").is_err());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<FloatMatcher>());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<BoolMatcher>());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(
+            downcast_pattern::<ChoiceMatcher>(next.0.as_ref()),
+            &ChoiceMatcher::new(vec!["fast".into(), "slow".into()])
+        );
+        assert_eq!(&next.1.unwrap(), "mode");
+
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    pub fn test_creator_iter_invalid_arguments() {
+        for format in &[
+            "<n: Unsigned(1-100)>",
+            "<n: Signed(a..b)>",
+            "<c: Choice(a||b)>",
+            "<d: Duration(1..2)>",
+        ] {
+            let mut iter = CmdCreatorBranchIter::new((*format).into());
+            match iter.next().unwrap() {
+                Err(CmdError::CreatorInvalidArgument { .. }) => {}
+                _ => unreachable!(),
+            }
+        }
+
+        let mut iter = CmdCreatorBranchIter::new("<n: Garbage(1..2)>".into());
+        match iter.next().unwrap() {
+            Err(CmdError::CreatorUnknownMatcher { ty }) => assert_eq!(ty, "Garbage"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    pub fn test_creator_iter_regex() {
+        let mut iter = CmdCreatorBranchIter::new("ticket <id: /[A-Z]+-\\d+/>".into());
+
+        assert!(iter.next().unwrap().is_ok());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(
+            downcast_pattern::<RegexMatcher>(next.0.as_ref()),
+            &RegexMatcher::new("[A-Z]+-\\d+").unwrap()
+        );
+        assert_eq!(next.0.fragment_type_id(), TypeId::of::<RegexCaptures>());
+        assert!(next.0.matches("FFI-42"));
+        assert!(!next.0.matches("see FFI-42"));
+        assert_eq!(&next.1.unwrap(), "id");
+
+        assert!(iter.next().is_none());
+
+        let mut iter = CmdCreatorBranchIter::new("<id: /(unclosed/>".into());
+        match iter.next().unwrap() {
+            Err(CmdError::CreatorInvalidArgument { ty, .. }) => assert_eq!(ty, "Regex"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    pub fn test_creator_iter_closing_chevrons() {
+        // A `>` of a regex or of an argument doesn't end the typed part
+        let mut iter = CmdCreatorBranchIter::new(
+            "<arrow: /->|<-/> <pair: /(?P<key>\\w+)\\/(?P<value>\\w+)/> <c: Choice(a>b|b<a)> end"
+                .into(),
+        );
+
+        let next = iter.next().unwrap().unwrap();
+        assert!(next.0.matches("->"));
+        assert!(next.0.matches("<-"));
+        assert_eq!(&next.1.unwrap(), "arrow");
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(
+            downcast_pattern::<RegexMatcher>(next.0.as_ref()),
+            &RegexMatcher::new("(?P<key>\\w+)/(?P<value>\\w+)").unwrap()
+        );
+        assert!(next.0.matches("lang/rust"));
+        assert_eq!(&next.1.unwrap(), "pair");
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(
+            downcast_pattern::<ChoiceMatcher>(next.0.as_ref()),
+            &ChoiceMatcher::new(vec!["a>b".into(), "b<a".into()])
+        );
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(
+            downcast_pattern::<ExactMatcher>(next.0.as_ref()),
+            &ExactMatcher::new("end".into())
+        );
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    pub fn test_creator_iter_discord_types() {
+        let mut iter = CmdCreatorBranchIter::new(
+            "quote <link: MessageLink> <site: Url(https)> <c: Colour> <id: Snowflake>".into(),
+        );
+
+        assert!(iter.next().unwrap().is_ok());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<MessageLinkMatcher>());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(
+            downcast_pattern::<UrlMatcher>(next.0.as_ref()),
+            &UrlMatcher::with_schemes(&["https"])
+        );
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<ColourMatcher>());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<SnowflakeMatcher>());
+        assert_eq!(&next.1.unwrap(), "id");
+
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    pub fn test_creator_iter_pseudo_arguments() {
+        let mut iter =
+            CmdCreatorBranchIter::new("upload <file: Attachment> <target: RepliedMessage>".into());
+
+        assert!(iter.next().unwrap().is_ok());
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<AttachmentMatcher>());
+        assert_eq!(next.0.context_arg(), Some(ContextArg::Attachment));
+        assert_eq!(&next.1.unwrap(), "file");
+
+        let next = iter.next().unwrap().unwrap();
+        assert_eq!(next.0.as_ref().type_id(), TypeId::of::<RepliedMessageMatcher>());
+        assert_eq!(next.0.context_arg(), Some(ContextArg::RepliedMessage));
+        assert_eq!(&next.1.unwrap(), "target");
+
+        assert!(iter.next().is_none());
+    }
 }
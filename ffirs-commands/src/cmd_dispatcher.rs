@@ -0,0 +1,328 @@
+use crate::cmd_args::CmdArgs;
+use crate::cmd_manager::CmdManager;
+use crate::cmd_tree::{CmdNode, CmdTree};
+use crate::context::CmdContext;
+use crate::error::{CmdError, CmdResult};
+use crate::fragment_iter::Fragments;
+use crate::mappers::MapperOutput;
+use crate::matchers::ContextArg;
+use crate::middleware::Invocation;
+use std::any::TypeId;
+
+impl CmdManager {
+    /// Find the command matching `raw`, the message content without its prefix,
+    /// map its arguments and execute it, running the middlewares around it.
+    pub async fn dispatch(
+        &self,
+        cmd_root: &CmdTree,
+        ctx: &dyn CmdContext,
+        raw: &str,
+    ) -> CmdResult<()> {
+        let mut invocation = Invocation {
+            ctx,
+            raw,
+            path: Vec::new(),
+            args: CmdArgs::new(),
+        };
+
+        let result = self.invoke(cmd_root, &mut invocation).await;
+
+        for middleware in self.middlewares().iter().rev() {
+            match &result {
+                Ok(()) => middleware.after(&invocation).await,
+                Err(error) => middleware.on_error(&invocation, error).await,
+            }
+        }
+
+        result
+    }
+
+    async fn invoke<'a>(
+        &self,
+        cmd_root: &'a CmdTree,
+        invocation: &mut Invocation<'a>,
+    ) -> CmdResult<()> {
+        let frags = Self::split_fragments(invocation.raw.to_owned())?;
+        invocation.path = Self::find_cmd_path(cmd_root, &frags)?;
+
+        // A path always ends on a leaf, but it still has to be executable
+        let handler = invocation.path[0]
+            .handler
+            .clone()
+            .ok_or(CmdError::NoPathFound)?;
+
+        // Refused commands never get their arguments mapped, which can call Discord
+        for middleware in self.middlewares() {
+            middleware.before(invocation).await?;
+        }
+
+        invocation.args = self
+            .map_args(&invocation.path, &frags, invocation.ctx)
+            .await?;
+
+        handler.execute(invocation.ctx, &mut invocation.args).await
+    }
+
+    /// Map the arguments of a path found with `find_cmd_path`.
+    /// Pseudo-arguments are resolved from the message, literals are skipped.
+    /// They are only found on Discord, other transports have no attachments or replies.
+    pub async fn map_args(
+        &self,
+        path: &[&CmdNode],
+        frags: &Fragments,
+        ctx: &dyn CmdContext,
+    ) -> CmdResult<CmdArgs> {
+        let mut args = CmdArgs::new();
+        let mut attachments = ctx
+            .discord()
+            .map(|(_, message)| message.attachments.iter())
+            .into_iter()
+            .flatten();
+
+        for (node, frag) in Self::pair_fragments(path, frags) {
+            let value = match (node.matcher.context_arg(), frag) {
+                (Some(kind), _) => {
+                    let value = match kind {
+                        ContextArg::Attachment => attachments
+                            .next()
+                            .map(|attachment| Box::new(attachment.clone()) as MapperOutput),
+                        ContextArg::RepliedMessage => Self::fetch_replied_message(ctx).await?,
+                    };
+
+                    value.ok_or_else(|| CmdError::MissingContextArg {
+                        name: node.name.clone().unwrap_or_default(),
+                        kind,
+                    })?
+                }
+                (None, Some(frag)) => {
+                    if node.matcher.fragment_type_id() == TypeId::of::<()>() {
+                        continue;
+                    }
+                    self.try_map_node(node, &frag)?
+                }
+                // Only roots are left without a fragment, they have nothing to map
+                (None, None) => continue,
+            };
+
+            args.push(node.name.clone(), value);
+        }
+
+        Ok(args)
+    }
+
+    async fn fetch_replied_message(ctx: &dyn CmdContext) -> CmdResult<Option<MapperOutput>> {
+        let (ctx, message) = match ctx.discord() {
+            Some(discord) => discord,
+            None => return Ok(None),
+        };
+        let reference = match &message.message_reference {
+            Some(reference) => reference,
+            None => return Ok(None),
+        };
+
+        match reference.message_id {
+            Some(message_id) => {
+                let replied = ctx
+                    .http
+                    .get_message(reference.channel_id.0, message_id.0)
+                    .await?;
+                Ok(Some(Box::new(replied)))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd_args::CmdArgs;
+    use crate::cmd_creator::CmdCreator;
+    use crate::cmd_handler::CmdHandler;
+    use crate::cmd_manager::CmdManager;
+    use crate::cmd_tree::CmdNode;
+    use crate::context::{CmdContext, CmdUser, Embed};
+    use crate::error::{CmdError, CmdResult};
+    use crate::mappers::{FragmentMapper, MapperOutput};
+    use crate::middleware::{Invocation, Middleware};
+    use crate::type_map::AnyTypeMap;
+    use serenity::async_trait;
+    use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+    use serenity::prelude::RwLock;
+    use std::sync::{Arc, Mutex};
+
+    type Events = Arc<Mutex<Vec<String>>>;
+
+    struct TestContext {
+        author: CmdUser,
+        data: Arc<RwLock<AnyTypeMap>>,
+    }
+
+    impl TestContext {
+        fn new() -> Self {
+            Self {
+                author: CmdUser {
+                    id: UserId(7),
+                    name: String::from("ferris"),
+                    bot: false,
+                },
+                data: Arc::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl CmdContext for TestContext {
+        fn author(&self) -> &CmdUser {
+            &self.author
+        }
+
+        fn guild(&self) -> Option<GuildId> {
+            None
+        }
+
+        fn channel(&self) -> ChannelId {
+            ChannelId(1)
+        }
+
+        fn message_id(&self) -> MessageId {
+            MessageId(1)
+        }
+
+        fn content(&self) -> &str {
+            ""
+        }
+
+        fn data(&self) -> &Arc<RwLock<AnyTypeMap>> {
+            &self.data
+        }
+
+        async fn reply(&self, _content: &str) -> CmdResult<()> {
+            Ok(())
+        }
+
+        async fn send_embed(&self, _embed: &Embed) -> CmdResult<()> {
+            Ok(())
+        }
+
+        async fn react(&self, _emoji: &str) -> CmdResult<()> {
+            Ok(())
+        }
+
+        async fn fetch_user(&self, _user: UserId) -> CmdResult<CmdUser> {
+            Ok(self.author.clone())
+        }
+    }
+
+    struct RecordingMapper(Events);
+
+    impl FragmentMapper for RecordingMapper {
+        fn map(&self, fragment: &str) -> MapperOutput {
+            self.0.lock().unwrap().push(format!("map {}", fragment));
+            Box::new(fragment.parse::<u64>().unwrap())
+        }
+    }
+
+    struct RecordingMiddleware {
+        events: Events,
+        refuse: bool,
+    }
+
+    #[async_trait]
+    impl Middleware for RecordingMiddleware {
+        async fn before(&self, invocation: &Invocation<'_>) -> CmdResult<()> {
+            self.events.lock().unwrap().push(String::from("before"));
+            assert!(invocation.args.is_empty());
+
+            if self.refuse {
+                return Err(CmdError::MissingPerm { level: 1 });
+            }
+            Ok(())
+        }
+
+        async fn after(&self, _invocation: &Invocation<'_>) {
+            self.events.lock().unwrap().push(String::from("after"));
+        }
+
+        async fn on_error(&self, _invocation: &Invocation<'_>, error: &CmdError) {
+            self.events.lock().unwrap().push(format!("error {}", error));
+        }
+    }
+
+    /// Fails on 0.
+    struct RollCmd(Events);
+
+    #[async_trait]
+    impl CmdHandler for RollCmd {
+        async fn execute(&self, _ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()> {
+            let sides = args.take::<u64>("sides").unwrap_or_default();
+            self.0.lock().unwrap().push(format!("roll {}", sides));
+
+            match sides {
+                0 => Err(CmdError::NoPathFound),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    fn setup(refuse: bool) -> (CmdManager, CmdNode, Events) {
+        let events = Events::default();
+        let manager = CmdManager::new()
+            .register_mapper::<u64>(Box::new(RecordingMapper(events.clone())))
+            .with_middleware(RecordingMiddleware {
+                events: events.clone(),
+                refuse,
+            });
+        let tree = CmdNode::root(vec![CmdCreator::create_cmd(
+            "roll <sides: Unsigned>",
+            RollCmd(events.clone()),
+        )
+        .unwrap()]);
+
+        (manager, tree, events)
+    }
+
+    fn take(events: &Events) -> Vec<String> {
+        events.lock().unwrap().split_off(0)
+    }
+
+    #[tokio::test]
+    pub async fn test_dispatch_refused_never_maps() {
+        let (manager, tree, events) = setup(true);
+        let ctx = TestContext::new();
+
+        assert!(matches!(
+            manager.dispatch(&tree, &ctx, "roll 6").await,
+            Err(CmdError::MissingPerm { level: 1 })
+        ));
+        assert_eq!(
+            take(&events),
+            vec!["before", "error Missing permission, required level: 1"]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_dispatch_after_and_on_error() {
+        let (manager, tree, events) = setup(false);
+        let ctx = TestContext::new();
+
+        manager.dispatch(&tree, &ctx, "roll 6").await.unwrap();
+        assert_eq!(take(&events), vec!["before", "map 6", "roll 6", "after"]);
+
+        assert!(manager.dispatch(&tree, &ctx, "roll 0").await.is_err());
+        assert_eq!(
+            take(&events),
+            vec!["before", "map 0", "roll 0", "error No matching path found"]
+        );
+
+        // Commands not found never reach `before`
+        assert!(manager.dispatch(&tree, &ctx, "flip").await.is_err());
+        assert_eq!(take(&events), vec!["error No matching path found"]);
+
+        // Numbers that don't fit are never mapped
+        assert!(manager
+            .dispatch(&tree, &ctx, "roll 99999999999999999999")
+            .await
+            .is_err());
+        assert_eq!(take(&events), vec!["error No matching path found"]);
+    }
+}
@@ -0,0 +1,11 @@
+use crate::cmd_args::CmdArgs;
+use crate::context::CmdContext;
+use crate::error::CmdResult;
+use serenity::async_trait;
+
+/// The code behind a command, attached to the last node of its path.
+/// Arguments are lent so middlewares still see what is left of them afterwards.
+#[async_trait]
+pub trait CmdHandler: Send + Sync {
+    async fn execute(&self, ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()>;
+}
@@ -1,29 +1,100 @@
-use crate::mappers::FragmentMapper;
+use crate::cmd_tree::CmdNode;
+use crate::error::{CmdError, CmdResult};
+use crate::mappers::{
+    BoolMapper, ChannelMapper, ColourMapper, DateTimeMapper, DurationMapper, ExactMapper,
+    FragmentMapper, FromStrMapper, LocalDateTime, LocalDateTimeMapper, MapperOutput, MessageLink,
+    MessageLinkMapper, NoopMapper, Snowflake, SnowflakeMapper, UrlMapper, UserMapper,
+};
+use crate::middleware::Middleware;
 use crate::type_map::TypeMap;
-use std::any::{Any, TypeId};
+use chrono::{DateTime, Utc};
+use serenity::model::id::{ChannelId, UserId};
+use serenity::utils::Colour;
+use std::any::TypeId;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
 
 pub struct CmdManager {
     mappers: TypeMap<Box<dyn FragmentMapper>>,
+    middlewares: Vec<Arc<dyn Middleware>>,
 }
 
 impl CmdManager {
     pub fn new() -> Self {
         Self {
             mappers: TypeMap::new(),
+            middlewares: Vec::new(),
         }
     }
 
+    /// Create a manager with a mapper for every built-in matcher.
+    pub fn with_default_mappers() -> Self {
+        Self::new()
+            .register_mapper::<()>(Box::new(NoopMapper))
+            .register_mapper::<u64>(Box::new(FromStrMapper::<u64>::default()))
+            .register_mapper::<i64>(Box::new(FromStrMapper::<i64>::default()))
+            .register_mapper::<f64>(Box::new(FromStrMapper::<f64>::default()))
+            .register_mapper::<bool>(Box::new(BoolMapper))
+            .register_mapper::<String>(Box::new(ExactMapper))
+            .register_mapper::<UserId>(Box::new(UserMapper))
+            .register_mapper::<ChannelId>(Box::new(ChannelMapper))
+            .register_mapper::<Duration>(Box::new(DurationMapper))
+            .register_mapper::<DateTime<Utc>>(Box::new(DateTimeMapper::default()))
+            .register_mapper::<LocalDateTime>(Box::new(LocalDateTimeMapper))
+            .register_mapper::<MessageLink>(Box::new(MessageLinkMapper))
+            .register_mapper::<Url>(Box::new(UrlMapper))
+            .register_mapper::<Colour>(Box::new(ColourMapper))
+            .register_mapper::<Snowflake>(Box::new(SnowflakeMapper))
+    }
+
     pub fn register_mapper<K: 'static>(mut self, mapper: Box<dyn FragmentMapper>) -> Self {
         self.mappers.insert::<K>(mapper);
         self
     }
 
+    /// Add a middleware at the end of the chain.
+    /// Permission checks usually come first, so denied calls don't use cooldowns.
+    pub fn with_middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Middlewares in the order they were added.
+    pub fn middlewares(&self) -> &[Arc<dyn Middleware>] {
+        &self.middlewares
+    }
+
     /// # Panics
     /// Panic if there is no mapper associated with this TypeId.
-    pub fn map(&self, ty: TypeId, fragment: &str) -> Box<dyn Any> {
+    pub fn map(&self, ty: TypeId, fragment: &str) -> MapperOutput {
         self.mappers.get_raw(ty).unwrap().map(fragment)
     }
 
+    /// Map a fragment matched by `node`, letting its matcher map it first if it can.
+    /// # Panics
+    /// Panic if the matcher doesn't map itself and there is no mapper for its TypeId.
+    pub fn map_node(&self, node: &CmdNode, fragment: &str) -> MapperOutput {
+        match node.matcher.map_self(fragment) {
+            Some(value) => value,
+            None => self.map(node.matcher.fragment_type_id(), fragment),
+        }
+    }
+
+    /// Like `map_node` but fails instead of panicking when there is no mapper.
+    pub fn try_map_node(&self, node: &CmdNode, fragment: &str) -> CmdResult<MapperOutput> {
+        if let Some(value) = node.matcher.map_self(fragment) {
+            return Ok(value);
+        }
+
+        self.mappers
+            .get_raw(node.matcher.fragment_type_id())
+            .map(|mapper| mapper.map(fragment))
+            .ok_or_else(|| CmdError::MissingMapper {
+                matcher: format!("{:?}", node.matcher),
+            })
+    }
+
     /// # Panics
     /// Panic if there is no mapper associated with this TypeId.
     /// Also panic if the value returned by the mapper cannot be downcasted to `T`.
@@ -37,8 +108,12 @@ impl CmdManager {
 #[cfg(test)]
 mod tests {
     use crate::cmd_manager::CmdManager;
+    use crate::cmd_tree::CmdNode;
+    use crate::error::CmdError;
     use crate::mappers::{FromStrMapper, UserMapper};
+    use crate::matchers::{DurationMatcher, RegexCaptures, RegexMatcher, SignedMatcher};
     use serenity::model::id::UserId;
+    use std::time::Duration;
 
     fn create_manager() -> CmdManager {
         CmdManager::new()
@@ -68,4 +143,48 @@ mod tests {
         assert_eq!(*manager.map_downcast::<UserId>("<@1234>"), UserId(1234));
         assert_eq!(*manager.map_downcast::<UserId>("<@!1234>"), UserId(1234));
     }
+
+    #[test]
+    pub fn test_manager_default_mappers() {
+        let manager = CmdManager::with_default_mappers();
+
+        assert_eq!(*manager.map_downcast::<u64>("42"), 42_u64);
+        assert_eq!(
+            *manager.map_downcast::<Duration>("1h30m"),
+            Duration::from_secs(5400)
+        );
+    }
+
+    #[test]
+    pub fn test_manager_map_node() {
+        let manager = CmdManager::with_default_mappers();
+
+        let node = CmdNode::new(SignedMatcher);
+        assert_eq!(
+            *manager.map_node(&node, "-42").downcast::<i64>().unwrap(),
+            -42
+        );
+
+        // Not registered, the matcher maps the fragment itself
+        let node = CmdNode::new(RegexMatcher::new("([a-z]+)").unwrap());
+        let captures = manager
+            .map_node(&node, "hey")
+            .downcast::<RegexCaptures>()
+            .unwrap();
+        assert_eq!(captures.get(1), Some("hey"));
+    }
+
+    #[test]
+    pub fn test_manager_try_map_node() {
+        let manager = create_manager();
+
+        let node = CmdNode::new(RegexMatcher::new("[a-z]+").unwrap());
+        assert!(manager.try_map_node(&node, "hey").is_ok());
+
+        let node = CmdNode::new(DurationMatcher);
+        match manager.try_map_node(&node, "1h") {
+            Err(CmdError::MissingMapper { matcher }) => assert_eq!(matcher, "DurationMatcher"),
+            _ => unreachable!(),
+        }
+    }
 }
@@ -1,13 +1,34 @@
-use crate::matchers::FragMatcher;
-use std::fmt::Debug;
+use crate::cmd_handler::CmdHandler;
+use crate::cooldown::Cooldown;
+use crate::matchers::{FragMatcher, RootMatcher};
+use crate::perms::PermRequirement;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
 
 pub type CmdTree = CmdNode;
 
-#[derive(Debug)]
 pub struct CmdNode {
     pub matcher: Box<dyn FragMatcher>,
     pub children: Vec<CmdNode>,
     pub name: Option<String>,
+    /// Executed when a path ends on this node.
+    pub handler: Option<Arc<dyn CmdHandler>>,
+    /// Applies to this node and its children, unless one of them declares its own.
+    pub requirement: Option<PermRequirement>,
+    /// Applies to this node and its children, unless one of them declares its own.
+    pub cooldown: Option<Cooldown>,
+    /// Other words accepted in place of a literal, usually translations.
+    pub aliases: Vec<String>,
+    /// Catalog key of the help text of this node and its children.
+    pub description: Option<String>,
+}
+
+/// How to type one of the commands of a tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Usage {
+    pub line: String,
+    /// The description of the deepest node of the command declaring one.
+    pub description: Option<String>,
 }
 
 impl CmdNode {
@@ -15,7 +36,12 @@ impl CmdNode {
         Self {
             matcher: Box::new(matcher),
             children: Vec::new(),
-            name: None
+            name: None,
+            handler: None,
+            requirement: None,
+            cooldown: None,
+            aliases: Vec::new(),
+            description: None,
         }
     }
 
@@ -24,6 +50,19 @@ impl CmdNode {
             matcher,
             children: Vec::new(),
             name: None,
+            handler: None,
+            requirement: None,
+            cooldown: None,
+            aliases: Vec::new(),
+            description: None,
+        }
+    }
+
+    /// A tree holding every command given, each starting with its own name.
+    pub fn root(commands: Vec<CmdNode>) -> CmdTree {
+        Self {
+            children: commands,
+            ..Self::new(RootMatcher)
         }
     }
 
@@ -32,6 +71,74 @@ impl CmdNode {
             matcher,
             children: Vec::new(),
             name: Some(name.into()),
+            handler: None,
+            requirement: None,
+            cooldown: None,
+            aliases: Vec::new(),
+            description: None,
         }
     }
 }
+
+impl CmdNode {
+    /// Whether `frag` is accepted by the matcher or is one of the aliases.
+    pub fn matches(&self, frag: &str) -> bool {
+        self.matcher.matches(frag) || self.aliases.iter().any(|alias| alias == frag)
+    }
+
+    /// Usage of every executable command from this node down, `parents` being the nodes
+    /// leading to it. Stops after `limit` commands.
+    pub fn usages(&self, parents: &[&CmdNode], limit: usize) -> Vec<Usage> {
+        let words = parents.iter().map(|node| node.usage_word()).collect();
+        let description = parents.iter().rev().find_map(|node| node.description.as_ref());
+
+        let mut usages = Vec::new();
+        self.collect_usages(words, description, limit, &mut usages);
+        usages
+    }
+
+    fn collect_usages(
+        &self,
+        mut words: Vec<String>,
+        description: Option<&String>,
+        limit: usize,
+        usages: &mut Vec<Usage>,
+    ) {
+        words.push(self.usage_word());
+        let description = self.description.as_ref().or(description);
+
+        if self.handler.is_some() {
+            let words = words.iter().filter(|word| !word.is_empty());
+            usages.push(Usage {
+                line: words.cloned().collect::<Vec<_>>().join(" "),
+                description: description.cloned(),
+            });
+        }
+
+        for child in &self.children {
+            if usages.len() >= limit {
+                return;
+            }
+            child.collect_usages(words.clone(), description, limit, usages);
+        }
+    }
+
+    fn usage_word(&self) -> String {
+        self.matcher.usage(self.name.as_deref().unwrap_or("arg"))
+    }
+}
+
+impl Debug for CmdNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CmdNode")
+            .field("matcher", &self.matcher)
+            .field("children", &self.children)
+            .field("name", &self.name)
+            .field("handler", &self.handler.is_some())
+            .field("requirement", &self.requirement)
+            .field("cooldown", &self.cooldown)
+            .field("aliases", &self.aliases)
+            .field("description", &self.description)
+            .finish()
+    }
+}
@@ -1,6 +1,10 @@
+use crate::cmd_handler::CmdHandler;
 use crate::cmd_tree::CmdNode;
+use crate::cooldown::Cooldown;
 use crate::error::{CmdError, CmdResult};
-use crate::matchers::{FragMatcher};
+use crate::matchers::FragMatcher;
+use crate::perms::PermRequirement;
+use std::sync::Arc;
 
 pub type CmdTreeBuilder = CmdTreeBuilderBranched;
 
@@ -59,6 +63,41 @@ impl CmdTreeBuilderBranched {
         self
     }
 
+    /// Add a whole command under the last node pushed, next to its other children.
+    pub fn command(&mut self, command: CmdNode) -> CmdResult<&mut Self> {
+        let node = self.stack.last_mut().ok_or(CmdError::EmptyCmdBuilder)?;
+        node.children.push(command);
+        Ok(self)
+    }
+
+    /// Make the last node pushed executable.
+    pub fn handler<H: CmdHandler + 'static>(&mut self, handler: H) -> CmdResult<&mut Self> {
+        let node = self.stack.last_mut().ok_or(CmdError::EmptyCmdBuilder)?;
+        node.handler = Some(Arc::new(handler));
+        Ok(self)
+    }
+
+    /// Restrict the last node pushed and everything after it.
+    pub fn require(&mut self, requirement: PermRequirement) -> CmdResult<&mut Self> {
+        let node = self.stack.last_mut().ok_or(CmdError::EmptyCmdBuilder)?;
+        node.requirement = Some(requirement);
+        Ok(self)
+    }
+
+    /// Rate limit the last node pushed and everything after it.
+    pub fn cooldown(&mut self, cooldown: Cooldown) -> CmdResult<&mut Self> {
+        let node = self.stack.last_mut().ok_or(CmdError::EmptyCmdBuilder)?;
+        node.cooldown = Some(cooldown);
+        Ok(self)
+    }
+
+    /// Describe the last node pushed and everything after it, `key` being a catalog key.
+    pub fn describe(&mut self, key: &str) -> CmdResult<&mut Self> {
+        let node = self.stack.last_mut().ok_or(CmdError::EmptyCmdBuilder)?;
+        node.description = Some(key.to_owned());
+        Ok(self)
+    }
+
     pub fn build(self) -> CmdResult<CmdNode> {
         let mut stack = self.stack;
 
@@ -106,10 +145,14 @@ impl CmdTreeBuilderParallel<'_> {
 
 #[cfg(test)]
 mod tests {
+    use crate::cmd_tree::CmdNode;
+    use crate::cmd_tree_builder::CmdTreeBuilder;
+    use crate::cmd_tree_builder_ext::CmdTreeBuilderExt;
+    use crate::cooldown::{Cooldown, CooldownScope};
     use crate::matchers::{ExactMatcher, FragMatcher, SignedMatcher};
+    use crate::perms::{PermLevel, PermRequirement};
     use std::any::TypeId;
-    use crate::cmd_tree_builder_ext::CmdTreeBuilderExt;
-    use crate::cmd_tree_builder::CmdTreeBuilder;
+    use std::time::Duration;
 
     fn downcast_pattern<T: FragMatcher>(node: &dyn FragMatcher) -> &T {
         node.as_any().downcast_ref().unwrap()
@@ -131,7 +174,7 @@ mod tests {
         );
         assert_eq!(root.children.len(), 1);
         assert_eq!(
-            root.children[0].matcher.type_id(),
+            root.children[0].matcher.as_ref().type_id(),
             TypeId::of::<SignedMatcher>()
         );
         assert_eq!(root.children[0].children.len(), 1);
@@ -176,4 +219,62 @@ mod tests {
         assert_eq!(branch_sub.children.len(), 1);
         assert!(branch_sub.children[0].children.is_empty());
     }
+
+    #[test]
+    pub fn test_builder_require() {
+        let root = CmdTreeBuilder::from_consumer(|builder| {
+            builder
+                .exact("prefix")
+                .require(PermRequirement::level(PermLevel::ADMIN))?
+                .exact("set");
+            Ok(())
+        })
+        .unwrap()
+        .build()
+        .unwrap();
+
+        assert_eq!(
+            root.requirement,
+            Some(PermRequirement::level(PermLevel::ADMIN))
+        );
+        assert_eq!(root.children[0].requirement, None);
+
+        let mut empty = CmdTreeBuilder::new();
+        assert!(empty
+            .require(PermRequirement::level(PermLevel::ADMIN))
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_builder_cooldown() {
+        let roll = Cooldown::new("roll", 1, Duration::from_secs(5), CooldownScope::User);
+        let root = CmdTreeBuilder::from_consumer(|builder| {
+            builder.exact("roll").cooldown(roll.clone())?.unsigned();
+            Ok(())
+        })
+        .unwrap()
+        .build()
+        .unwrap();
+
+        assert_eq!(root.cooldown, Some(roll));
+        assert_eq!(root.children[0].cooldown, None);
+    }
+
+    #[test]
+    pub fn test_builder_command() {
+        let command = |literal: &str| CmdNode::new(ExactMatcher::new(literal.into()));
+        let mut builder = CmdTreeBuilder::new();
+        assert!(builder.command(command("a")).is_err());
+
+        builder
+            .exact("root")
+            .command(command("a"))
+            .unwrap()
+            .command(command("b"))
+            .unwrap();
+        let root = builder.build().unwrap();
+
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.children[1].matcher.literal(), Some("b"));
+    }
 }
@@ -1,13 +1,39 @@
-use crate::matchers::{ExactMatcher, SignedMatcher, UnsignedMatcher, UserMentionMatcher};
-use crate::error::CmdResult;
+use crate::matchers::{
+    AttachmentMatcher, BoolMatcher, ChannelMentionMatcher, Choice, ChoiceMatcher, ColourMatcher,
+    DateTimeMatcher, DurationMatcher, ExactMatcher, FloatMatcher, LocalDateTimeMatcher,
+    MessageLinkMatcher, RangeMatcher, RegexMatcher, RepliedMessageMatcher, RestMatcher,
+    SignedMatcher, SnowflakeMatcher, UnsignedMatcher, UrlMatcher, UserMentionMatcher, WordMatcher,
+};
+use std::ops::RangeBounds;
+use crate::error::{CmdError, CmdResult};
 use crate::cmd_tree_builder::CmdTreeBuilderBranched;
 
 /// Helpers to easily add specific matchers.
 pub trait CmdTreeBuilderExt {
     fn exact(&mut self, literal: &str) -> &mut Self;
+    fn word(&mut self) -> &mut Self;
+    fn rest(&mut self) -> &mut Self;
     fn signed(&mut self) -> &mut Self;
     fn unsigned(&mut self) -> &mut Self;
+    fn signed_in<R: RangeBounds<i128>>(&mut self, range: R) -> &mut Self;
+    fn unsigned_in<R: RangeBounds<i128>>(&mut self, range: R) -> &mut Self;
+    fn float(&mut self) -> &mut Self;
+    fn boolean(&mut self) -> &mut Self;
+    fn choice(&mut self, choices: &[&str]) -> &mut Self;
+    fn choice_of<C: Choice>(&mut self) -> &mut Self;
+    fn regex(&mut self, pattern: &str) -> CmdResult<&mut Self>;
     fn user_mention(&mut self) -> &mut Self;
+    fn channel_mention(&mut self) -> &mut Self;
+    fn duration(&mut self) -> &mut Self;
+    fn date_time(&mut self) -> &mut Self;
+    fn local_date_time(&mut self) -> &mut Self;
+    fn message_link(&mut self) -> &mut Self;
+    fn url(&mut self) -> &mut Self;
+    fn url_with_schemes(&mut self, schemes: &[&str]) -> &mut Self;
+    fn colour(&mut self) -> &mut Self;
+    fn snowflake(&mut self) -> &mut Self;
+    fn attachment(&mut self) -> &mut Self;
+    fn replied_message(&mut self) -> &mut Self;
 }
 
 impl CmdTreeBuilderExt for CmdTreeBuilderBranched {
@@ -15,6 +41,14 @@ impl CmdTreeBuilderExt for CmdTreeBuilderBranched {
         self.raw_matcher(ExactMatcher::new(literal.into()))
     }
 
+    fn word(&mut self) -> &mut Self {
+        self.raw_matcher(WordMatcher)
+    }
+
+    fn rest(&mut self) -> &mut Self {
+        self.raw_matcher(RestMatcher)
+    }
+
     fn signed(&mut self) -> &mut Self {
         self.raw_matcher(SignedMatcher)
     }
@@ -23,9 +57,88 @@ impl CmdTreeBuilderExt for CmdTreeBuilderBranched {
         self.raw_matcher(UnsignedMatcher)
     }
 
+    fn signed_in<R: RangeBounds<i128>>(&mut self, range: R) -> &mut Self {
+        self.raw_matcher(RangeMatcher::new(SignedMatcher, range))
+    }
+
+    fn unsigned_in<R: RangeBounds<i128>>(&mut self, range: R) -> &mut Self {
+        self.raw_matcher(RangeMatcher::new(UnsignedMatcher, range))
+    }
+
+    fn float(&mut self) -> &mut Self {
+        self.raw_matcher(FloatMatcher)
+    }
+
+    fn boolean(&mut self) -> &mut Self {
+        self.raw_matcher(BoolMatcher)
+    }
+
+    fn choice(&mut self, choices: &[&str]) -> &mut Self {
+        self.raw_matcher(ChoiceMatcher::new(
+            choices.iter().map(|&choice| choice.to_owned()).collect(),
+        ))
+    }
+
+    fn choice_of<C: Choice>(&mut self) -> &mut Self {
+        self.raw_matcher(ChoiceMatcher::of::<C>())
+    }
+
+    fn regex(&mut self, pattern: &str) -> CmdResult<&mut Self> {
+        let matcher =
+            RegexMatcher::new(pattern).map_err(|err| CmdError::CreatorInvalidArgument {
+                ty: "Regex".into(),
+                argument: err.to_string(),
+            })?;
+        Ok(self.raw_matcher(matcher))
+    }
+
     fn user_mention(&mut self) -> &mut Self {
         self.raw_matcher(UserMentionMatcher::default())
     }
+
+    fn channel_mention(&mut self) -> &mut Self {
+        self.raw_matcher(ChannelMentionMatcher::default())
+    }
+
+    fn duration(&mut self) -> &mut Self {
+        self.raw_matcher(DurationMatcher)
+    }
+
+    fn date_time(&mut self) -> &mut Self {
+        self.raw_matcher(DateTimeMatcher::default())
+    }
+
+    fn local_date_time(&mut self) -> &mut Self {
+        self.raw_matcher(LocalDateTimeMatcher::default())
+    }
+
+    fn message_link(&mut self) -> &mut Self {
+        self.raw_matcher(MessageLinkMatcher)
+    }
+
+    fn url(&mut self) -> &mut Self {
+        self.raw_matcher(UrlMatcher::default())
+    }
+
+    fn url_with_schemes(&mut self, schemes: &[&str]) -> &mut Self {
+        self.raw_matcher(UrlMatcher::with_schemes(schemes))
+    }
+
+    fn colour(&mut self) -> &mut Self {
+        self.raw_matcher(ColourMatcher)
+    }
+
+    fn snowflake(&mut self) -> &mut Self {
+        self.raw_matcher(SnowflakeMatcher)
+    }
+
+    fn attachment(&mut self) -> &mut Self {
+        self.raw_matcher(AttachmentMatcher)
+    }
+
+    fn replied_message(&mut self) -> &mut Self {
+        self.raw_matcher(RepliedMessageMatcher)
+    }
 }
 
 fn parse_command_build() {
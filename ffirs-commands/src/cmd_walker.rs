@@ -1,57 +1,151 @@
 use crate::cmd_manager::CmdManager;
 use crate::cmd_tree::{CmdNode, CmdTree};
 use crate::error::{CmdError, CmdResult};
-use crate::fragment_iter::FragmentIter;
+use crate::fragment_iter::Fragments;
+use std::borrow::Cow;
 
 pub type CmdPath<'a> = Vec<&'a CmdNode>;
 
 impl CmdManager {
     pub fn try_find_cmd_path(cmd_root: &CmdTree, raw: String) -> CmdResult<CmdPath> {
-        let frags = FragmentIter::new(raw).collect::<Vec<_>>();
+        let frags = Self::split_fragments(raw)?;
+        Self::find_cmd_path(cmd_root, &frags)
+    }
 
-        let err = frags.iter().enumerate().find(|(_, res)| res.is_err());
-        if let Some((i, _)) = err {
-            let err = frags.into_iter().nth(i).unwrap();
-            return Err(err.unwrap_err());
-        }
+    /// Split a raw command into fragments, failing on the first parsing error.
+    pub fn split_fragments(raw: String) -> CmdResult<Fragments> {
+        Fragments::parse(raw)
+    }
 
-        let frags = frags
-            .into_iter()
-            .map(|res| res.unwrap())
-            .collect::<Vec<_>>();
+    pub fn find_cmd_path<'a>(cmd_root: &'a CmdTree, frags: &Fragments) -> CmdResult<CmdPath<'a>> {
+        let path = Self::walk_command_tree(cmd_root, frags).ok_or(CmdError::NoPathFound)?;
+
+        for (node, frag) in Self::pair_fragments(&path, frags) {
+            if let Some(frag) = frag {
+                node.matcher.check(&frag)?;
+            }
+        }
 
-        Self::walk_command_tree(cmd_root, &frags).ok_or(CmdError::NoPathFound)
+        Ok(path)
     }
 
-    pub(self) fn walk_command_tree<'a>(node: &'a CmdNode, frags: &[String]) -> Option<CmdPath<'a>> {
-        if let Some(frag) = frags.first() {
-            if node.matcher.matches(frag) {
-                if node.children.is_empty() {
-                    // We're at the end of the chain
-                    Some(vec![node])
+    /// Pair every node of a path, from the root down, with the fragment it consumed.
+    /// Pseudo-arguments and roots don't consume any fragment and get `None`,
+    /// a matcher taking the rest gets the raw command left, as it was typed.
+    pub fn pair_fragments<'a, 'f>(
+        path: &[&'a CmdNode],
+        frags: &'f Fragments,
+    ) -> Vec<(&'a CmdNode, Option<Cow<'f, str>>)> {
+        let mut index = 0;
+
+        // The path goes from the leaf up while the fragments go from the root down
+        path.iter()
+            .rev()
+            .map(|&node| {
+                if !node.matcher.consumes_fragment() {
+                    (node, None)
+                } else if node.matcher.takes_rest() {
+                    let rest = frags.rest(index);
+                    index = frags.len();
+                    (node, Some(Cow::Borrowed(rest)))
                 } else {
-                    // The node is good and has children, recurse and get the first matching path
-                    let child_res = node
-                        .children
-                        .iter()
-                        .find_map(|child| Self::walk_command_tree(child, &frags[1..]));
-
-                    if let Some(mut chain) = child_res {
-                        // There is a match down the recursion, append to the chain and return up
-                        chain.push(node);
-                        Some(chain)
-                    } else {
-                        // No match found in the children
-                        None
-                    }
+                    let frag = frags.get(index).map(|frag| Cow::Borrowed(frag.as_str()));
+                    index += 1;
+                    (node, frag)
                 }
+            })
+            .collect()
+    }
+
+    /// The literals, or their aliases, that can follow `frags` and start with `partial`.
+    /// Arguments are not completed, the literals after them are.
+    pub fn complete(cmd_root: &CmdTree, frags: &[String], partial: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        Self::collect_completions(cmd_root, frags, partial, &mut words);
+
+        words.sort_unstable();
+        words.dedup();
+        words
+    }
+
+    fn collect_completions(
+        node: &CmdNode,
+        frags: &[String],
+        partial: &str,
+        words: &mut Vec<String>,
+    ) {
+        let remaining = if !node.matcher.consumes_fragment() {
+            frags
+        } else if let Some(frag) = frags.first() {
+            if !node.matches(frag) {
+                return;
+            }
+            &frags[1..]
+        } else {
+            // This node is the one being typed
+            let literals = node.matcher.literal().into_iter();
+            let literals = literals.chain(node.aliases.iter().map(String::as_str));
+            words.extend(
+                literals
+                    .filter(|word| word.starts_with(partial))
+                    .map(str::to_owned),
+            );
+            return;
+        };
+
+        for child in &node.children {
+            Self::collect_completions(child, remaining, partial, words);
+        }
+    }
+
+    pub(self) fn walk_command_tree<'a>(
+        node: &'a CmdNode,
+        frags: &Fragments,
+    ) -> Option<CmdPath<'a>> {
+        Self::walk_from(node, frags, 0)
+    }
+
+    /// Walk down from `node`, which gets the fragment at `index`.
+    fn walk_from<'a>(node: &'a CmdNode, frags: &Fragments, index: usize) -> Option<CmdPath<'a>> {
+        let next = if !node.matcher.consumes_fragment() {
+            // Pseudo-arguments are taken from the message and roots only group commands
+            index
+        } else if node.matcher.takes_rest() && index < frags.len() {
+            if node.matches(frags.rest(index)) {
+                frags.len()
+            } else {
+                return None;
+            }
+        } else if let Some(frag) = frags.get(index) {
+            if node.matches(frag) {
+                index + 1
             } else {
                 // The node didn't match the fragment
-                None
+                return None;
             }
         } else {
             // No fragment left, can't match this node
-            None
+            return None;
+        };
+
+        if node.children.is_empty() {
+            // We're at the end of the chain
+            Some(vec![node])
+        } else {
+            // The node is good and has children, recurse and get the first matching path
+            let child_res = node
+                .children
+                .iter()
+                .find_map(|child| Self::walk_from(child, frags, next));
+
+            if let Some(mut chain) = child_res {
+                // There is a match down the recursion, append to the chain and return up
+                chain.push(node);
+                Some(chain)
+            } else {
+                // No match found in the children
+                None
+            }
         }
     }
 }
@@ -60,7 +154,12 @@ impl CmdManager {
 mod tests {
     use crate::cmd_manager::CmdManager;
     use crate::cmd_tree::CmdNode;
-    use crate::matchers::{ExactMatcher, SignedMatcher, UnsignedMatcher};
+    use crate::error::CmdError;
+    use crate::fragment_iter::Fragments;
+    use crate::matchers::{
+        AttachmentMatcher, DurationMatcher, ExactMatcher, RangeMatcher, RepliedMessageMatcher,
+        RestMatcher, SignedMatcher, UnsignedMatcher,
+    };
     use std::any::TypeId;
 
     fn make_tree() -> CmdNode {
@@ -75,7 +174,17 @@ mod tests {
                         matcher: Box::new(SignedMatcher),
                         name: None,
                         children: vec![CmdNode::new(UnsignedMatcher)],
+                        handler: None,
+                        requirement: None,
+                        cooldown: None,
+                        aliases: Vec::new(),
+                        description: None,
                     }],
+                    handler: None,
+                    requirement: None,
+                    cooldown: None,
+                    aliases: Vec::new(),
+                    description: None,
                 },
                 CmdNode {
                     matcher: Box::new(ExactMatcher::new(String::from("add"))),
@@ -84,10 +193,25 @@ mod tests {
                         matcher: Box::new(ExactMatcher::new(String::from("infty"))),
                         name: None,
                         children: vec![CmdNode::new(SignedMatcher)],
+                        handler: None,
+                        requirement: None,
+                        cooldown: None,
+                        aliases: Vec::new(),
+                        description: None,
                     }],
+                    handler: None,
+                    requirement: None,
+                    cooldown: None,
+                    aliases: Vec::new(),
+                    description: None,
                 },
                 CmdNode::new(ExactMatcher::new(String::from("sub"))),
             ],
+            handler: None,
+            requirement: None,
+            cooldown: None,
+            aliases: Vec::new(),
+            description: None,
         }
     }
 
@@ -95,7 +219,7 @@ mod tests {
     pub fn test_tree_simple() {
         let cmd_root = make_tree();
 
-        let frags = vec!["root".to_owned(), "sub".to_owned()];
+        let frags = Fragments::from(vec!["root".to_owned(), "sub".to_owned()]);
         let path = CmdManager::walk_command_tree(&cmd_root, &frags).unwrap();
 
         assert_eq!(path.len(), 2);
@@ -124,7 +248,11 @@ mod tests {
     pub fn test_simple_frag_leftover() {
         let cmd_root = make_tree();
 
-        let frags = vec!["root".to_owned(), "sub".to_owned(), "garbage".to_owned()];
+        let frags = Fragments::from(vec![
+            "root".to_owned(),
+            "sub".to_owned(),
+            "garbage".to_owned(),
+        ]);
         let path = CmdManager::walk_command_tree(&cmd_root, &frags);
 
         assert!(path.is_some());
@@ -135,12 +263,12 @@ mod tests {
     pub fn test_tree_complex() {
         let cmd_root = make_tree();
 
-        let frags = vec![
+        let frags = Fragments::from(vec![
             "root".to_owned(),
             "add".to_owned(),
             "-12".to_owned(),
             "42".to_owned(),
-        ];
+        ]);
         let path = CmdManager::walk_command_tree(&cmd_root, &frags).unwrap();
 
         assert_eq!(path.len(), 4);
@@ -187,12 +315,12 @@ mod tests {
     pub fn test_tree_complex2() {
         let cmd_root = make_tree();
 
-        let frags = vec![
+        let frags = Fragments::from(vec![
             "root".to_owned(),
             "add".to_owned(),
             "infty".to_owned(),
             "42".to_owned(),
-        ];
+        ]);
         let path = CmdManager::walk_command_tree(&cmd_root, &frags).unwrap();
 
         assert_eq!(path.len(), 4);
@@ -239,18 +367,151 @@ mod tests {
     pub fn test_tree_invalid() {
         let cmd_root = make_tree();
 
-        let frags = vec!["garbage".to_owned()];
+        let frags = Fragments::from(vec!["garbage".to_owned()]);
         assert!(CmdManager::walk_command_tree(&cmd_root, &frags).is_none());
 
-        let frags = vec!["root".to_owned(), "add".to_owned()];
+        let frags = Fragments::from(vec!["root".to_owned(), "add".to_owned()]);
         assert!(CmdManager::walk_command_tree(&cmd_root, &frags).is_none());
 
-        let frags = vec![
+        let frags = Fragments::from(vec![
             "root".to_owned(),
             "add".to_owned(),
             "-12".to_owned(),
             "-12".to_owned(),
-        ];
+        ]);
         assert!(CmdManager::walk_command_tree(&cmd_root, &frags).is_none());
     }
+
+    #[test]
+    pub fn test_tree_out_of_range() {
+        let mut cmd_root = CmdNode::new(ExactMatcher::new(String::from("roll")));
+        cmd_root
+            .children
+            .push(CmdNode::new(RangeMatcher::new(UnsignedMatcher, 1..=100)));
+
+        let path = CmdManager::try_find_cmd_path(&cmd_root, String::from("roll 20")).unwrap();
+        assert_eq!(path.len(), 2);
+
+        match CmdManager::try_find_cmd_path(&cmd_root, String::from("roll 500")) {
+            Err(CmdError::OutOfRange { value, range }) => {
+                assert_eq!(value, "500");
+                assert_eq!(range, "1..=100");
+            }
+            _ => unreachable!(),
+        }
+
+        match CmdManager::try_find_cmd_path(&cmd_root, String::from("roll -1")) {
+            Err(CmdError::NoPathFound) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    pub fn test_tree_pseudo_arguments() {
+        // upload <file: Attachment> <n: Unsigned> <target: RepliedMessage>
+        let mut cmd_root = CmdNode::new(ExactMatcher::new(String::from("upload")));
+        let mut target = CmdNode::new(RepliedMessageMatcher);
+        target.name = Some("target".into());
+        let mut n = CmdNode::new(UnsignedMatcher);
+        n.children.push(target);
+        let mut file = CmdNode::new(AttachmentMatcher);
+        file.children.push(n);
+        cmd_root.children.push(file);
+
+        let frags = Fragments::from(vec!["upload".to_owned(), "42".to_owned()]);
+        let path = CmdManager::find_cmd_path(&cmd_root, &frags).unwrap();
+        assert_eq!(path.len(), 4);
+
+        let paired = CmdManager::pair_fragments(&path, &frags);
+        assert_eq!(paired.len(), 4);
+        assert_eq!(paired[0].1.as_deref(), Some("upload"));
+        assert_eq!(paired[1].1.as_deref(), None);
+        assert_eq!(paired[2].1.as_deref(), Some("42"));
+        assert_eq!(paired[3].1.as_deref(), None);
+        assert_eq!(paired[3].0.name.as_deref(), Some("target"));
+
+        // Pseudo-arguments can't stand in for a missing fragment
+        let frags = Fragments::from(vec!["upload".to_owned()]);
+        assert!(CmdManager::find_cmd_path(&cmd_root, &frags).is_err());
+    }
+
+    #[test]
+    pub fn test_tree_rest() {
+        // remind <in: Duration> <text: Rest>
+        let mut cmd_root = CmdNode::new(ExactMatcher::new(String::from("remind")));
+        let mut duration = CmdNode::new(DurationMatcher);
+        duration.children.push(CmdNode::new(RestMatcher));
+        cmd_root.children.push(duration);
+
+        let frags =
+            CmdManager::split_fragments(String::from("remind 1h buy  'some milk'")).unwrap();
+        let path = CmdManager::find_cmd_path(&cmd_root, &frags).unwrap();
+        assert_eq!(path.len(), 3);
+
+        let paired = CmdManager::pair_fragments(&path, &frags);
+        assert_eq!(paired[1].1.as_deref(), Some("1h"));
+        assert_eq!(paired[2].1.as_deref(), Some("buy  'some milk'"));
+
+        // Lines are kept as they were typed
+        let frags = CmdManager::split_fragments(String::from("remind 1h list:\n- milk\n")).unwrap();
+        let path = CmdManager::find_cmd_path(&cmd_root, &frags).unwrap();
+        let paired = CmdManager::pair_fragments(&path, &frags);
+        assert_eq!(paired[2].1.as_deref(), Some("list:\n- milk"));
+
+        // The rest can't be empty
+        assert!(CmdManager::try_find_cmd_path(&cmd_root, String::from("remind 1h")).is_err());
+    }
+
+    #[test]
+    pub fn test_tree_root() {
+        let mut roll = CmdNode::new(ExactMatcher::new(String::from("roll")));
+        roll.children.push(CmdNode::new(UnsignedMatcher));
+        let stop = CmdNode::new(ExactMatcher::new(String::from("stop")));
+        let cmd_root = CmdNode::root(vec![roll, stop]);
+
+        let frags = Fragments::from(vec!["roll".to_owned(), "6".to_owned()]);
+        let path = CmdManager::find_cmd_path(&cmd_root, &frags).unwrap();
+        assert_eq!(path.len(), 3);
+
+        let paired = CmdManager::pair_fragments(&path, &frags);
+        assert_eq!(paired[0].1.as_deref(), None);
+        assert_eq!(paired[1].1.as_deref(), Some("roll"));
+        assert_eq!(paired[2].1.as_deref(), Some("6"));
+
+        let path = CmdManager::try_find_cmd_path(&cmd_root, String::from("stop")).unwrap();
+        assert_eq!(path.len(), 2);
+
+        assert!(CmdManager::try_find_cmd_path(&cmd_root, String::from("nope")).is_err());
+        assert!(CmdManager::try_find_cmd_path(&cmd_root, String::new()).is_err());
+    }
+
+    #[test]
+    pub fn test_complete() {
+        let cmd_root = CmdNode::root(vec![make_tree()]);
+        let frags = |frags: &[&str]| {
+            frags
+                .iter()
+                .map(|&frag| frag.to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(CmdManager::complete(&cmd_root, &[], "r"), vec!["root"]);
+        assert_eq!(
+            CmdManager::complete(&cmd_root, &frags(&["root"]), ""),
+            vec!["add", "sub"]
+        );
+        assert_eq!(
+            CmdManager::complete(&cmd_root, &frags(&["root", "add"]), "in"),
+            vec!["infty"]
+        );
+        assert!(CmdManager::complete(&cmd_root, &frags(&["root", "add", "1"]), "").is_empty());
+        assert!(CmdManager::complete(&cmd_root, &frags(&["nope"]), "").is_empty());
+
+        let mut cmd_root = cmd_root;
+        cmd_root.children[0].children[2].aliases = vec![String::from("soustraire")];
+        assert_eq!(
+            CmdManager::complete(&cmd_root, &frags(&["root"]), "s"),
+            vec!["soustraire", "sub"]
+        );
+    }
 }
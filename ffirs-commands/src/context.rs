@@ -0,0 +1,159 @@
+use crate::error::CmdResult;
+use crate::type_map::AnyTypeMap;
+use serenity::async_trait;
+use serenity::builder::CreateEmbed;
+use serenity::client::Context;
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::prelude::RwLock;
+use serenity::utils::Colour;
+use std::sync::Arc;
+
+/// A user, as much as commands need to know of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CmdUser {
+    pub id: UserId,
+    pub name: String,
+    pub bot: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    pub inline: bool,
+}
+
+/// Rich content, each transport showing it its own way.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Embed {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub colour: Option<u32>,
+    pub fields: Vec<EmbedField>,
+}
+
+impl Embed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title<S: Into<String>>(mut self, title: S) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn colour(mut self, colour: u32) -> Self {
+        self.colour = Some(colour);
+        self
+    }
+
+    pub fn field<N: Into<String>, V: Into<String>>(
+        mut self,
+        name: N,
+        value: V,
+        inline: bool,
+    ) -> Self {
+        self.fields.push(EmbedField {
+            name: name.into(),
+            value: value.into(),
+            inline,
+        });
+        self
+    }
+
+    /// Plain text, for transports without embeds.
+    pub fn to_text(&self) -> String {
+        let mut lines = Vec::new();
+
+        if let Some(title) = &self.title {
+            lines.push(format!("== {} ==", title));
+        }
+        if let Some(description) = &self.description {
+            lines.push(description.clone());
+        }
+        for field in &self.fields {
+            lines.push(format!("{}:", field.name));
+            lines.push(field.value.clone());
+        }
+
+        lines.join("\n")
+    }
+
+    /// Fill a serenity embed, as with `m.embed(|e| embed.fill(e))`.
+    pub fn fill<'a>(&self, embed: &'a mut CreateEmbed) -> &'a mut CreateEmbed {
+        if let Some(title) = &self.title {
+            embed.title(title);
+        }
+        if let Some(description) = &self.description {
+            embed.description(description);
+        }
+        if let Some(colour) = self.colour {
+            embed.colour(Colour::new(colour));
+        }
+        for field in &self.fields {
+            embed.field(&field.name, &field.value, field.inline);
+        }
+
+        embed
+    }
+}
+
+/// Where a command comes from and where its answers go,
+/// so the same commands run on Discord, in a terminal or in tests.
+#[async_trait]
+pub trait CmdContext: Send + Sync {
+    fn author(&self) -> &CmdUser;
+
+    /// `None` in direct messages.
+    fn guild(&self) -> Option<GuildId>;
+
+    fn channel(&self) -> ChannelId;
+
+    /// The message the command was sent in.
+    fn message_id(&self) -> MessageId;
+
+    fn content(&self) -> &str;
+
+    /// Values shared with the command handlers, one per type.
+    fn data(&self) -> &Arc<RwLock<AnyTypeMap>>;
+
+    /// Answer in the channel of the command.
+    async fn reply(&self, content: &str) -> CmdResult<()>;
+
+    async fn send_embed(&self, embed: &Embed) -> CmdResult<()>;
+
+    /// React to the message of the command with `emoji`, unicode or `name:id`.
+    async fn react(&self, emoji: &str) -> CmdResult<()>;
+
+    async fn fetch_user(&self, user: UserId) -> CmdResult<CmdUser>;
+
+    /// Serenity's context and message, only when running on Discord.
+    fn discord(&self) -> Option<(&Context, &Message)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::Embed;
+
+    #[test]
+    pub fn test_embed_to_text() {
+        let embed = Embed::new()
+            .title("Commands")
+            .description("`help` - List the commands")
+            .field("Hint", "Try `help`", false);
+
+        assert_eq!(
+            embed.to_text(),
+            "== Commands ==\n`help` - List the commands\nHint:\nTry `help`"
+        );
+        assert_eq!(Embed::new().description("text").to_text(), "text");
+    }
+}
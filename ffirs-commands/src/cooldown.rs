@@ -0,0 +1,474 @@
+use crate::clock::{Clock, SystemClock};
+use crate::cmd_tree::CmdNode;
+use crate::error::{CmdError, CmdResult};
+use crate::middleware::{Invocation, Middleware};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serenity::async_trait;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// What uses are counted together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CooldownScope {
+    User,
+    Channel,
+    /// Counted per channel in direct messages.
+    Guild,
+    Global,
+}
+
+/// Allows `uses` executions every `per`, counted separately for each user/channel/guild
+/// depending on the scope. Commands declaring the same bucket share their count.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cooldown {
+    pub bucket: String,
+    pub uses: u32,
+    pub per: Duration,
+    pub scope: CooldownScope,
+}
+
+impl Cooldown {
+    pub fn new<B: Into<String>>(bucket: B, uses: u32, per: Duration, scope: CooldownScope) -> Self {
+        Self {
+            bucket: bucket.into(),
+            uses,
+            per,
+            scope,
+        }
+    }
+
+    /// The cooldown of the deepest node of the path declaring one.
+    pub fn of_path<'a>(path: &[&'a CmdNode]) -> Option<&'a Cooldown> {
+        // Paths go from the leaf up
+        path.iter().find_map(|node| node.cooldown.as_ref())
+    }
+}
+
+/// A single bucket, for a single user/channel/guild.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CooldownKey {
+    pub bucket: String,
+    pub scope: CooldownScope,
+    pub target: u64,
+}
+
+/// Keeps track of the uses of every bucket.
+pub trait CooldownStore: Send + Sync {
+    /// Record a use at `now` if the bucket allows it, otherwise return how long to wait.
+    fn try_acquire(
+        &self,
+        key: &CooldownKey,
+        uses: u32,
+        per: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<(), Duration>;
+
+    /// Forget every bucket that doesn't limit anything anymore.
+    fn evict(&self, now: DateTime<Utc>);
+}
+
+#[derive(Debug)]
+struct Bucket {
+    per: ChronoDuration,
+    /// Time of the uses still in the window, oldest first.
+    uses: VecDeque<DateTime<Utc>>,
+}
+
+/// A `CooldownStore` that lives in memory, cooldowns are lost on restart.
+#[derive(Debug, Default)]
+pub struct MemoryCooldownStore {
+    buckets: Mutex<HashMap<CooldownKey, Bucket>>,
+}
+
+impl MemoryCooldownStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Keys of the buckets kept, for the stores saving them.
+    pub fn keys(&self) -> Vec<CooldownKey> {
+        self.buckets.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Time of the uses of a bucket, oldest first.
+    pub fn uses(&self, key: &CooldownKey) -> Vec<DateTime<Utc>> {
+        self.buckets
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|bucket| bucket.uses.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Put back a bucket, like one saved before a restart.
+    pub fn restore(&self, key: CooldownKey, per: Duration, uses: Vec<DateTime<Utc>>) {
+        let per = ChronoDuration::from_std(per).unwrap_or_else(|_| ChronoDuration::max_value());
+        let bucket = Bucket {
+            per,
+            uses: uses.into(),
+        };
+        self.buckets.lock().unwrap().insert(key, bucket);
+    }
+}
+
+impl CooldownStore for MemoryCooldownStore {
+    fn try_acquire(
+        &self,
+        key: &CooldownKey,
+        uses: u32,
+        per: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<(), Duration> {
+        let window = ChronoDuration::from_std(per).unwrap_or_else(|_| ChronoDuration::max_value());
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.clone()).or_insert_with(|| Bucket {
+            per: window,
+            uses: VecDeque::new(),
+        });
+        bucket.per = window;
+
+        // Forget the uses that left the window
+        while bucket.uses.front().map_or(false, |&used| {
+            expiry(used, window).map_or(false, |end| end <= now)
+        }) {
+            bucket.uses.pop_front();
+        }
+
+        if bucket.uses.len() < uses as usize {
+            bucket.uses.push_back(now);
+            Ok(())
+        } else {
+            let oldest = bucket.uses.front().copied().unwrap_or(now);
+            match expiry(oldest, window) {
+                Some(free_at) => Err((free_at - now).to_std().unwrap_or_default()),
+                None => Err(per),
+            }
+        }
+    }
+
+    fn evict(&self, now: DateTime<Utc>) {
+        self.buckets.lock().unwrap().retain(|_, bucket| {
+            bucket.uses.back().map_or(false, |&last| {
+                expiry(last, bucket.per).map_or(true, |end| end > now)
+            })
+        });
+    }
+}
+
+/// When a use at `used` leaves a window of `per`, `None` when it never does.
+fn expiry(used: DateTime<Utc>, per: ChronoDuration) -> Option<DateTime<Utc>> {
+    used.checked_add_signed(per)
+}
+
+/// Enforces cooldowns as a middleware, evicting stale buckets every once in a while.
+pub struct Cooldowns {
+    store: Box<dyn CooldownStore>,
+    clock: Arc<dyn Clock>,
+    eviction_interval: ChronoDuration,
+    last_eviction: Mutex<DateTime<Utc>>,
+}
+
+impl Cooldowns {
+    pub fn new(store: Box<dyn CooldownStore>, clock: Arc<dyn Clock>) -> Self {
+        let last_eviction = Mutex::new(clock.now());
+
+        Self {
+            store,
+            clock,
+            eviction_interval: ChronoDuration::minutes(10),
+            last_eviction,
+        }
+    }
+
+    pub fn with_eviction_interval(mut self, interval: Duration) -> Self {
+        self.eviction_interval =
+            ChronoDuration::from_std(interval).unwrap_or_else(|_| ChronoDuration::max_value());
+        self
+    }
+
+    /// Count a use of `cooldown`, fails with the remaining wait if there is none left.
+    pub fn check(
+        &self,
+        cooldown: &Cooldown,
+        user: UserId,
+        channel: ChannelId,
+        guild: Option<GuildId>,
+    ) -> CmdResult<()> {
+        let now = self.clock.now();
+        self.evict_if_due(now);
+
+        let target = match cooldown.scope {
+            CooldownScope::User => user.0,
+            CooldownScope::Channel => channel.0,
+            CooldownScope::Guild => guild.map_or(channel.0, |guild| guild.0),
+            CooldownScope::Global => 0,
+        };
+        let key = CooldownKey {
+            bucket: cooldown.bucket.clone(),
+            scope: cooldown.scope,
+            target,
+        };
+
+        self.store
+            .try_acquire(&key, cooldown.uses, cooldown.per, now)
+            .map_err(|remaining| CmdError::OnCooldown { remaining })
+    }
+
+    fn evict_if_due(&self, now: DateTime<Utc>) {
+        let mut last_eviction = self.last_eviction.lock().unwrap();
+
+        if now - *last_eviction >= self.eviction_interval {
+            self.store.evict(now);
+            *last_eviction = now;
+        }
+    }
+}
+
+impl Default for Cooldowns {
+    fn default() -> Self {
+        Self::new(Box::new(MemoryCooldownStore::new()), Arc::new(SystemClock))
+    }
+}
+
+/// Counts a use of the invoked command, if it has a cooldown.
+#[async_trait]
+impl Middleware for Cooldowns {
+    async fn before(&self, invocation: &Invocation<'_>) -> CmdResult<()> {
+        match Cooldown::of_path(&invocation.path) {
+            Some(cooldown) => {
+                let ctx = invocation.ctx;
+                self.check(cooldown, ctx.author().id, ctx.channel(), ctx.guild())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::MockClock;
+    use crate::cmd_tree::CmdNode;
+    use crate::cooldown::{
+        Cooldown, CooldownKey, CooldownScope, CooldownStore, Cooldowns, MemoryCooldownStore,
+    };
+    use crate::error::CmdError;
+    use crate::matchers::ExactMatcher;
+    use chrono::{DateTime, Duration as ChronoDuration, TimeZone, Utc};
+    use serenity::model::id::{ChannelId, GuildId, UserId};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn start() -> DateTime<Utc> {
+        Utc.ymd(2020, 6, 1).and_hms(12, 0, 0)
+    }
+
+    fn make_cooldowns() -> (Cooldowns, Arc<MockClock>) {
+        let clock = Arc::new(MockClock::new(start()));
+        let cooldowns = Cooldowns::new(Box::new(MemoryCooldownStore::new()), clock.clone());
+        (cooldowns, clock)
+    }
+
+    fn remaining(res: Result<(), CmdError>) -> Duration {
+        match res {
+            Err(CmdError::OnCooldown { remaining }) => remaining,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    pub fn test_cooldown_per_user() {
+        let (cooldowns, clock) = make_cooldowns();
+        let roll = Cooldown::new("roll", 1, Duration::from_secs(5), CooldownScope::User);
+        let check = |user| cooldowns.check(&roll, UserId(user), ChannelId(1), Some(GuildId(1)));
+
+        assert!(check(1).is_ok());
+        assert_eq!(remaining(check(1)), Duration::from_secs(5));
+        // Other users have their own bucket
+        assert!(check(2).is_ok());
+
+        clock.advance(ChronoDuration::seconds(3));
+        assert_eq!(remaining(check(1)), Duration::from_secs(2));
+
+        clock.advance(ChronoDuration::seconds(2));
+        assert!(check(1).is_ok());
+    }
+
+    #[test]
+    pub fn test_cooldown_per_guild() {
+        let (cooldowns, clock) = make_cooldowns();
+        let poll = Cooldown::new("poll", 3, Duration::from_secs(3600), CooldownScope::Guild);
+        let check =
+            |user, guild| cooldowns.check(&poll, UserId(user), ChannelId(1), Some(GuildId(guild)));
+
+        assert!(check(1, 1).is_ok());
+        clock.advance(ChronoDuration::minutes(10));
+        assert!(check(2, 1).is_ok());
+        clock.advance(ChronoDuration::minutes(10));
+        assert!(check(3, 1).is_ok());
+
+        // Shared by the whole guild
+        assert_eq!(remaining(check(4, 1)), Duration::from_secs(40 * 60));
+        assert!(check(4, 2).is_ok());
+
+        // The first use leaves the window, one slot is free again
+        clock.advance(ChronoDuration::minutes(40));
+        assert!(check(1, 1).is_ok());
+        assert_eq!(remaining(check(1, 1)), Duration::from_secs(10 * 60));
+    }
+
+    #[test]
+    pub fn test_cooldown_guild_in_dm() {
+        let (cooldowns, _) = make_cooldowns();
+        let poll = Cooldown::new("poll", 1, Duration::from_secs(60), CooldownScope::Guild);
+
+        assert!(cooldowns
+            .check(&poll, UserId(1), ChannelId(1), None)
+            .is_ok());
+        assert!(cooldowns
+            .check(&poll, UserId(1), ChannelId(1), None)
+            .is_err());
+        assert!(cooldowns
+            .check(&poll, UserId(1), ChannelId(2), None)
+            .is_ok());
+    }
+
+    #[test]
+    pub fn test_cooldown_shared_bucket() {
+        let (cooldowns, _) = make_cooldowns();
+        let a = Cooldown::new("dice", 1, Duration::from_secs(60), CooldownScope::Global);
+        let b = Cooldown::new("dice", 1, Duration::from_secs(60), CooldownScope::Global);
+
+        assert!(cooldowns.check(&a, UserId(1), ChannelId(1), None).is_ok());
+        assert!(cooldowns.check(&b, UserId(2), ChannelId(2), None).is_err());
+    }
+
+    #[test]
+    pub fn test_store_eviction() {
+        let store = MemoryCooldownStore::new();
+        let per = Duration::from_secs(60);
+        let key = |target| CooldownKey {
+            bucket: "roll".into(),
+            scope: CooldownScope::User,
+            target,
+        };
+
+        assert!(store.try_acquire(&key(1), 1, per, start()).is_ok());
+        let later = start() + ChronoDuration::seconds(30);
+        assert!(store.try_acquire(&key(2), 1, per, later).is_ok());
+        assert_eq!(store.len(), 2);
+
+        store.evict(start() + ChronoDuration::seconds(60));
+        assert_eq!(store.len(), 1);
+        store.evict(start() + ChronoDuration::seconds(90));
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    pub fn test_store_restore() {
+        let store = MemoryCooldownStore::new();
+        let per = Duration::from_secs(60);
+        let key = CooldownKey {
+            bucket: "roll".into(),
+            scope: CooldownScope::User,
+            target: 1,
+        };
+
+        assert!(store.try_acquire(&key, 2, per, start()).is_ok());
+        assert_eq!(store.uses(&key), vec![start()]);
+        assert_eq!(store.keys(), vec![key.clone()]);
+
+        let restored = MemoryCooldownStore::new();
+        let later = start() + ChronoDuration::seconds(10);
+        restored.restore(key.clone(), per, vec![start(), later]);
+        assert_eq!(
+            restored.try_acquire(&key, 2, per, later),
+            Err(Duration::from_secs(50))
+        );
+    }
+
+    #[test]
+    pub fn test_store_huge_window() {
+        let store = MemoryCooldownStore::new();
+        let per = Duration::from_secs(u64::MAX);
+        let key = CooldownKey {
+            bucket: "roll".into(),
+            scope: CooldownScope::User,
+            target: 1,
+        };
+
+        assert!(store.try_acquire(&key, 1, per, start()).is_ok());
+        let later = start() + ChronoDuration::days(365);
+        assert_eq!(store.try_acquire(&key, 1, per, later), Err(per));
+
+        store.evict(later);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    pub fn test_cooldowns_periodic_eviction() {
+        let clock = Arc::new(MockClock::new(start()));
+        let store = Arc::new(MemoryCooldownStore::new());
+
+        struct SharedStore(Arc<MemoryCooldownStore>);
+        impl CooldownStore for SharedStore {
+            fn try_acquire(
+                &self,
+                key: &CooldownKey,
+                uses: u32,
+                per: Duration,
+                now: DateTime<Utc>,
+            ) -> Result<(), Duration> {
+                self.0.try_acquire(key, uses, per, now)
+            }
+
+            fn evict(&self, now: DateTime<Utc>) {
+                self.0.evict(now)
+            }
+        }
+
+        let cooldowns = Cooldowns::new(Box::new(SharedStore(store.clone())), clock.clone())
+            .with_eviction_interval(Duration::from_secs(60));
+        let short = Cooldown::new("short", 1, Duration::from_secs(1), CooldownScope::User);
+
+        assert!(cooldowns
+            .check(&short, UserId(1), ChannelId(1), None)
+            .is_ok());
+        clock.advance(ChronoDuration::seconds(30));
+        assert!(cooldowns
+            .check(&short, UserId(2), ChannelId(1), None)
+            .is_ok());
+        assert_eq!(store.len(), 2);
+
+        // Past the interval, the next check evicts both stale buckets before adding its own
+        clock.advance(ChronoDuration::seconds(30));
+        assert!(cooldowns
+            .check(&short, UserId(3), ChannelId(1), None)
+            .is_ok());
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    pub fn test_cooldown_of_path() {
+        let roll = Cooldown::new("roll", 1, Duration::from_secs(5), CooldownScope::User);
+        let mut root = CmdNode::new(ExactMatcher::new("roll".into()));
+        root.cooldown = Some(roll.clone());
+        let mut sub = CmdNode::new(ExactMatcher::new("many".into()));
+
+        assert_eq!(Cooldown::of_path(&[&sub, &root]), Some(&roll));
+
+        let many = Cooldown::new("roll", 1, Duration::from_secs(30), CooldownScope::User);
+        sub.cooldown = Some(many.clone());
+        assert_eq!(Cooldown::of_path(&[&sub, &root]), Some(&many));
+    }
+}
@@ -0,0 +1,452 @@
+use crate::cmd_tree::{CmdNode, CmdTree};
+use crate::context::Embed;
+use crate::error::CmdError;
+use crate::fragment_iter::FragmentIter;
+use crate::locale::Locales;
+use crate::matchers::ContextArg;
+use serenity::utils::Colour;
+use std::ops::Range;
+
+/// Usage lines shown at most, commands with more subcommands are cut.
+const MAX_USAGES: usize = 5;
+
+/// A `CmdError` made presentable: the command as typed with the offending part underlined,
+/// how the command should be used and a hint to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// The command as typed, without its prefix.
+    pub source: String,
+    /// Byte range of `source` to underline, empty to point right after a character.
+    pub span: Option<Range<usize>>,
+    pub usages: Vec<String>,
+    pub hint: Option<String>,
+    /// Translations of "Usage" and "Hint".
+    labels: (String, String),
+}
+
+impl Diagnostic {
+    /// Describe `error`, returned when dispatching `raw` against `cmd_root`, in `locale`.
+    pub fn new(
+        error: &CmdError,
+        raw: &str,
+        cmd_root: &CmdTree,
+        locales: &Locales,
+        locale: &str,
+    ) -> Self {
+        let tr = |key: &str, args: &[(&str, String)]| locales.tr(locale, key, args);
+
+        let mut diagnostic = Self {
+            message: locales.error(locale, error),
+            source: raw.to_owned(),
+            span: None,
+            usages: Vec::new(),
+            hint: None,
+            labels: (tr("diagnostic-usage", &[]), tr("diagnostic-hint", &[])),
+        };
+
+        // Nothing the user can act upon
+        if Self::is_internal(error) {
+            return diagnostic;
+        }
+
+        if let CmdError::ParsingError { start, end, .. } = error {
+            diagnostic.span = Some(*start..(*end + 1).min(raw.len()));
+            return diagnostic;
+        }
+
+        // Find how far the command went in the tree to point at what comes next
+        let frags = match Self::spanned_fragments(raw) {
+            Some(frags) => frags,
+            None => return diagnostic,
+        };
+        let words = frags
+            .iter()
+            .map(|(frag, _)| frag.clone())
+            .collect::<Vec<_>>();
+        let (path, consumed) = Self::deepest_match(cmd_root, &words).unwrap_or_default();
+
+        match error {
+            CmdError::NoPathFound | CmdError::NotFound { .. } => match frags.get(consumed) {
+                Some((frag, span)) if consumed == 0 => {
+                    diagnostic.span = Some(span.clone());
+                    let args = [("command", frag.clone())];
+                    diagnostic.hint = Some(tr("hint-unknown-command", &args));
+                }
+                Some((frag, span)) => {
+                    diagnostic.span = Some(span.clone());
+                    let args = [("fragment", frag.clone())];
+                    diagnostic.hint = Some(tr("hint-unexpected", &args));
+                }
+                None => {
+                    diagnostic.span = Some(raw.len()..raw.len());
+                    diagnostic.hint = Some(tr("hint-missing-arguments", &[]));
+                }
+            },
+            CmdError::OutOfRange { value, .. } => {
+                diagnostic.span = frags
+                    .iter()
+                    .find(|(frag, _)| frag == value)
+                    .map(|(_, span)| span.clone());
+            }
+            CmdError::MissingContextArg { kind, .. } => {
+                diagnostic.hint = Some(match kind {
+                    ContextArg::Attachment => tr("hint-attach-file", &[]),
+                    ContextArg::RepliedMessage => tr("hint-reply-message", &[]),
+                });
+            }
+            // Not about how the command was written
+            _ => return diagnostic,
+        }
+
+        if consumed > 0 {
+            if let Some((last, parents)) = path.split_last() {
+                diagnostic.usages = last
+                    .usages(parents, MAX_USAGES)
+                    .into_iter()
+                    .map(|usage| usage.line)
+                    .collect();
+            }
+        }
+
+        diagnostic
+    }
+
+    /// Errors coming from the bot itself rather than from the way a command was used.
+    /// They should be logged, their details mean nothing to users.
+    pub fn is_internal(error: &CmdError) -> bool {
+        match error {
+            CmdError::Module(error) => error.is_internal(),
+            _ => matches!(
+                error,
+                CmdError::Serenity(_)
+                    | CmdError::Io(_)
+                    | CmdError::MissingMapper { .. }
+                    | CmdError::EmptyCmdBuilder
+                    | CmdError::CreatorUnknownMatcher { .. }
+                    | CmdError::CreatorInvalidArgument { .. }
+                    | CmdError::InvalidCatalog { .. }
+            ),
+        }
+    }
+
+    /// Whether `error` comes from `raw` not starting like any command of `cmd_root`,
+    /// like a message only sharing its first characters with the prefix.
+    pub fn is_unknown_command(error: &CmdError, raw: &str, cmd_root: &CmdTree) -> bool {
+        if !matches!(error, CmdError::NoPathFound) {
+            return false;
+        }
+
+        let words = match Self::spanned_fragments(raw) {
+            Some(frags) => frags.into_iter().map(|(frag, _)| frag).collect::<Vec<_>>(),
+            None => return false,
+        };
+        Self::deepest_match(cmd_root, &words).map_or(true, |(_, consumed)| consumed == 0)
+    }
+
+    /// The source with a caret line under the span.
+    pub fn snippet(&self) -> Option<String> {
+        let span = self.span.as_ref()?;
+        let column = |byte: usize| {
+            self.source
+                .char_indices()
+                .take_while(|&(i, _)| i < byte)
+                .count()
+        };
+
+        let start = column(span.start);
+        let width = (column(span.end) - start).max(1);
+
+        Some(format!(
+            "{}\n{}{}",
+            self.source,
+            " ".repeat(start),
+            "^".repeat(width)
+        ))
+    }
+
+    /// Plain text reply.
+    pub fn render(&self) -> String {
+        let mut out = self.message.clone();
+
+        if let Some(snippet) = self.snippet() {
+            out.push_str(&format!("\n```\n{}\n```", snippet));
+        }
+        let (usage_label, hint_label) = &self.labels;
+        match self.usages.as_slice() {
+            [] => {}
+            [usage] => out.push_str(&format!("\n{}: `{}`", usage_label, usage)),
+            usages => {
+                out.push_str(&format!("\n{}:", usage_label));
+                for usage in usages {
+                    out.push_str(&format!("\n`{}`", usage));
+                }
+            }
+        }
+        if let Some(hint) = &self.hint {
+            out.push_str(&format!("\n{}: {}", hint_label, hint));
+        }
+
+        out
+    }
+
+    /// Embed reply.
+    pub fn embed(&self) -> Embed {
+        let mut embed = Embed::new().colour(Colour::RED.0).title(&self.message);
+
+        if let Some(snippet) = self.snippet() {
+            embed = embed.description(format!("```\n{}\n```", snippet));
+        }
+        if !self.usages.is_empty() {
+            let usages = self
+                .usages
+                .iter()
+                .map(|usage| format!("`{}`", usage))
+                .collect::<Vec<_>>();
+            embed = embed.field(&self.labels.0, usages.join("\n"), false);
+        }
+        if let Some(hint) = &self.hint {
+            embed = embed.field(&self.labels.1, hint, false);
+        }
+
+        embed
+    }
+
+    fn spanned_fragments(raw: &str) -> Option<Vec<(String, Range<usize>)>> {
+        let mut iter = FragmentIter::new(raw.to_owned());
+        let mut frags = Vec::new();
+
+        while let Some(res) = iter.next_spanned() {
+            frags.push(res.ok()?);
+        }
+
+        Some(frags)
+    }
+
+    /// The path, from the root down, matching the most fragments and how many it matched.
+    fn deepest_match<'a>(node: &'a CmdNode, frags: &[String]) -> Option<(Vec<&'a CmdNode>, usize)> {
+        let consumed = if !node.matcher.consumes_fragment() {
+            0
+        } else if node.matcher.takes_rest() {
+            match frags.is_empty() || !node.matches(&frags.join(" ")) {
+                true => return None,
+                false => frags.len(),
+            }
+        } else if frags.first().map_or(false, |frag| node.matches(frag)) {
+            1
+        } else {
+            return None;
+        };
+
+        // On a tie the first child wins, like when walking the tree
+        let (mut path, matched) = node
+            .children
+            .iter()
+            .rev()
+            .filter_map(|child| Self::deepest_match(child, &frags[consumed..]))
+            .max_by_key(|(_, matched)| *matched)
+            .unwrap_or_default();
+
+        path.insert(0, node);
+        Some((path, matched + consumed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd_args::CmdArgs;
+    use crate::cmd_creator::CmdCreator;
+    use crate::cmd_handler::CmdHandler;
+    use crate::cmd_manager::CmdManager;
+    use crate::cmd_tree::{CmdNode, CmdTree};
+    use crate::context::{CmdContext, Embed, EmbedField};
+    use crate::diagnostic::Diagnostic;
+    use crate::error::{CmdError, CmdResult};
+    use crate::locale::Locales;
+    use crate::matchers::ContextArg;
+    use serenity::async_trait;
+    use serenity::utils::Colour;
+
+    struct Noop;
+
+    #[async_trait]
+    impl CmdHandler for Noop {
+        async fn execute(&self, _: &dyn CmdContext, _: &mut CmdArgs) -> CmdResult<()> {
+            Ok(())
+        }
+    }
+
+    fn make_tree() -> CmdTree {
+        let mut prefix = CmdCreator::create_cmd("prefix set <prefix: Word>", Noop).unwrap();
+        prefix
+            .children
+            .push(CmdCreator::create_cmd("reset", Noop).unwrap());
+
+        CmdNode::root(vec![
+            CmdCreator::create_cmd("roll <n: Unsigned(1..=100)>", Noop).unwrap(),
+            prefix,
+            CmdCreator::create_cmd("upload <file: Attachment>", Noop).unwrap(),
+        ])
+    }
+
+    fn render_in(raw: &str, locale: &str) -> String {
+        let tree = make_tree();
+        let error = CmdManager::try_find_cmd_path(&tree, raw.into()).unwrap_err();
+        Diagnostic::new(&error, raw, &tree, &Locales::builtin(), locale).render()
+    }
+
+    fn render(raw: &str) -> String {
+        render_in(raw, "en")
+    }
+
+    #[test]
+    pub fn test_render_unexpected_argument() {
+        assert_eq!(
+            render("roll 1O0"),
+            "No matching path found\n\
+             ```\n\
+             roll 1O0\n     ^^^\n\
+             ```\n\
+             Usage: `roll <n: 1..=100>`\n\
+             Hint: Unexpected `1O0`, check the usage"
+        );
+    }
+
+    #[test]
+    pub fn test_render_out_of_range() {
+        assert_eq!(
+            render("roll  101"),
+            "Value 101 is out of range, expected 1..=100\n\
+             ```\n\
+             roll  101\n      ^^^\n\
+             ```\n\
+             Usage: `roll <n: 1..=100>`"
+        );
+    }
+
+    #[test]
+    pub fn test_render_missing_arguments() {
+        assert_eq!(
+            render("prefix"),
+            "No matching path found\n\
+             ```\n\
+             prefix\n      ^\n\
+             ```\n\
+             Usage:\n\
+             `prefix set <prefix>`\n\
+             `prefix reset`\n\
+             Hint: Missing arguments"
+        );
+    }
+
+    #[test]
+    pub fn test_render_unknown_command() {
+        assert_eq!(
+            render("dançe now"),
+            "No matching path found\n\
+             ```\n\
+             dançe now\n^^^^^\n\
+             ```\n\
+             Hint: Unknown command `dançe`"
+        );
+    }
+
+    #[test]
+    pub fn test_is_unknown_command() {
+        let tree = make_tree();
+        let is_unknown = |raw: &str| {
+            let error = CmdManager::try_find_cmd_path(&tree, raw.into()).unwrap_err();
+            Diagnostic::is_unknown_command(&error, raw, &tree)
+        };
+
+        assert!(is_unknown("dançe now"));
+        assert!(is_unknown("!!"));
+        assert!(!is_unknown("roll"));
+        assert!(!is_unknown("prefix set a b"));
+        assert!(!is_unknown("roll 'big"));
+    }
+
+    #[test]
+    pub fn test_render_parsing_error() {
+        assert_eq!(
+            render("roll 'big"),
+            "Command parsing error at [5..8]: Can't find closing quote.\n\
+             ```\n\
+             roll 'big\n     ^^^^\n\
+             ```"
+        );
+    }
+
+    #[test]
+    pub fn test_render_context_arg() {
+        let tree = make_tree();
+        let error = CmdError::MissingContextArg {
+            name: "file".into(),
+            kind: ContextArg::Attachment,
+        };
+
+        assert_eq!(
+            Diagnostic::new(&error, "upload", &tree, &Locales::builtin(), "en").render(),
+            "Missing attachment for argument file\n\
+             Usage: `upload [file]`\n\
+             Hint: Attach a file to your message"
+        );
+    }
+
+    #[test]
+    pub fn test_render_internal() {
+        let tree = make_tree();
+        let error = CmdError::MissingMapper {
+            matcher: "UnsignedMatcher".into(),
+        };
+
+        assert!(Diagnostic::is_internal(&error));
+        assert_eq!(
+            Diagnostic::new(&error, "roll 6", &tree, &Locales::builtin(), "en").render(),
+            "Something went wrong while running this command."
+        );
+    }
+
+    #[test]
+    pub fn test_embed() {
+        let tree = make_tree();
+        let error = CmdManager::try_find_cmd_path(&tree, "prefix".into()).unwrap_err();
+        let diagnostic = Diagnostic::new(&error, "prefix", &tree, &Locales::builtin(), "en");
+
+        assert_eq!(
+            diagnostic.embed(),
+            Embed {
+                title: Some("No matching path found".into()),
+                description: Some("```\nprefix\n      ^\n```".into()),
+                colour: Some(Colour::RED.0),
+                fields: vec![
+                    EmbedField {
+                        name: "Usage".into(),
+                        value: "`prefix set <prefix>`\n`prefix reset`".into(),
+                        inline: false,
+                    },
+                    EmbedField {
+                        name: "Hint".into(),
+                        value: "Missing arguments".into(),
+                        inline: false,
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_render_french() {
+        assert_eq!(
+            render_in("prefix", "fr"),
+            "Aucune commande ne correspond\n\
+             ```\n\
+             prefix\n      ^\n\
+             ```\n\
+             Utilisation:\n\
+             `prefix set <prefix>`\n\
+             `prefix reset`\n\
+             Astuce: Arguments manquants"
+        );
+    }
+}
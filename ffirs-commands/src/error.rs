@@ -1,7 +1,26 @@
+use crate::matchers::ContextArg;
+use serenity::model::permissions::Permissions;
+use std::any::Any;
+use std::error::Error as StdError;
+use std::time::Duration;
 use thiserror::Error;
 
 pub type CmdResult<T> = std::result::Result<T, CmdError>;
 
+/// An error of the code built on top of the commands, like the modules of a bot,
+/// described by its own messages in the catalogs of `Locales`.
+pub trait LocalizedError: StdError + Send + Sync + 'static {
+    /// Key of the message describing the error, with its arguments.
+    fn message(&self) -> (&'static str, Vec<(&'static str, String)>);
+
+    /// Errors coming from the bot itself, see `Diagnostic::is_internal`.
+    fn is_internal(&self) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any;
+}
+
 #[derive(Error, Debug)]
 pub enum CmdError {
     #[error("Command parsing error at [{start}..{end}]: {message}")]
@@ -16,9 +35,52 @@ pub enum CmdError {
     NoPathFound,
     #[error("Missing permission, required level: {level}")]
     MissingPerm { level: u32 },
+    #[error("Missing Discord permissions: {missing:?}")]
+    MissingPermissions { missing: Permissions },
+    #[error("Command on cooldown, retry in {:.1}s", .remaining.as_secs_f32())]
+    OnCooldown { remaining: Duration },
+    #[error("Value {value} is out of range, expected {range}")]
+    OutOfRange { value: String, range: String },
+    #[error("Missing {kind} for argument {name}")]
+    MissingContextArg { name: String, kind: ContextArg },
+    #[error("No mapper registered for {matcher}")]
+    MissingMapper { matcher: String },
+    #[error("This command can only be used in a guild")]
+    GuildOnly,
+    #[error("This command can only be used on Discord")]
+    DiscordOnly,
+    #[error("Unknown locale {locale}")]
+    UnknownLocale { locale: String },
+    #[error("{0}")]
+    Module(Box<dyn LocalizedError>),
+    #[error(transparent)]
+    Serenity(#[from] serenity::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
 
     #[error("Can't build a command without fragments !")]
     EmptyCmdBuilder,
     #[error("Unknown matcher type ecoutered: {ty}")]
     CreatorUnknownMatcher { ty: String },
+    #[error("Invalid argument for matcher type {ty}: {argument}")]
+    CreatorInvalidArgument { ty: String, argument: String },
+
+    #[error("Invalid catalog at line {line}: {message}")]
+    InvalidCatalog { line: usize, message: String },
+}
+
+impl CmdError {
+    /// The error of a module, if it is an `E`.
+    pub fn downcast_ref<E: LocalizedError>(&self) -> Option<&E> {
+        match self {
+            CmdError::Module(error) => error.as_any().downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl<E: LocalizedError> From<E> for CmdError {
+    fn from(error: E) -> Self {
+        CmdError::Module(Box::new(error))
+    }
 }
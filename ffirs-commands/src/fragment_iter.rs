@@ -1,5 +1,6 @@
 use crate::error::CmdError::ParsingError;
 use crate::error::CmdResult;
+use std::ops::{Deref, Range};
 
 pub struct FragmentIter {
     original: String,
@@ -19,33 +20,33 @@ impl FragmentIter {
             self.original.len() - self.original[self.current_position..].trim_start().len();
     }
 
+    /// Byte length of the first word of `fragment`.
     pub(self) fn find_end_of_word(fragment: &str) -> usize {
-        fragment.chars().take_while(|c| !c.is_whitespace()).count()
+        fragment
+            .find(char::is_whitespace)
+            .unwrap_or_else(|| fragment.len())
     }
 
     /// Take a string slice that begin with a type of quote.
-    /// Returns the position of its corresponding quote or None.
+    /// Returns the byte position of its corresponding quote or None.
     /// A quote can actually be any valid char.
     pub(self) fn find_end_of_quote(fragment: &str) -> Option<usize> {
         let quote = fragment.chars().next().unwrap();
+        let quote_len = quote.len_utf8();
 
         // TODO: Escape characters ?
-        let quoted_length = fragment.chars().skip(1).take_while(|&c| c != quote).count();
-
-        let supposed_end = quoted_length + 1;
-        if fragment.chars().nth(supposed_end)? == quote {
-            Some(supposed_end)
-        } else {
-            None
-        }
+        fragment[quote_len..]
+            .find(quote)
+            .map(|quoted_length| quoted_length + quote_len)
     }
 }
 
-impl Iterator for FragmentIter {
-    type Item = CmdResult<String>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+impl FragmentIter {
+    /// Like `next` but also gives the byte range of the fragment in the original string,
+    /// quotes included.
+    pub fn next_spanned(&mut self) -> Option<CmdResult<(String, Range<usize>)>> {
         self.trim_self();
+        let start = self.current_position;
         let remaining = &self.original[self.current_position..];
 
         if self.current_position < self.original.len() {
@@ -56,7 +57,6 @@ impl Iterator for FragmentIter {
                 let end = Self::find_end_of_quote(remaining);
 
                 if let None = end {
-                    let start = self.current_position;
                     self.current_position = self.original.len();
                     return Some(Err(ParsingError {
                         message: String::from("Can't find closing quote."),
@@ -65,7 +65,8 @@ impl Iterator for FragmentIter {
                     }));
                 }
 
-                (&remaining[1..end.unwrap()], end.unwrap() + 1)
+                let end = end.unwrap();
+                (&remaining[first_char.len_utf8()..end], end + first_char.len_utf8())
             } else {
                 let end = Self::find_end_of_word(remaining);
                 (&remaining[..end], end)
@@ -73,17 +74,85 @@ impl Iterator for FragmentIter {
 
             self.current_position += end;
 
-            Some(Ok(fragment.to_owned()))
+            Some(Ok((fragment.to_owned(), start..self.current_position)))
         } else {
             None
         }
     }
 }
 
+impl Iterator for FragmentIter {
+    type Item = CmdResult<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spanned()
+            .map(|res| res.map(|(fragment, _)| fragment))
+    }
+}
+
+/// The fragments of a raw command, remembering where each of them starts
+/// so that a matcher taking the rest gets it as it was typed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fragments {
+    raw: String,
+    frags: Vec<String>,
+    starts: Vec<usize>,
+}
+
+impl Fragments {
+    /// Split `raw` into fragments, failing on the first parsing error.
+    pub fn parse(raw: String) -> CmdResult<Self> {
+        let mut iter = FragmentIter::new(raw.clone());
+        let mut frags = Vec::new();
+        let mut starts = Vec::new();
+
+        while let Some(res) = iter.next_spanned() {
+            let (frag, span) = res?;
+            frags.push(frag);
+            starts.push(span.start);
+        }
+
+        Ok(Self { raw, frags, starts })
+    }
+
+    /// The raw command from the fragment at `index` on, with its quotes, spaces and newlines.
+    pub fn rest(&self, index: usize) -> &str {
+        self.starts
+            .get(index)
+            .map_or("", |&start| self.raw[start..].trim_end())
+    }
+}
+
+impl Deref for Fragments {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        &self.frags
+    }
+}
+
+/// Fragments separated by a single space, as if typed like that.
+impl From<Vec<String>> for Fragments {
+    fn from(frags: Vec<String>) -> Self {
+        let mut starts = Vec::with_capacity(frags.len());
+        let mut start = 0;
+        for frag in &frags {
+            starts.push(start);
+            start += frag.len() + 1;
+        }
+
+        Self {
+            raw: frags.join(" "),
+            frags,
+            starts,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::error::CmdError;
-    use crate::fragment_iter::FragmentIter;
+    use crate::fragment_iter::{FragmentIter, Fragments};
 
     #[test]
     pub fn test_fragment_iterator() {
@@ -127,6 +196,25 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn test_fragment_spans() {
+        let mut iterator = FragmentIter::new(String::from(" roll  'big one' 6"));
+
+        assert_eq!(iterator.next_spanned().unwrap().unwrap(), ("roll".into(), 1..5));
+        assert_eq!(iterator.next_spanned().unwrap().unwrap(), ("big one".into(), 7..16));
+        assert_eq!(iterator.next_spanned().unwrap().unwrap(), ("6".into(), 17..18));
+        assert!(iterator.next_spanned().is_none());
+    }
+
+    #[test]
+    pub fn test_fragment_non_ascii() {
+        let mut iterator = FragmentIter::new(String::from("créer 'été chaud' ok"));
+
+        assert_eq!(iterator.next_spanned().unwrap().unwrap(), ("créer".into(), 0..6));
+        assert_eq!(iterator.next_spanned().unwrap().unwrap(), ("été chaud".into(), 7..20));
+        assert_eq!(iterator.next().unwrap().unwrap(), "ok");
+    }
+
     #[test]
     pub fn test_trim_self_normal() {
         let mut it = FragmentIter::new(String::from("  hey"));
@@ -188,4 +276,18 @@ mod tests {
         let invalid = "'nope hey";
         assert_eq!(FragmentIter::find_end_of_quote(invalid), None);
     }
+
+    #[test]
+    pub fn test_fragments_rest() {
+        let frags = Fragments::parse(String::from(" note  'a b'  c\n  d \n")).unwrap();
+
+        assert_eq!(&*frags, &["note", "a b", "c", "d"]);
+        assert_eq!(frags.rest(1), "'a b'  c\n  d");
+        assert_eq!(frags.rest(3), "d");
+        assert_eq!(frags.rest(4), "");
+
+        let frags = Fragments::from(vec![String::from("a"), String::from("b c")]);
+        assert_eq!(frags.rest(1), "b c");
+        assert!(Fragments::parse(String::from("a 'b")).is_err());
+    }
 }
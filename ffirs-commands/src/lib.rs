@@ -4,16 +4,27 @@ use serenity::model::prelude::Message;
 
 use error::CmdResult;
 
+pub mod clock;
+pub mod cmd_args;
 pub mod cmd_creator;
+pub mod cmd_dispatcher;
+pub mod cmd_handler;
 pub mod cmd_tree_builder_ext;
 pub mod cmd_tree_builder;
 pub mod cmd_manager;
 pub mod cmd_tree;
 pub mod cmd_walker;
+pub mod context;
+pub mod cooldown;
+pub mod diagnostic;
 pub mod error;
 pub mod fragment_iter;
+pub mod locale;
 pub mod mappers;
 pub mod matchers;
+pub mod middleware;
+mod parsers;
+pub mod perms;
 pub mod type_map;
 
 // #[command]
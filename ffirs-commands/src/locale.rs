@@ -0,0 +1,532 @@
+use crate::cmd_tree::CmdNode;
+use crate::error::{CmdError, CmdResult};
+use crate::matchers::ContextArg;
+use serenity::model::id::{GuildId, UserId};
+use std::collections::HashMap;
+use std::io;
+use std::sync::RwLock;
+
+const BUILTIN_EN: &str = include_str!("../locales/en.ftl");
+const BUILTIN_FR: &str = include_str!("../locales/fr.ftl");
+
+/// Messages of a single locale, parsed from a subset of the Fluent syntax:
+///
+/// ```ftl
+/// # A comment
+/// error-out-of-range = Value { $value } is out of range, expected { $range }
+/// help-title = Commands
+///     indented lines continue the previous message
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Catalog {
+    messages: HashMap<String, String>,
+}
+
+impl Catalog {
+    pub fn parse(source: &str) -> CmdResult<Self> {
+        let invalid = |line: usize, message: &str| CmdError::InvalidCatalog {
+            line,
+            message: message.into(),
+        };
+
+        let mut messages = HashMap::<String, String>::new();
+        let mut last_key: Option<String> = None;
+
+        for (index, line) in source.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with(char::is_whitespace) {
+                let key = last_key
+                    .as_ref()
+                    .ok_or_else(|| invalid(index + 1, "Indented line outside of a message"))?;
+                let value = messages.get_mut(key).unwrap();
+                if !value.is_empty() {
+                    value.push('\n');
+                }
+                value.push_str(trimmed);
+                continue;
+            }
+
+            let eq = line
+                .find('=')
+                .ok_or_else(|| invalid(index + 1, "Expected `key = value`"))?;
+            let key = line[..eq].trim();
+            if !Self::is_valid_key(key) {
+                return Err(invalid(index + 1, "Invalid message key"));
+            }
+
+            messages.insert(key.to_owned(), line[eq + 1..].trim().to_owned());
+            last_key = Some(key.to_owned());
+        }
+
+        Ok(Self { messages })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.messages.get(key).map(String::as_str)
+    }
+
+    /// The message `key` with its `{ $name }` placeables replaced by `args`.
+    pub fn format(&self, key: &str, args: &[(&str, String)]) -> Option<String> {
+        self.get(key)
+            .map(|message| Self::format_message(message, args))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.messages.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Add the messages of `other`, replacing the ones with the same key.
+    pub fn merge(&mut self, other: Catalog) {
+        self.messages.extend(other.messages);
+    }
+
+    fn is_valid_key(key: &str) -> bool {
+        let mut chars = key.chars();
+        chars.next().map_or(false, |c| c.is_ascii_alphabetic())
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    fn format_message(message: &str, args: &[(&str, String)]) -> String {
+        let mut formatted = String::with_capacity(message.len());
+        let mut rest = message;
+
+        while let Some(open) = rest.find('{') {
+            formatted.push_str(&rest[..open]);
+            let after = &rest[open + 1..];
+
+            let close = match after.find('}') {
+                Some(close) => close,
+                None => {
+                    rest = &rest[open..];
+                    break;
+                }
+            };
+
+            let placeable = after[..close].trim();
+            let value = placeable
+                .strip_prefix('$')
+                .and_then(|name| args.iter().find(|(arg, _)| *arg == name));
+            match value {
+                Some((_, value)) => formatted.push_str(value),
+                // Like Fluent, show what couldn't be resolved
+                None => formatted.push_str(&format!("{{{}}}", placeable)),
+            }
+
+            rest = &after[close + 1..];
+        }
+
+        formatted.push_str(rest);
+        formatted
+    }
+}
+
+/// Who chose a locale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LocaleOwner {
+    Guild(GuildId),
+    User(UserId),
+}
+
+/// Keeps the locale chosen by each guild and user.
+pub trait LocaleStore: Send + Sync {
+    fn get(&self, owner: LocaleOwner) -> Option<String>;
+    fn set(&self, owner: LocaleOwner, locale: &str) -> io::Result<()>;
+    fn remove(&self, owner: LocaleOwner) -> io::Result<()>;
+}
+
+/// Forgets every choice on restart.
+#[derive(Debug, Default)]
+pub struct MemoryLocaleStore {
+    locales: RwLock<HashMap<LocaleOwner, String>>,
+}
+
+impl MemoryLocaleStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl LocaleStore for MemoryLocaleStore {
+    fn get(&self, owner: LocaleOwner) -> Option<String> {
+        self.locales.read().unwrap().get(&owner).cloned()
+    }
+
+    fn set(&self, owner: LocaleOwner, locale: &str) -> io::Result<()> {
+        self.locales
+            .write()
+            .unwrap()
+            .insert(owner, locale.to_owned());
+        Ok(())
+    }
+
+    fn remove(&self, owner: LocaleOwner) -> io::Result<()> {
+        self.locales.write().unwrap().remove(&owner);
+        Ok(())
+    }
+}
+
+/// Every catalog of the bot and the locale chosen by guilds and users.
+pub struct Locales {
+    default: String,
+    catalogs: HashMap<String, Catalog>,
+    store: Box<dyn LocaleStore>,
+}
+
+impl Locales {
+    /// Empty locales, falling back to `default` when a message is missing.
+    pub fn new<S: Into<String>>(default: S) -> Self {
+        Self {
+            default: default.into(),
+            catalogs: HashMap::new(),
+            store: Box::new(MemoryLocaleStore::new()),
+        }
+    }
+
+    /// English and French catalogs of the command framework, English by default.
+    pub fn builtin() -> Self {
+        Self::new("en")
+            .with_catalog(
+                "en",
+                Catalog::parse(BUILTIN_EN).expect("Invalid builtin catalog"),
+            )
+            .with_catalog(
+                "fr",
+                Catalog::parse(BUILTIN_FR).expect("Invalid builtin catalog"),
+            )
+    }
+
+    /// Add the messages of `catalog` to `locale`, replacing the ones with the same key.
+    pub fn with_catalog<S: Into<String>>(mut self, locale: S, catalog: Catalog) -> Self {
+        self.catalogs
+            .entry(locale.into())
+            .or_default()
+            .merge(catalog);
+        self
+    }
+
+    pub fn with_store(mut self, store: Box<dyn LocaleStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    pub fn default_locale(&self) -> &str {
+        &self.default
+    }
+
+    pub fn available(&self) -> Vec<&str> {
+        let mut locales = self.catalogs.keys().map(String::as_str).collect::<Vec<_>>();
+        locales.sort_unstable();
+        locales
+    }
+
+    pub fn set_guild_locale(&self, guild: GuildId, locale: &str) -> CmdResult<()> {
+        let locale = self.check_locale(locale)?;
+        self.store.set(LocaleOwner::Guild(guild), &locale)?;
+        Ok(())
+    }
+
+    pub fn clear_guild_locale(&self, guild: GuildId) -> CmdResult<()> {
+        self.store.remove(LocaleOwner::Guild(guild))?;
+        Ok(())
+    }
+
+    pub fn set_user_locale(&self, user: UserId, locale: &str) -> CmdResult<()> {
+        let locale = self.check_locale(locale)?;
+        self.store.set(LocaleOwner::User(user), &locale)?;
+        Ok(())
+    }
+
+    pub fn clear_user_locale(&self, user: UserId) -> CmdResult<()> {
+        self.store.remove(LocaleOwner::User(user))?;
+        Ok(())
+    }
+
+    /// The locale chosen by the user, else by the guild, else the default one.
+    /// A stored locale without catalog anymore is skipped.
+    pub fn locale_of(&self, user: UserId, guild: Option<GuildId>) -> String {
+        let chosen = |owner| {
+            self.store
+                .get(owner)
+                .filter(|locale| self.catalogs.contains_key(locale))
+        };
+
+        chosen(LocaleOwner::User(user))
+            .or_else(|| guild.and_then(|guild| chosen(LocaleOwner::Guild(guild))))
+            .unwrap_or_else(|| self.default.clone())
+    }
+
+    /// The message `key` in `locale`, else in the default locale.
+    pub fn lookup(&self, locale: &str, key: &str, args: &[(&str, String)]) -> Option<String> {
+        self.catalogs
+            .get(locale)
+            .and_then(|catalog| catalog.format(key, args))
+            .or_else(|| self.catalogs.get(&self.default)?.format(key, args))
+    }
+
+    /// Like `lookup` but gives back the key when no catalog has the message.
+    pub fn tr(&self, locale: &str, key: &str, args: &[(&str, String)]) -> String {
+        self.lookup(locale, key, args)
+            .unwrap_or_else(|| key.to_owned())
+    }
+
+    /// Localized description of `error`, internal errors all give the same message.
+    pub fn error(&self, locale: &str, error: &CmdError) -> String {
+        let (key, args) = match error {
+            CmdError::ParsingError {
+                message,
+                start,
+                end,
+            } => (
+                "error-parsing",
+                vec![
+                    ("message", message.clone()),
+                    ("start", start.to_string()),
+                    ("end", end.to_string()),
+                ],
+            ),
+            CmdError::NotFound { name } => ("error-not-found", vec![("name", name.clone())]),
+            CmdError::NoPathFound => ("error-no-path-found", vec![]),
+            CmdError::MissingPerm { level } => {
+                ("error-missing-perm", vec![("level", level.to_string())])
+            }
+            CmdError::MissingPermissions { missing } => (
+                "error-missing-permissions",
+                vec![("missing", format!("{:?}", missing))],
+            ),
+            CmdError::OnCooldown { remaining } => (
+                "error-on-cooldown",
+                vec![("seconds", format!("{:.1}", remaining.as_secs_f32()))],
+            ),
+            CmdError::OutOfRange { value, range } => (
+                "error-out-of-range",
+                vec![("value", value.clone()), ("range", range.clone())],
+            ),
+            CmdError::MissingContextArg { name, kind } => {
+                let kind = match kind {
+                    ContextArg::Attachment => "context-arg-attachment",
+                    ContextArg::RepliedMessage => "context-arg-replied-message",
+                };
+                (
+                    "error-missing-context-arg",
+                    vec![("name", name.clone()), ("kind", self.tr(locale, kind, &[]))],
+                )
+            }
+            CmdError::GuildOnly => ("error-guild-only", vec![]),
+            CmdError::DiscordOnly => ("error-discord-only", vec![]),
+            CmdError::UnknownLocale { locale } => (
+                "error-unknown-locale",
+                vec![
+                    ("locale", locale.clone()),
+                    ("available", self.available().join(", ")),
+                ],
+            ),
+            CmdError::Module(error) if !error.is_internal() => error.message(),
+            _ => ("error-internal", vec![]),
+        };
+
+        self.lookup(locale, key, &args)
+            .unwrap_or_else(|| error.to_string())
+    }
+
+    /// Let the literals of a tree be typed in every language, the aliases of a literal
+    /// being listed under `alias-<literals leading to it>`, like `alias-prefix-set = définir`.
+    pub fn apply_aliases(&self, cmd_root: &mut CmdNode) {
+        let mut catalogs = self.catalogs.iter().collect::<Vec<_>>();
+        catalogs.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        let catalogs = catalogs
+            .into_iter()
+            .map(|(_, catalog)| catalog)
+            .collect::<Vec<_>>();
+
+        Self::apply_aliases_from(&catalogs, cmd_root, Vec::new());
+    }
+
+    fn apply_aliases_from(catalogs: &[&Catalog], node: &mut CmdNode, mut words: Vec<String>) {
+        if let Some(literal) = node.matcher.literal() {
+            words.push(literal.to_owned());
+            let key = format!("alias-{}", words.join("-"));
+
+            let aliases = catalogs
+                .iter()
+                .filter_map(|catalog| catalog.get(&key))
+                .flat_map(str::split_whitespace);
+            for alias in aliases {
+                if alias != literal && !node.aliases.iter().any(|known| known == alias) {
+                    node.aliases.push(alias.to_owned());
+                }
+            }
+        }
+
+        for child in &mut node.children {
+            Self::apply_aliases_from(catalogs, child, words.clone());
+        }
+    }
+
+    fn check_locale(&self, locale: &str) -> CmdResult<String> {
+        let locale = locale.to_lowercase();
+
+        if self.catalogs.contains_key(&locale) {
+            Ok(locale)
+        } else {
+            Err(CmdError::UnknownLocale { locale })
+        }
+    }
+}
+
+impl Default for Locales {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd_creator::CmdCreator;
+    use crate::cmd_manager::CmdManager;
+    use crate::error::CmdError;
+    use crate::locale::{Catalog, Locales};
+    use crate::matchers::ContextArg;
+    use serenity::model::id::{GuildId, UserId};
+
+    #[test]
+    pub fn test_catalog_parse() {
+        let catalog = Catalog::parse(
+            "# comment\n\
+             \n\
+             greeting = Hello { $name }!\n\
+             multi = first\n    second\n\
+             broken = { $missing } and {  $name}\n",
+        )
+        .unwrap();
+
+        assert_eq!(catalog.len(), 3);
+        assert_eq!(
+            catalog.format("greeting", &[("name", "Ffirs".into())]),
+            Some("Hello Ffirs!".into())
+        );
+        assert_eq!(catalog.get("multi"), Some("first\nsecond"));
+        assert_eq!(
+            catalog.format("broken", &[("name", "x".into())]),
+            Some("{$missing} and x".into())
+        );
+        assert_eq!(catalog.get("nope"), None);
+    }
+
+    #[test]
+    pub fn test_catalog_parse_errors() {
+        match Catalog::parse("ok = fine\nno equal sign") {
+            Err(CmdError::InvalidCatalog { line, .. }) => assert_eq!(line, 2),
+            _ => unreachable!(),
+        }
+        assert!(Catalog::parse("  orphan").is_err());
+        assert!(Catalog::parse("bad key = value").is_err());
+        assert!(Catalog::parse("-bad = value").is_err());
+    }
+
+    #[test]
+    pub fn test_builtin_catalogs_complete() {
+        let en = Catalog::parse(include_str!("../locales/en.ftl")).unwrap();
+        let fr = Catalog::parse(include_str!("../locales/fr.ftl")).unwrap();
+
+        let mut en_keys = en.keys().collect::<Vec<_>>();
+        let mut fr_keys = fr.keys().collect::<Vec<_>>();
+        en_keys.sort_unstable();
+        fr_keys.sort_unstable();
+        assert_eq!(en_keys, fr_keys);
+    }
+
+    #[test]
+    pub fn test_locale_selection() {
+        let locales = Locales::builtin();
+        let guild = GuildId(1);
+        let user = UserId(2);
+
+        assert_eq!(locales.locale_of(user, Some(guild)), "en");
+        locales.set_guild_locale(guild, "FR").unwrap();
+        assert_eq!(locales.locale_of(user, Some(guild)), "fr");
+        assert_eq!(locales.locale_of(user, None), "en");
+
+        locales.set_user_locale(user, "en").unwrap();
+        assert_eq!(locales.locale_of(user, Some(guild)), "en");
+        locales.clear_user_locale(user).unwrap();
+        assert_eq!(locales.locale_of(user, Some(guild)), "fr");
+
+        match locales.set_user_locale(user, "de") {
+            Err(CmdError::UnknownLocale { locale }) => assert_eq!(locale, "de"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    pub fn test_localized_errors() {
+        let locales = Locales::builtin();
+        let error = CmdError::MissingContextArg {
+            name: "file".into(),
+            kind: ContextArg::Attachment,
+        };
+
+        // The English catalog says the same as the error itself
+        assert_eq!(locales.error("en", &error), error.to_string());
+        assert_eq!(
+            locales.error("fr", &error),
+            "Il manque une pièce jointe pour l'argument file"
+        );
+        assert_eq!(
+            locales.error(
+                "fr",
+                &CmdError::UnknownLocale {
+                    locale: "de".into()
+                }
+            ),
+            "Langue inconnue de, disponibles : en, fr"
+        );
+        assert_eq!(
+            locales.error("fr", &CmdError::EmptyCmdBuilder),
+            "Une erreur est survenue pendant l'exécution de cette commande."
+        );
+    }
+
+    #[test]
+    pub fn test_fallback_to_default() {
+        let locales = Locales::builtin()
+            .with_catalog("fr", Catalog::parse("only-fr = Bonjour").unwrap())
+            .with_catalog("en", Catalog::parse("only-en = Hello").unwrap());
+
+        assert_eq!(locales.tr("fr", "only-en", &[]), "Hello");
+        assert_eq!(locales.tr("de", "only-en", &[]), "Hello");
+        assert_eq!(locales.tr("en", "only-fr", &[]), "only-fr");
+        // Merged with the builtin messages
+        assert_eq!(locales.tr("fr", "diagnostic-hint", &[]), "Astuce");
+    }
+
+    #[test]
+    pub fn test_apply_aliases() {
+        let locales = Locales::builtin().with_catalog(
+            "fr",
+            Catalog::parse("alias-prefix = préfixe\nalias-prefix-set = définir changer").unwrap(),
+        );
+        let mut cmd_root = CmdCreator::create_cmd_branch("prefix set <prefix: Word>").unwrap();
+        locales.apply_aliases(&mut cmd_root);
+
+        assert_eq!(cmd_root.aliases, vec![String::from("préfixe")]);
+        assert_eq!(
+            cmd_root.children[0].aliases,
+            vec![String::from("définir"), String::from("changer")]
+        );
+
+        let path = CmdManager::try_find_cmd_path(&cmd_root, "préfixe changer ?".into()).unwrap();
+        assert_eq!(path.len(), 3);
+    }
+}
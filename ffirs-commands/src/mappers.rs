@@ -1,12 +1,21 @@
-use serenity::model::id::UserId;
+use crate::matchers::{BoolMatcher, Choice};
+use crate::parsers::{
+    is_day_only, parse_colour, parse_date_time, parse_date_time_in, parse_duration,
+    parse_message_link, parse_snowflake,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::utils::Colour;
 use std::any::Any;
 use std::str::FromStr;
+use url::Url;
 
-pub type MapperOutput = Box<dyn Any>;
+/// Mapped values are sent along with the command to its async handler.
+pub type MapperOutput = Box<dyn Any + Send + Sync>;
 
 /// Used to map matched fragments to there output type.
 /// The mapper must never `panic!` or fail because it was checked by its corresponding matcher.
-pub trait FragmentMapper {
+pub trait FragmentMapper: Send + Sync {
     fn map(&self, fragment: &str) -> MapperOutput;
 }
 
@@ -25,7 +34,7 @@ pub struct FromStrMapper<F: FromStr> {
     _phantom_data: std::marker::PhantomData<F>,
 }
 
-impl<F: FromStr + 'static> FragmentMapper for FromStrMapper<F> {
+impl<F: FromStr + Send + Sync + 'static> FragmentMapper for FromStrMapper<F> {
     fn map(&self, fragment: &str) -> MapperOutput {
         match F::from_str(fragment) {
             Ok(value) => Box::new(value),
@@ -51,6 +60,16 @@ impl FragmentMapper for UserMapper {
     }
 }
 
+/// Extracts the `ChannelId` from a channel mention.
+pub struct ChannelMapper;
+
+impl FragmentMapper for ChannelMapper {
+    fn map(&self, fragment: &str) -> MapperOutput {
+        let id_str = &fragment[2..fragment.len() - 1];
+        Box::new(ChannelId(id_str.parse::<u64>().unwrap()))
+    }
+}
+
 /// Return the fragment as is.
 pub struct ExactMapper;
 
@@ -60,10 +79,180 @@ impl FragmentMapper for ExactMapper {
     }
 }
 
+/// Maps a compact duration to a `std::time::Duration`.
+pub struct DurationMapper;
+
+impl FragmentMapper for DurationMapper {
+    fn map(&self, fragment: &str) -> MapperOutput {
+        Box::new(parse_duration(fragment).unwrap())
+    }
+}
+
+/// Maps a point in time to a `DateTime<Utc>`.
+/// Relative phrases are resolved against the mapper's clock.
+pub struct DateTimeMapper {
+    now: fn() -> DateTime<Utc>,
+}
+
+impl DateTimeMapper {
+    /// Use a custom clock, mostly useful to get deterministic tests.
+    pub fn with_clock(now: fn() -> DateTime<Utc>) -> Self {
+        Self { now }
+    }
+}
+
+impl Default for DateTimeMapper {
+    fn default() -> Self {
+        Self { now: Utc::now }
+    }
+}
+
+impl FragmentMapper for DateTimeMapper {
+    fn map(&self, fragment: &str) -> MapperOutput {
+        Box::new(parse_date_time(fragment, (self.now)()).unwrap())
+    }
+}
+
+/// A point in time as typed, its times without an offset being in a timezone
+/// only known by the command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalDateTime(String);
+
+impl LocalDateTime {
+    /// The point in time in the timezone of `now`,
+    /// `None` for a local time skipped when the clocks went forward.
+    pub fn resolve<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<DateTime<Utc>> {
+        parse_date_time_in(&self.0, now)
+    }
+
+    /// The point in time as typed.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether only a day was typed, like `tomorrow`, the time of day possibly
+    /// being the next word when the user forgot to quote both.
+    pub fn is_day_only(&self) -> bool {
+        is_day_only(&self.0)
+    }
+}
+
+/// Maps a point in time to a `LocalDateTime`.
+pub struct LocalDateTimeMapper;
+
+impl FragmentMapper for LocalDateTimeMapper {
+    fn map(&self, fragment: &str) -> MapperOutput {
+        Box::new(LocalDateTime(fragment.to_owned()))
+    }
+}
+
+/// Maps the words accepted by a `BoolMatcher` to a `bool`.
+pub struct BoolMapper;
+
+impl FragmentMapper for BoolMapper {
+    fn map(&self, fragment: &str) -> MapperOutput {
+        Box::new(BoolMatcher::parse(fragment).unwrap())
+    }
+}
+
+/// Maps a name to its `Choice` value.
+pub struct ChoiceMapper<C: Choice> {
+    _phantom_data: std::marker::PhantomData<C>,
+}
+
+impl<C: Choice> Default for ChoiceMapper<C> {
+    fn default() -> Self {
+        Self {
+            _phantom_data: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<C: Choice> FragmentMapper for ChoiceMapper<C> {
+    fn map(&self, fragment: &str) -> MapperOutput {
+        Box::new(C::from_choice(fragment).unwrap())
+    }
+}
+
+/// Ids extracted from a Discord message link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageLink {
+    /// `None` for a message in direct messages.
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+}
+
+/// Maps a message link to a `MessageLink`.
+pub struct MessageLinkMapper;
+
+impl FragmentMapper for MessageLinkMapper {
+    fn map(&self, fragment: &str) -> MapperOutput {
+        let (guild, channel, message) = parse_message_link(fragment).unwrap();
+
+        Box::new(MessageLink {
+            guild_id: guild.map(GuildId),
+            channel_id: ChannelId(channel),
+            message_id: MessageId(message),
+        })
+    }
+}
+
+/// Maps an URL to a `url::Url`.
+pub struct UrlMapper;
+
+impl FragmentMapper for UrlMapper {
+    fn map(&self, fragment: &str) -> MapperOutput {
+        Box::new(Url::parse(fragment).unwrap())
+    }
+}
+
+/// Maps a colour to serenity's `Colour`.
+pub struct ColourMapper;
+
+impl FragmentMapper for ColourMapper {
+    fn map(&self, fragment: &str) -> MapperOutput {
+        Box::new(Colour::new(parse_colour(fragment).unwrap()))
+    }
+}
+
+/// A raw Discord id, of any kind of object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Snowflake(pub u64);
+
+impl Snowflake {
+    /// First millisecond of 2015, ids count from there.
+    const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+    /// Creation time embedded in the id.
+    pub fn timestamp(self) -> DateTime<Utc> {
+        let millis = (self.0 >> 22) + Self::DISCORD_EPOCH;
+        Utc.timestamp_millis(millis as i64)
+    }
+}
+
+/// Maps a raw id to a `Snowflake`.
+pub struct SnowflakeMapper;
+
+impl FragmentMapper for SnowflakeMapper {
+    fn map(&self, fragment: &str) -> MapperOutput {
+        Box::new(Snowflake(parse_snowflake(fragment).unwrap()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::mappers::{FragmentMapper, FromStrMapper, NoopMapper, UserMapper};
-    use serenity::model::id::UserId;
+    use crate::mappers::{
+        BoolMapper, ChannelMapper, ChoiceMapper, ColourMapper, DateTimeMapper, DurationMapper,
+        FragmentMapper, FromStrMapper, LocalDateTime, LocalDateTimeMapper, MessageLink,
+        MessageLinkMapper, NoopMapper, Snowflake, SnowflakeMapper, UrlMapper, UserMapper,
+    };
+    use crate::matchers::Choice;
+    use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+    use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+    use serenity::utils::Colour;
+    use url::Url;
+    use std::time::Duration;
 
     #[test]
     pub fn test_mapper_noop() {
@@ -88,6 +277,16 @@ mod tests {
         assert_eq!(mapper.map("false").downcast().unwrap(), Box::new(false));
     }
 
+    #[test]
+    pub fn test_mapper_channel_id() {
+        let mapper = ChannelMapper;
+
+        assert_eq!(
+            mapper.map("<#123456>").downcast().unwrap(),
+            Box::new(ChannelId(123456))
+        );
+    }
+
     #[test]
     pub fn test_mapper_user_id() {
         let mapper = UserMapper;
@@ -101,4 +300,173 @@ mod tests {
             Box::new(UserId(123456))
         );
     }
+
+    #[test]
+    pub fn test_mapper_duration() {
+        let mapper = DurationMapper;
+
+        assert_eq!(
+            mapper.map("1h30m").downcast().unwrap(),
+            Box::new(Duration::from_secs(5400))
+        );
+        assert_eq!(
+            mapper.map("2d").downcast().unwrap(),
+            Box::new(Duration::from_secs(2 * 86400))
+        );
+    }
+
+    #[test]
+    pub fn test_mapper_date_time() {
+        fn now() -> DateTime<Utc> {
+            Utc.ymd(2020, 6, 1).and_hms(12, 0, 0)
+        }
+        let mapper = DateTimeMapper::with_clock(now);
+
+        assert_eq!(
+            mapper.map("tomorrow 9am").downcast().unwrap(),
+            Box::new(Utc.ymd(2020, 6, 2).and_hms(9, 0, 0))
+        );
+        assert_eq!(
+            mapper.map("<t:1700000000:R>").downcast().unwrap(),
+            Box::new(Utc.timestamp(1700000000, 0))
+        );
+        assert_eq!(
+            mapper.map("2020-06-01T18:00:00+02:00").downcast().unwrap(),
+            Box::new(Utc.ymd(2020, 6, 1).and_hms(16, 0, 0))
+        );
+    }
+
+    #[test]
+    pub fn test_mapper_local_date_time() {
+        let local = LocalDateTimeMapper
+            .map("tomorrow 9am")
+            .downcast::<LocalDateTime>()
+            .unwrap();
+        let now = Utc.ymd(2020, 6, 1).and_hms(12, 0, 0);
+
+        assert_eq!(
+            local.resolve(&now),
+            Some(Utc.ymd(2020, 6, 2).and_hms(9, 0, 0))
+        );
+        assert_eq!(
+            local.resolve(&now.with_timezone(&FixedOffset::west(5 * 3600))),
+            Some(Utc.ymd(2020, 6, 2).and_hms(14, 0, 0))
+        );
+        assert!(!local.is_day_only());
+
+        let day = |frag: &str| LocalDateTime(frag.to_owned()).is_day_only();
+        assert!(day("tomorrow"));
+        assert!(day("Today"));
+        assert!(day("2020-06-02"));
+        assert!(!day("9am"));
+        assert!(!day("2020-06-02T09:00"));
+    }
+
+    #[test]
+    pub fn test_mapper_bool() {
+        let mapper = BoolMapper;
+
+        assert_eq!(mapper.map("yes").downcast().unwrap(), Box::new(true));
+        assert_eq!(mapper.map("On").downcast().unwrap(), Box::new(true));
+        assert_eq!(mapper.map("no").downcast().unwrap(), Box::new(false));
+        assert_eq!(mapper.map("OFF").downcast().unwrap(), Box::new(false));
+    }
+
+    #[test]
+    pub fn test_mapper_from_str_f64() {
+        let mapper = FromStrMapper::<f64>::default();
+
+        assert_eq!(mapper.map("2.5").downcast().unwrap(), Box::new(2.5_f64));
+        assert_eq!(mapper.map("1e3").downcast().unwrap(), Box::new(1000_f64));
+    }
+
+    #[test]
+    pub fn test_mapper_choice() {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        enum Mode {
+            Add,
+            Remove,
+        }
+
+        impl Choice for Mode {
+            fn choices() -> &'static [(&'static str, Self)] {
+                &[("add", Mode::Add), ("remove", Mode::Remove), ("rm", Mode::Remove)]
+            }
+        }
+
+        let mapper = ChoiceMapper::<Mode>::default();
+
+        assert_eq!(mapper.map("add").downcast().unwrap(), Box::new(Mode::Add));
+        assert_eq!(mapper.map("REMOVE").downcast().unwrap(), Box::new(Mode::Remove));
+        assert_eq!(mapper.map("rm").downcast().unwrap(), Box::new(Mode::Remove));
+    }
+
+    #[test]
+    pub fn test_mapper_message_link() {
+        let mapper = MessageLinkMapper;
+        let link =
+            "https://discord.com/channels/81384788765712384/381889648827301889/712349587234958734";
+
+        assert_eq!(
+            mapper.map(link).downcast().unwrap(),
+            Box::new(MessageLink {
+                guild_id: Some(GuildId(81384788765712384)),
+                channel_id: ChannelId(381889648827301889),
+                message_id: MessageId(712349587234958734),
+            })
+        );
+        assert_eq!(
+            mapper
+                .map("https://discord.com/channels/@me/381889648827301889/712349587234958734")
+                .downcast()
+                .unwrap(),
+            Box::new(MessageLink {
+                guild_id: None,
+                channel_id: ChannelId(381889648827301889),
+                message_id: MessageId(712349587234958734),
+            })
+        );
+    }
+
+    #[test]
+    pub fn test_mapper_url() {
+        let mapper = UrlMapper;
+
+        let url = mapper
+            .map("https://example.com/path?q=1")
+            .downcast::<Url>()
+            .unwrap();
+        assert_eq!(url.scheme(), "https");
+        assert_eq!(url.host_str(), Some("example.com"));
+        assert_eq!(url.path(), "/path");
+    }
+
+    #[test]
+    pub fn test_mapper_colour() {
+        let mapper = ColourMapper;
+
+        assert_eq!(
+            mapper.map("#ff8800").downcast().unwrap(),
+            Box::new(Colour::new(0xff8800))
+        );
+        assert_eq!(
+            mapper.map("rgb(1, 2, 3)").downcast().unwrap(),
+            Box::new(Colour::new(0x010203))
+        );
+    }
+
+    #[test]
+    pub fn test_mapper_snowflake() {
+        let mapper = SnowflakeMapper;
+
+        let snowflake = mapper
+            .map("175928847299117063")
+            .downcast::<Snowflake>()
+            .unwrap();
+        assert_eq!(*snowflake, Snowflake(175928847299117063));
+        assert_eq!(
+            snowflake.timestamp(),
+            Utc.ymd(2016, 4, 30).and_hms_milli(11, 18, 25, 796)
+        );
+    }
 }
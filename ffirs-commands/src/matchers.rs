@@ -1,10 +1,22 @@
+use crate::error::{CmdError, CmdResult};
+use crate::mappers::{LocalDateTime, MapperOutput, MessageLink, Snowflake};
+use crate::parsers::{
+    parse_colour, parse_date_time, parse_duration, parse_message_link, parse_snowflake,
+};
+use chrono::{DateTime, Utc};
 use regex::Regex;
-use serenity::model::id::UserId;
+use serenity::model::channel::{Attachment, Message};
+use serenity::model::id::{ChannelId, UserId};
+use serenity::utils::Colour;
 use std::any::{Any, TypeId};
-use std::fmt::Debug;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::ops::{Bound, RangeBounds, RangeInclusive};
+use std::time::Duration;
+use url::Url;
 
 /// Trait used to recognize arguments and map them to a real object.
-pub trait FragMatcher: Debug + Any {
+pub trait FragMatcher: Debug + Any + Send + Sync {
     /// Check if the given token can be mapped into the output type.
     /// If this returns true, the associated mapper must not fail.
     fn matches(&self, frag: &str) -> bool;
@@ -12,35 +24,651 @@ pub trait FragMatcher: Debug + Any {
     /// `TypeId` contains in the fragment, the mapper associated with this type will be used.
     fn fragment_type_id(&self) -> TypeId;
 
+    /// Called on the fragments of a path that fully matched, before mapping.
+    /// Lets a matcher reject a well-formed fragment with an explicit error.
+    fn check(&self, _frag: &str) -> CmdResult<()> {
+        Ok(())
+    }
+
+    /// Matchers carrying their own parameters (like a regex) can map the fragment themselves.
+    /// When this returns `None` the mapper registered for `fragment_type_id` is used.
+    fn map_self(&self, _frag: &str) -> Option<MapperOutput> {
+        None
+    }
+
+    /// Pseudo-arguments don't consume a fragment, the dispatcher takes their value
+    /// from the message itself. `matches` is never called on them.
+    fn context_arg(&self) -> Option<ContextArg> {
+        None
+    }
+
+    /// How this matcher is shown in the usage of a command, `name` being its argument name.
+    fn usage(&self, name: &str) -> String {
+        format!("<{}>", name)
+    }
+
+    /// The word matched, for matchers of a single literal.
+    fn literal(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether the walker gives a fragment to this matcher or goes straight to its children.
+    fn consumes_fragment(&self) -> bool {
+        self.context_arg().is_none()
+    }
+
+    /// Whether this matcher takes every fragment left, joined by a space, instead of one.
+    /// Nothing can follow it but pseudo-arguments.
+    fn takes_rest(&self) -> bool {
+        false
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Matches a string literal perfectly.
+#[derive(Debug, Clone)]
+pub struct ExactMatcher {
+    literal: String,
+}
+
+impl ExactMatcher {
+    pub fn new(literal: String) -> Self {
+        Self { literal }
+    }
+}
+
+impl PartialEq for ExactMatcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.literal == other.literal
+    }
+}
+
+impl FragMatcher for ExactMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        self.literal == frag
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<()>()
+    }
+
+    fn usage(&self, _name: &str) -> String {
+        self.literal.clone()
+    }
+
+    fn literal(&self) -> Option<&str> {
+        Some(&self.literal)
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Groups the commands of a tree under a single root, without consuming any fragment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RootMatcher;
+
+impl FragMatcher for RootMatcher {
+    fn matches(&self, _: &str) -> bool {
+        true
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<()>()
+    }
+
+    fn usage(&self, _name: &str) -> String {
+        String::new()
+    }
+
+    fn consumes_fragment(&self) -> bool {
+        false
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Matches any single fragment, mapped to a `String`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WordMatcher;
+
+impl FragMatcher for WordMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        !frag.is_empty()
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<String>()
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Matches an unsigned number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsignedMatcher;
+
+impl FragMatcher for UnsignedMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        frag.chars().all(|c| c.is_ascii_digit()) && frag.parse::<u64>().is_ok()
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<u64>()
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Matches a signed number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignedMatcher;
+
+impl FragMatcher for SignedMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        let digits = frag.strip_prefix('-').unwrap_or(frag);
+        digits.chars().all(|c| c.is_ascii_digit()) && frag.parse::<i64>().is_ok()
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<i64>()
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Parses the id captured by a mention regex, `None` when it doesn't fit in a `u64`.
+fn mention_id(regex: &Regex, frag: &str) -> Option<u64> {
+    regex.captures(frag)?.get(1)?.as_str().parse().ok()
+}
+
+/// Matches a User mention (`<@123456789>`).
+/// Supports nicks, the whole fragment must be the mention.
+#[derive(Debug, Clone)]
+pub struct UserMentionMatcher {
+    regex: Regex,
+}
+
+impl Default for UserMentionMatcher {
+    fn default() -> Self {
+        Self {
+            regex: Regex::new(&anchored("<@!?([0-9]{1,20})>"))
+                .expect("Failed to compile user regex"),
+        }
+    }
+}
+
+impl PartialEq for UserMentionMatcher {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl FragMatcher for UserMentionMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        mention_id(&self.regex, frag).is_some()
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<UserId>()
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Matches a channel mention (`<#123456789>`), the whole fragment must be the mention.
+#[derive(Debug, Clone)]
+pub struct ChannelMentionMatcher {
+    regex: Regex,
+}
+
+impl Default for ChannelMentionMatcher {
+    fn default() -> Self {
+        Self {
+            regex: Regex::new(&anchored("<#([0-9]{1,20})>"))
+                .expect("Failed to compile channel regex"),
+        }
+    }
+}
+
+impl PartialEq for ChannelMentionMatcher {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl FragMatcher for ChannelMentionMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        mention_id(&self.regex, frag).is_some()
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<ChannelId>()
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Matches the rest of the command, mapped to a `String` of its fragments joined by a space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestMatcher;
+
+impl FragMatcher for RestMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        !frag.is_empty()
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<String>()
+    }
+
+    fn usage(&self, name: &str) -> String {
+        format!("<{}...>", name)
+    }
+
+    fn takes_rest(&self) -> bool {
+        true
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Matches a compact duration (`90s`, `1h30m`, `2d`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DurationMatcher;
+
+impl FragMatcher for DurationMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        parse_duration(frag).is_some()
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<Duration>()
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Matches a point in time: ISO-8601, Discord timestamp markup (`<t:1700000000:R>`)
+/// or a relative phrase (`tomorrow 9am`, quoted since it contains a space).
+/// Relative phrases are resolved against the matcher's clock.
+#[derive(Debug, Clone, Copy)]
+pub struct DateTimeMatcher {
+    now: fn() -> DateTime<Utc>,
+}
+
+impl DateTimeMatcher {
+    /// Use a custom clock, the one of the `DateTimeMapper` mapping the fragment.
+    pub fn with_clock(now: fn() -> DateTime<Utc>) -> Self {
+        Self { now }
+    }
+}
+
+impl Default for DateTimeMatcher {
+    fn default() -> Self {
+        Self { now: Utc::now }
+    }
+}
+
+impl FragMatcher for DateTimeMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        parse_date_time(frag, (self.now)()).is_some()
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<DateTime<Utc>>()
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Matches a point in time like `DateTimeMatcher`, mapped to a `LocalDateTime`
+/// resolved by the command in the timezone of its author.
+#[derive(Debug, Clone, Copy)]
+pub struct LocalDateTimeMatcher {
+    now: fn() -> DateTime<Utc>,
+}
+
+impl LocalDateTimeMatcher {
+    /// Use a custom clock, the one the command resolves the `LocalDateTime` with.
+    pub fn with_clock(now: fn() -> DateTime<Utc>) -> Self {
+        Self { now }
+    }
+}
+
+impl Default for LocalDateTimeMatcher {
+    fn default() -> Self {
+        Self { now: Utc::now }
+    }
+}
+
+impl FragMatcher for LocalDateTimeMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        parse_date_time(frag, (self.now)()).is_some()
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<LocalDateTime>()
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Matches a decimal number (`3.14`, `-2`, `1e3`).
+/// Infinities and NaN are refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloatMatcher;
+
+impl FragMatcher for FloatMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        frag.chars().all(|c| c.is_ascii_digit() || ".-+eE".contains(c))
+            && frag.parse::<f64>().map_or(false, f64::is_finite)
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<f64>()
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Matches a boolean: `true`/`false`, `yes`/`no` or `on`/`off`, case insensitive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoolMatcher;
+
+impl BoolMatcher {
+    pub(crate) fn parse(frag: &str) -> Option<bool> {
+        match frag.to_lowercase().as_str() {
+            "true" | "yes" | "on" => Some(true),
+            "false" | "no" | "off" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl FragMatcher for BoolMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        Self::parse(frag).is_some()
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<bool>()
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A type that can be picked by name from a fixed set, usually a field-less enum.
+pub trait Choice: Sized + Copy + Send + Sync + 'static {
+    /// Every accepted name with its associated value.
+    fn choices() -> &'static [(&'static str, Self)];
+
+    fn from_choice(frag: &str) -> Option<Self> {
+        Self::choices()
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(frag))
+            .map(|&(_, value)| value)
+    }
+}
+
+/// Matches one of a fixed set of words, case insensitive.
+/// Built with `of` it maps onto a `Choice`, otherwise the fragment is kept as a `String`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChoiceMatcher {
+    choices: Vec<String>,
+    ty: TypeId,
+}
+
+impl ChoiceMatcher {
+    pub fn new(choices: Vec<String>) -> Self {
+        Self {
+            choices,
+            ty: TypeId::of::<String>(),
+        }
+    }
+
+    pub fn of<C: Choice>() -> Self {
+        Self {
+            choices: C::choices()
+                .iter()
+                .map(|(name, _)| (*name).to_owned())
+                .collect(),
+            ty: TypeId::of::<C>(),
+        }
+    }
+
+    pub fn choices(&self) -> &[String] {
+        &self.choices
+    }
+}
+
+impl FragMatcher for ChoiceMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        self.choices.iter().any(|c| c.eq_ignore_ascii_case(frag))
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        self.ty
+    }
+
+    fn usage(&self, name: &str) -> String {
+        format!("<{}: {}>", name, self.choices.join("|"))
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Restricts an integer matcher to a range.
+/// A number outside of the range still matches so the user gets an explicit error
+/// instead of a command not found.
+///
+/// Open ends stop at the bounds of the type the inner matcher maps to.
+#[derive(Debug)]
+pub struct RangeMatcher {
+    inner: Box<dyn FragMatcher>,
+    start: Bound<i128>,
+    end: Bound<i128>,
+    limits: RangeInclusive<i128>,
+}
+
+impl RangeMatcher {
+    pub fn new<M: FragMatcher, R: RangeBounds<i128>>(inner: M, range: R) -> Self {
+        Self::new_raw(Box::new(inner), range)
+    }
+
+    pub fn new_raw<R: RangeBounds<i128>>(inner: Box<dyn FragMatcher>, range: R) -> Self {
+        fn owned(bound: Bound<&i128>) -> Bound<i128> {
+            match bound {
+                Bound::Included(&b) => Bound::Included(b),
+                Bound::Excluded(&b) => Bound::Excluded(b),
+                Bound::Unbounded => Bound::Unbounded,
+            }
+        }
+
+        Self {
+            limits: Self::type_limits(inner.fragment_type_id()),
+            inner,
+            start: owned(range.start_bound()),
+            end: owned(range.end_bound()),
+        }
+    }
+
+    /// The values the mapper of `ty` can produce.
+    fn type_limits(ty: TypeId) -> RangeInclusive<i128> {
+        if ty == TypeId::of::<u64>() {
+            0..=u64::MAX as i128
+        } else if ty == TypeId::of::<i64>() {
+            i64::MIN as i128..=i64::MAX as i128
+        } else {
+            i128::MIN..=i128::MAX
+        }
+    }
+
+    /// Parse a range written like in Rust: `1..=100`, `-5..5`, `10..`, `..=3`.
+    pub fn parse_range(range: &str) -> Option<(Bound<i128>, Bound<i128>)> {
+        let sep = range.find("..")?;
+        let (start, end) = (range[..sep].trim(), &range[sep + 2..]);
+        let (end, inclusive) = if end.starts_with('=') {
+            (end[1..].trim(), true)
+        } else {
+            (end.trim(), false)
+        };
+
+        let start = if start.is_empty() {
+            Bound::Unbounded
+        } else {
+            Bound::Included(start.parse().ok()?)
+        };
+        let end = match (end.is_empty(), inclusive) {
+            (true, false) => Bound::Unbounded,
+            (true, true) => return None,
+            (false, true) => Bound::Included(end.parse().ok()?),
+            (false, false) => Bound::Excluded(end.parse().ok()?),
+        };
+
+        Some((start, end))
+    }
+
+    fn describe_range(&self) -> String {
+        let start = match self.start {
+            Bound::Included(b) | Bound::Excluded(b) => b.to_string(),
+            Bound::Unbounded => String::new(),
+        };
+        let end = match self.end {
+            Bound::Included(b) => format!("={}", b),
+            Bound::Excluded(b) => b.to_string(),
+            Bound::Unbounded => String::new(),
+        };
+
+        format!("{}..{}", start, end)
+    }
+}
+
+impl FragMatcher for RangeMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        self.inner.matches(frag)
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        self.inner.fragment_type_id()
+    }
+
+    fn usage(&self, name: &str) -> String {
+        format!("<{}: {}>", name, self.describe_range())
+    }
+
+    fn check(&self, frag: &str) -> CmdResult<()> {
+        let in_range = frag.parse::<i128>().map_or(false, |value| {
+            (self.start, self.end).contains(&value) && self.limits.contains(&value)
+        });
+
+        if in_range {
+            Ok(())
+        } else {
+            Err(CmdError::OutOfRange {
+                value: frag.into(),
+                range: self.describe_range(),
+            })
+        }
+    }
+
     #[cfg(test)]
-    fn as_any(&self) -> &dyn Any;
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
-/// Matches a string literal perfectly.
-#[derive(Debug, Clone)]
-pub struct ExactMatcher {
-    literal: String,
+/// Matches a Discord message link (`https://discord.com/channels/<guild>/<channel>/<message>`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageLinkMatcher;
+
+impl FragMatcher for MessageLinkMatcher {
+    fn matches(&self, frag: &str) -> bool {
+        parse_message_link(frag).is_some()
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<MessageLink>()
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
-impl ExactMatcher {
-    pub fn new(literal: String) -> Self {
-        Self { literal }
+/// Matches an absolute URL whose scheme is in an allowed list, `http` and `https` by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlMatcher {
+    schemes: Vec<String>,
+}
+
+impl UrlMatcher {
+    pub fn with_schemes(schemes: &[&str]) -> Self {
+        Self {
+            schemes: schemes.iter().map(|&scheme| scheme.to_lowercase()).collect(),
+        }
     }
 }
 
-impl PartialEq for ExactMatcher {
-    fn eq(&self, other: &Self) -> bool {
-        self.literal == other.literal
+impl Default for UrlMatcher {
+    fn default() -> Self {
+        Self::with_schemes(&["http", "https"])
     }
 }
 
-impl FragMatcher for ExactMatcher {
+impl FragMatcher for UrlMatcher {
     fn matches(&self, frag: &str) -> bool {
-        self.literal == frag
+        Url::parse(frag).map_or(false, |url| {
+            self.schemes.iter().any(|scheme| scheme == url.scheme())
+        })
     }
 
     fn fragment_type_id(&self) -> TypeId {
-        TypeId::of::<()>()
+        TypeId::of::<Url>()
     }
 
     #[cfg(test)]
@@ -49,17 +677,17 @@ impl FragMatcher for ExactMatcher {
     }
 }
 
-/// Matches an unsigned number.
+/// Matches a colour: `#ff8800`, `#f80`, `0xff8800` or `rgb(255, 136, 0)` (quoted if spaced).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct UnsignedMatcher;
+pub struct ColourMatcher;
 
-impl FragMatcher for UnsignedMatcher {
+impl FragMatcher for ColourMatcher {
     fn matches(&self, frag: &str) -> bool {
-        !frag.is_empty() && frag.chars().all(|c| c.is_numeric())
+        parse_colour(frag).is_some()
     }
 
     fn fragment_type_id(&self) -> TypeId {
-        TypeId::of::<u64>()
+        TypeId::of::<Colour>()
     }
 
     #[cfg(test)]
@@ -68,22 +696,17 @@ impl FragMatcher for UnsignedMatcher {
     }
 }
 
-/// Matches a signed number.
+/// Matches a raw Discord id (`81384788765712384`).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct SignedMatcher;
+pub struct SnowflakeMatcher;
 
-impl FragMatcher for SignedMatcher {
+impl FragMatcher for SnowflakeMatcher {
     fn matches(&self, frag: &str) -> bool {
-        if frag.is_empty() {
-            false
-        } else {
-            let first = frag.chars().next().unwrap();
-            (first.is_numeric() || first == '-') && frag.chars().skip(1).all(|c| c.is_numeric())
-        }
+        parse_snowflake(frag).is_some()
     }
 
     fn fragment_type_id(&self) -> TypeId {
-        TypeId::of::<i64>()
+        TypeId::of::<Snowflake>()
     }
 
     #[cfg(test)]
@@ -92,34 +715,192 @@ impl FragMatcher for SignedMatcher {
     }
 }
 
-/// Matches a User mention (`<@123456789>`).
-/// Supports nicks.
+/// Wrap a pattern so it has to match the whole fragment.
+/// Every regex-backed matcher goes through this, a fragment is never matched partially.
+pub(crate) fn anchored(pattern: &str) -> String {
+    format!("^(?:{})$", pattern)
+}
+
+/// Capture groups extracted by a `RegexMatcher`.
+/// Group 0 is always the whole fragment.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RegexCaptures {
+    groups: Vec<Option<String>>,
+    named: HashMap<String, String>,
+}
+
+impl RegexCaptures {
+    fn from_regex(regex: &Regex, frag: &str) -> Option<Self> {
+        let captures = regex.captures(frag)?;
+
+        let groups = captures
+            .iter()
+            .map(|group| group.map(|m| m.as_str().to_owned()))
+            .collect();
+        let named = regex
+            .capture_names()
+            .flatten()
+            .filter_map(|name| Some((name.to_owned(), captures.name(name)?.as_str().to_owned())))
+            .collect();
+
+        Some(Self { groups, named })
+    }
+
+    /// Get a group by index, `None` if it didn't participate in the match.
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.groups.get(index)?.as_deref()
+    }
+
+    /// Get a named group, `None` if it didn't participate in the match.
+    pub fn name(&self, name: &str) -> Option<&str> {
+        self.named.get(name).map(String::as_str)
+    }
+
+    /// Number of groups, including the implicit group 0.
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+/// Types that can be built from the capture groups of a `RegexMatcher`.
+pub trait FromCaptures: Sized + Send + Sync + 'static {
+    /// Returning `None` makes the fragment not match.
+    fn from_captures(captures: RegexCaptures) -> Option<Self>;
+}
+
+impl FromCaptures for RegexCaptures {
+    fn from_captures(captures: RegexCaptures) -> Option<Self> {
+        Some(captures)
+    }
+}
+
+/// Matches a user provided regex against the whole fragment.
+/// The fragment is mapped by the matcher itself, to `RegexCaptures` or to any `FromCaptures`.
 #[derive(Debug, Clone)]
-pub struct UserMentionMatcher {
+pub struct RegexMatcher {
     regex: Regex,
+    ty: TypeId,
+    convert: fn(RegexCaptures) -> Option<MapperOutput>,
 }
 
-impl Default for UserMentionMatcher {
-    fn default() -> Self {
-        Self {
-            regex: Regex::new("<@!?[0-9]+>").expect("Failed to compile user regex"),
+impl RegexMatcher {
+    /// The pattern is anchored on both ends, `[A-Z]+` won't match `ab-CD`.
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Self::typed::<RegexCaptures>(pattern)
+    }
+
+    /// Like `new` but maps the captures to `T`.
+    pub fn typed<T: FromCaptures>(pattern: &str) -> Result<Self, regex::Error> {
+        fn convert<T: FromCaptures>(captures: RegexCaptures) -> Option<MapperOutput> {
+            T::from_captures(captures).map(|value| Box::new(value) as MapperOutput)
         }
+
+        Ok(Self {
+            regex: Regex::new(&anchored(pattern))?,
+            ty: TypeId::of::<T>(),
+            convert: convert::<T>,
+        })
+    }
+
+    fn captures(&self, frag: &str) -> Option<MapperOutput> {
+        (self.convert)(RegexCaptures::from_regex(&self.regex, frag)?)
     }
 }
 
-impl PartialEq for UserMentionMatcher {
-    fn eq(&self, _: &Self) -> bool {
-        true
+impl PartialEq for RegexMatcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.regex.as_str() == other.regex.as_str() && self.ty == other.ty
     }
 }
 
-impl FragMatcher for UserMentionMatcher {
+impl FragMatcher for RegexMatcher {
     fn matches(&self, frag: &str) -> bool {
-        self.regex.is_match(frag)
+        self.captures(frag).is_some()
     }
 
     fn fragment_type_id(&self) -> TypeId {
-        TypeId::of::<UserId>()
+        self.ty
+    }
+
+    fn map_self(&self, frag: &str) -> Option<MapperOutput> {
+        self.captures(frag)
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Where a pseudo-argument takes its value from, instead of a fragment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextArg {
+    /// A file uploaded with the message, in upload order.
+    Attachment,
+    /// The message being replied to.
+    RepliedMessage,
+}
+
+impl Display for ContextArg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ContextArg::Attachment => write!(f, "attachment"),
+            ContextArg::RepliedMessage => write!(f, "replied message"),
+        }
+    }
+}
+
+/// Pseudo-argument taking the next file attached to the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentMatcher;
+
+impl FragMatcher for AttachmentMatcher {
+    fn matches(&self, _: &str) -> bool {
+        true
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<Attachment>()
+    }
+
+    /// Square brackets as it isn't typed but taken from the message.
+    fn usage(&self, name: &str) -> String {
+        format!("[{}]", name)
+    }
+
+    fn context_arg(&self) -> Option<ContextArg> {
+        Some(ContextArg::Attachment)
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Pseudo-argument taking the message that was replied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepliedMessageMatcher;
+
+impl FragMatcher for RepliedMessageMatcher {
+    fn matches(&self, _: &str) -> bool {
+        true
+    }
+
+    fn fragment_type_id(&self) -> TypeId {
+        TypeId::of::<Message>()
+    }
+
+    fn usage(&self, name: &str) -> String {
+        format!("[{}]", name)
+    }
+
+    fn context_arg(&self) -> Option<ContextArg> {
+        Some(ContextArg::RepliedMessage)
     }
 
     #[cfg(test)]
@@ -130,9 +911,29 @@ impl FragMatcher for UserMentionMatcher {
 
 #[cfg(test)]
 mod tests {
+    use crate::error::CmdError;
     use crate::matchers::{
-        ExactMatcher, FragMatcher, SignedMatcher, UnsignedMatcher, UserMentionMatcher,
+        BoolMatcher, ChannelMentionMatcher, Choice, ChoiceMatcher, ColourMatcher, DateTimeMatcher,
+        DurationMatcher, ExactMatcher, FloatMatcher, FragMatcher, FromCaptures,
+        LocalDateTimeMatcher, MessageLinkMatcher, RangeMatcher, RegexCaptures, RegexMatcher,
+        RestMatcher, SignedMatcher, SnowflakeMatcher, UnsignedMatcher, UrlMatcher,
+        UserMentionMatcher, WordMatcher,
     };
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::any::TypeId;
+    use std::ops::Bound;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Speed {
+        Slow,
+        Fast,
+    }
+
+    impl Choice for Speed {
+        fn choices() -> &'static [(&'static str, Self)] {
+            &[("slow", Speed::Slow), ("fast", Speed::Fast)]
+        }
+    }
 
     #[test]
     pub fn test_matcher_exact() {
@@ -143,6 +944,24 @@ mod tests {
         assert!(!matcher.matches("nope"));
     }
 
+    #[test]
+    pub fn test_matcher_word() {
+        assert!(WordMatcher.matches("!"));
+        assert!(WordMatcher.matches("two words"));
+        assert!(!WordMatcher.matches(""));
+        assert_eq!(WordMatcher.fragment_type_id(), TypeId::of::<String>());
+    }
+
+    #[test]
+    pub fn test_matcher_rest() {
+        assert!(RestMatcher.matches("buy some milk"));
+        assert!(!RestMatcher.matches(""));
+        assert!(RestMatcher.takes_rest());
+        assert!(!WordMatcher.takes_rest());
+        assert_eq!(RestMatcher.usage("text"), "<text...>");
+        assert_eq!(RestMatcher.fragment_type_id(), TypeId::of::<String>());
+    }
+
     #[test]
     pub fn test_matcher_exact2() {
         let matcher = ExactMatcher::new(String::from("12hey"));
@@ -164,6 +983,26 @@ mod tests {
         assert!(!matcher.matches("<@123"));
         assert!(!matcher.matches("<123>"));
         assert!(!matcher.matches("123"));
+        assert!(!matcher.matches("xx<@1>yy"));
+        assert!(!matcher.matches("<@1><@2>"));
+        assert!(matcher.matches("<@18446744073709551615>"));
+        assert!(!matcher.matches("<@18446744073709551616>"));
+        assert!(!matcher.matches("<@!123456789012345678901>"));
+    }
+
+    #[test]
+    pub fn test_matcher_channel_mention() {
+        let matcher = ChannelMentionMatcher::default();
+
+        assert!(matcher.matches("<#123>"));
+
+        assert!(!matcher.matches("<#abc>"));
+        assert!(!matcher.matches("<@123>"));
+        assert!(!matcher.matches("#general"));
+        assert!(!matcher.matches("123"));
+        assert!(matcher.matches("<#18446744073709551615>"));
+        assert!(!matcher.matches("<#99999999999999999999>"));
+        assert!(!matcher.matches("<#123456789012345678901>"));
     }
 
     #[test]
@@ -175,6 +1014,12 @@ mod tests {
         assert!(!matcher.matches("-12a"));
         assert!(!matcher.matches("a"));
         assert!(!matcher.matches("a12"));
+        assert!(!matcher.matches("-"));
+        assert!(!matcher.matches("+12"));
+        assert!(!matcher.matches("--12"));
+        assert!(!matcher.matches("١٢"));
+        assert!(matcher.matches("-9223372036854775808"));
+        assert!(!matcher.matches("9223372036854775808"));
     }
 
     #[test]
@@ -186,5 +1031,275 @@ mod tests {
         assert!(!matcher.matches("-12a"));
         assert!(!matcher.matches("a"));
         assert!(!matcher.matches("a12"));
+        assert!(!matcher.matches(""));
+        assert!(!matcher.matches("+12"));
+        assert!(!matcher.matches("١٢"));
+        assert!(matcher.matches("18446744073709551615"));
+        assert!(!matcher.matches("99999999999999999999"));
+    }
+
+    #[test]
+    pub fn test_matcher_duration() {
+        let matcher = DurationMatcher;
+
+        assert!(matcher.matches("90s"));
+        assert!(matcher.matches("1h30m"));
+        assert!(matcher.matches("2d"));
+        assert!(!matcher.matches("90"));
+        assert!(!matcher.matches("1h 30m"));
+        assert!(!matcher.matches("soon"));
+    }
+
+    #[test]
+    pub fn test_matcher_date_time() {
+        let matcher = DateTimeMatcher::default();
+
+        assert!(matcher.matches("2020-06-01T18:00:00Z"));
+        assert!(matcher.matches("<t:1700000000:R>"));
+        assert!(matcher.matches("tomorrow 9am"));
+        assert!(matcher.matches("18:00"));
+        assert!(!matcher.matches("tomorrow morning"));
+        assert!(!matcher.matches("<t:1700000000:X>"));
+        assert!(!matcher.matches("42"));
+    }
+
+    #[test]
+    pub fn test_matcher_date_time_clock() {
+        // The last day chrono can represent has no tomorrow
+        fn now() -> DateTime<Utc> {
+            Utc.ymd(262143, 12, 31).and_hms(12, 0, 0)
+        }
+
+        for matcher in [
+            Box::new(DateTimeMatcher::with_clock(now)) as Box<dyn FragMatcher>,
+            Box::new(LocalDateTimeMatcher::with_clock(now)),
+        ]
+        .iter()
+        {
+            assert!(matcher.matches("today 9am"));
+            assert!(!matcher.matches("tomorrow"));
+            assert!(!matcher.matches("in 2d"));
+        }
+        assert!(DateTimeMatcher::default().matches("tomorrow"));
+    }
+
+    #[test]
+    pub fn test_matcher_float() {
+        let matcher = FloatMatcher;
+
+        assert!(matcher.matches("3.14"));
+        assert!(matcher.matches("-2"));
+        assert!(matcher.matches("1e3"));
+        assert!(matcher.matches("2.5E-3"));
+        assert!(!matcher.matches(""));
+        assert!(!matcher.matches("inf"));
+        assert!(!matcher.matches("NaN"));
+        assert!(!matcher.matches("1e999"));
+        assert!(!matcher.matches("3,14"));
+        assert!(!matcher.matches("1.2.3"));
+    }
+
+    #[test]
+    pub fn test_matcher_bool() {
+        let matcher = BoolMatcher;
+
+        for frag in &["true", "false", "yes", "no", "on", "off", "Yes", "OFF"] {
+            assert!(matcher.matches(frag));
+        }
+        assert!(!matcher.matches("1"));
+        assert!(!matcher.matches("y"));
+        assert!(!matcher.matches("maybe"));
+    }
+
+    #[test]
+    pub fn test_matcher_choice() {
+        let matcher = ChoiceMatcher::new(vec!["red".into(), "blue".into()]);
+
+        assert!(matcher.matches("red"));
+        assert!(matcher.matches("BLUE"));
+        assert!(!matcher.matches("green"));
+        assert!(!matcher.matches("re"));
+
+        let matcher = ChoiceMatcher::of::<Speed>();
+        assert_eq!(matcher.choices(), &["slow", "fast"]);
+        assert!(matcher.matches("Fast"));
+        assert!(!matcher.matches("medium"));
+        assert_eq!(Speed::from_choice("FAST"), Some(Speed::Fast));
+    }
+
+    #[test]
+    pub fn test_matcher_range_parse() {
+        assert_eq!(
+            RangeMatcher::parse_range("1..=100"),
+            Some((Bound::Included(1), Bound::Included(100)))
+        );
+        assert_eq!(
+            RangeMatcher::parse_range("-5..5"),
+            Some((Bound::Included(-5), Bound::Excluded(5)))
+        );
+        assert_eq!(
+            RangeMatcher::parse_range("10.."),
+            Some((Bound::Included(10), Bound::Unbounded))
+        );
+        assert_eq!(
+            RangeMatcher::parse_range("..=3"),
+            Some((Bound::Unbounded, Bound::Included(3)))
+        );
+
+        assert_eq!(RangeMatcher::parse_range("1-100"), None);
+        assert_eq!(RangeMatcher::parse_range("a..b"), None);
+        assert_eq!(RangeMatcher::parse_range("1..="), None);
+    }
+
+    #[test]
+    pub fn test_matcher_range_check() {
+        let matcher = RangeMatcher::new(UnsignedMatcher, 1..=100);
+
+        assert!(matcher.matches("500"));
+        assert!(!matcher.matches("-5"));
+
+        assert!(matcher.check("1").is_ok());
+        assert!(matcher.check("100").is_ok());
+        match matcher.check("500") {
+            Err(CmdError::OutOfRange { value, range }) => {
+                assert_eq!(value, "500");
+                assert_eq!(range, "1..=100");
+            }
+            _ => unreachable!(),
+        }
+        assert!(matcher.check("0").is_err());
+
+        let matcher = RangeMatcher::new(SignedMatcher, -5..5);
+        assert!(matcher.check("-5").is_ok());
+        assert!(matcher.check("5").is_err());
+
+        // The mapper can't overflow
+        let matcher = RangeMatcher::new(UnsignedMatcher, 10..);
+        assert!(matcher.check("18446744073709551615").is_ok());
+        assert!(matcher.check("18446744073709551616").is_err());
+        let matcher = RangeMatcher::new(SignedMatcher, ..0);
+        assert!(matcher.check("-9223372036854775808").is_ok());
+        assert!(matcher.check("-9223372036854775809").is_err());
+    }
+
+    #[test]
+    pub fn test_matcher_regex_anchored() {
+        let matcher = RegexMatcher::new("[A-Z]+-\\d+").unwrap();
+
+        assert!(matcher.matches("FFI-42"));
+        assert!(!matcher.matches("xFFI-42"));
+        assert!(!matcher.matches("FFI-42x"));
+        assert!(!matcher.matches("ffi-42"));
+
+        // Alternations are anchored as a whole
+        let matcher = RegexMatcher::new("a|b").unwrap();
+        assert!(matcher.matches("a"));
+        assert!(!matcher.matches("ab"));
+        assert!(!matcher.matches("xb"));
+    }
+
+    #[test]
+    pub fn test_matcher_regex_captures() {
+        let matcher = RegexMatcher::new("(?P<project>[A-Z]+)-(\\d+)(-draft)?").unwrap();
+
+        let captures = matcher
+            .map_self("FFI-42")
+            .unwrap()
+            .downcast::<RegexCaptures>()
+            .unwrap();
+        assert_eq!(captures.len(), 4);
+        assert_eq!(captures.get(0), Some("FFI-42"));
+        assert_eq!(captures.get(1), Some("FFI"));
+        assert_eq!(captures.get(2), Some("42"));
+        assert_eq!(captures.get(3), None);
+        assert_eq!(captures.get(4), None);
+        assert_eq!(captures.name("project"), Some("FFI"));
+        assert_eq!(captures.name("nope"), None);
+
+        assert!(matcher.map_self("nope").is_none());
+    }
+
+    #[test]
+    pub fn test_matcher_regex_typed() {
+        #[derive(Debug, PartialEq)]
+        struct Ticket {
+            project: String,
+            number: u32,
+        }
+
+        impl FromCaptures for Ticket {
+            fn from_captures(captures: RegexCaptures) -> Option<Self> {
+                Some(Ticket {
+                    project: captures.get(1)?.to_owned(),
+                    number: captures.get(2)?.parse().ok()?,
+                })
+            }
+        }
+
+        let matcher = RegexMatcher::typed::<Ticket>("([A-Z]+)-(\\d+)").unwrap();
+
+        assert_eq!(
+            matcher.map_self("FFI-42").unwrap().downcast::<Ticket>().unwrap(),
+            Box::new(Ticket {
+                project: "FFI".into(),
+                number: 42
+            })
+        );
+        // Matches the regex but the conversion fails
+        assert!(!matcher.matches("FFI-99999999999"));
+    }
+
+    #[test]
+    pub fn test_matcher_regex_invalid() {
+        assert!(RegexMatcher::new("(unclosed").is_err());
+    }
+
+    #[test]
+    pub fn test_matcher_message_link() {
+        let matcher = MessageLinkMatcher;
+
+        assert!(matcher.matches(
+            "https://discord.com/channels/81384788765712384/381889648827301889/712349587234958734"
+        ));
+        assert!(matcher.matches(
+            "https://discordapp.com/channels/@me/381889648827301889/712349587234958734"
+        ));
+        assert!(!matcher.matches("https://discord.com/channels/1/2/3"));
+        assert!(!matcher.matches("https://example.com"));
+    }
+
+    #[test]
+    pub fn test_matcher_url() {
+        let matcher = UrlMatcher::default();
+
+        assert!(matcher.matches("https://example.com/a?b=c"));
+        assert!(matcher.matches("http://localhost:8080"));
+        assert!(!matcher.matches("ftp://example.com"));
+        assert!(!matcher.matches("javascript:alert(1)"));
+        assert!(!matcher.matches("example.com"));
+
+        let matcher = UrlMatcher::with_schemes(&["FTP"]);
+        assert!(matcher.matches("ftp://example.com"));
+        assert!(!matcher.matches("https://example.com"));
+    }
+
+    #[test]
+    pub fn test_matcher_colour() {
+        let matcher = ColourMatcher;
+
+        assert!(matcher.matches("#ff8800"));
+        assert!(matcher.matches("#F80"));
+        assert!(matcher.matches("rgb(1,2,3)"));
+        assert!(!matcher.matches("orange"));
+        assert!(!matcher.matches("rgb(1,2,300)"));
+    }
+
+    #[test]
+    pub fn test_matcher_snowflake() {
+        let matcher = SnowflakeMatcher;
+
+        assert!(matcher.matches("81384788765712384"));
+        assert!(!matcher.matches("42"));
+        assert!(!matcher.matches("<@81384788765712384>"));
     }
 }
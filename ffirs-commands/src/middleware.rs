@@ -0,0 +1,53 @@
+use crate::cmd_args::CmdArgs;
+use crate::cmd_walker::CmdPath;
+use crate::context::CmdContext;
+use crate::error::{CmdError, CmdResult};
+use serenity::async_trait;
+use std::sync::Arc;
+
+/// A command being dispatched, as seen by middlewares.
+pub struct Invocation<'a> {
+    pub ctx: &'a dyn CmdContext,
+    /// The message content without its prefix.
+    pub raw: &'a str,
+    /// Leaf first, empty if no command matched `raw`.
+    pub path: CmdPath<'a>,
+    /// Empty until the arguments are mapped, after `before`.
+    /// Values taken by the handler are gone afterwards.
+    pub args: CmdArgs,
+}
+
+/// Hooks run around every dispatch by the `CmdManager`.
+///
+/// `before` runs in the order middlewares were added, once the path is found
+/// and before the arguments are mapped.
+/// `after` and `on_error` run in the reverse order.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    /// Returning an error vetoes the command, `on_error` is then called with it.
+    async fn before(&self, _invocation: &Invocation<'_>) -> CmdResult<()> {
+        Ok(())
+    }
+
+    /// Called when the handler succeeded.
+    async fn after(&self, _invocation: &Invocation<'_>) {}
+
+    /// Called with any error of the dispatch, including commands not found.
+    async fn on_error(&self, _invocation: &Invocation<'_>, _error: &CmdError) {}
+}
+
+/// Lets a middleware be shared with the rest of the bot.
+#[async_trait]
+impl<M: Middleware + ?Sized> Middleware for Arc<M> {
+    async fn before(&self, invocation: &Invocation<'_>) -> CmdResult<()> {
+        (**self).before(invocation).await
+    }
+
+    async fn after(&self, invocation: &Invocation<'_>) {
+        (**self).after(invocation).await
+    }
+
+    async fn on_error(&self, invocation: &Invocation<'_>, error: &CmdError) {
+        (**self).on_error(invocation, error).await
+    }
+}
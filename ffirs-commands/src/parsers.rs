@@ -0,0 +1,537 @@
+//! Parsing routines shared by matchers and mappers.
+//! A matcher only checks that the parser succeeds, its mapper then unwraps the same result.
+
+use chrono::{
+    DateTime, Duration as ChronoDuration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+};
+use std::time::Duration;
+
+/// Parse a compact duration like `90s`, `1h30m` or `2d`.
+/// A bare number is refused because its unit would be ambiguous.
+pub(crate) fn parse_duration(frag: &str) -> Option<Duration> {
+    if frag.is_empty() {
+        return None;
+    }
+
+    let mut total = 0_u64;
+    let mut remaining = frag;
+
+    while !remaining.is_empty() {
+        let digits = remaining.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        let amount = remaining[..digits].parse::<u64>().ok()?;
+        remaining = &remaining[digits..];
+
+        let unit_len = remaining
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .count();
+        let unit = match &remaining[..unit_len] {
+            "s" | "sec" | "secs" => 1,
+            "m" | "min" | "mins" => 60,
+            "h" | "hr" | "hrs" => 60 * 60,
+            "d" => 60 * 60 * 24,
+            "w" => 60 * 60 * 24 * 7,
+            _ => return None,
+        };
+        remaining = &remaining[unit_len..];
+
+        total = total.checked_add(amount.checked_mul(unit)?)?;
+    }
+
+    Some(Duration::from_secs(total))
+}
+
+/// Parse a point in time, relative to `now` when needed.
+///
+/// Supported forms:
+/// - Discord timestamp markup: `<t:1700000000>`, `<t:1700000000:R>`
+/// - ISO-8601: `2020-06-01T18:00:00+02:00`, `2020-06-01T18:00`, `2020-06-01 18:00`, `2020-06-01`
+/// - Relative phrases: `now`, `in 2h`, `today`, `tomorrow`, `9am`, `tomorrow 9am`, `today 18:30`
+///
+/// Times without an offset are in UTC.
+/// Phrases containing spaces must be quoted to form a single fragment.
+pub(crate) fn parse_date_time(frag: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    parse_date_time_in(frag, &now)
+}
+
+/// Same as `parse_date_time`, times without an offset being in the timezone of `now`.
+pub(crate) fn parse_date_time_in<Tz: TimeZone>(
+    frag: &str,
+    now: &DateTime<Tz>,
+) -> Option<DateTime<Utc>> {
+    let frag = frag.trim();
+
+    parse_discord_timestamp(frag)
+        .or_else(|| parse_iso_8601(frag, &now.timezone()))
+        .or_else(|| parse_relative(frag, now))
+}
+
+/// `naive` in `tz`, the earliest when clocks go back and `None` when they skip it.
+fn resolve_local<Tz: TimeZone>(naive: NaiveDateTime, tz: &Tz) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .map(|date_time| date_time.with_timezone(&Utc))
+}
+
+fn parse_discord_timestamp(frag: &str) -> Option<DateTime<Utc>> {
+    if !frag.starts_with("<t:") || !frag.ends_with('>') {
+        return None;
+    }
+
+    let inner = &frag[3..frag.len() - 1];
+    let mut parts = inner.splitn(2, ':');
+    let seconds = parts.next()?;
+
+    if let Some(style) = parts.next() {
+        if !matches!(style, "t" | "T" | "d" | "D" | "f" | "F" | "R") {
+            return None;
+        }
+    }
+
+    Utc.timestamp_opt(seconds.parse().ok()?, 0).single()
+}
+
+fn parse_iso_8601<Tz: TimeZone>(frag: &str, tz: &Tz) -> Option<DateTime<Utc>> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(frag) {
+        return Some(date_time.with_timezone(&Utc));
+    }
+
+    const NAIVE_FORMATS: [&str; 4] = [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ];
+    for format in NAIVE_FORMATS.iter() {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(frag, format) {
+            return resolve_local(naive, tz);
+        }
+    }
+
+    NaiveDate::parse_from_str(frag, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| resolve_local(date.and_hms(0, 0, 0), tz))
+}
+
+fn parse_relative<Tz: TimeZone>(frag: &str, now: &DateTime<Tz>) -> Option<DateTime<Utc>> {
+    let lower = frag.to_lowercase();
+    let mut words = lower.split_whitespace();
+
+    let first = words.next()?;
+    let second = words.next();
+    if words.next().is_some() {
+        return None;
+    }
+
+    let tz = now.timezone();
+    let local = now.naive_local();
+    let now = now.with_timezone(&Utc);
+    let today = local.date();
+    let day = match first {
+        "now" if second.is_none() => return Some(now),
+        "in" => {
+            let duration = ChronoDuration::from_std(parse_duration(second?)?).ok()?;
+            return now.checked_add_signed(duration);
+        }
+        "today" => Some(today),
+        "tomorrow" => Some(today.succ_opt()?),
+        _ => None,
+    };
+
+    match (day, second) {
+        // A day without time keeps the current time of day
+        (Some(day), None) => resolve_local(day.and_time(local.time()), &tz),
+        (Some(day), Some(time)) => resolve_local(day.and_time(parse_time_of_day(time)?), &tz),
+        // A lone time of day is its next occurrence
+        (None, None) => {
+            let time = parse_time_of_day(first)?;
+            match resolve_local(today.and_time(time), &tz) {
+                Some(candidate) if candidate > now => Some(candidate),
+                _ => resolve_local(today.succ_opt()?.and_time(time), &tz),
+            }
+        }
+        (None, Some(_)) => None,
+    }
+}
+
+/// Whether `frag` is a day without a time of day, like `tomorrow` or `2020-06-01`.
+pub(crate) fn is_day_only(frag: &str) -> bool {
+    let frag = frag.trim();
+
+    matches!(frag.to_lowercase().as_str(), "today" | "tomorrow")
+        || NaiveDate::parse_from_str(frag, "%Y-%m-%d").is_ok()
+}
+
+/// Parse `9am`, `9:30pm`, `18:00` or `18:00:30`.
+/// A bare hour is refused, it needs either a meridiem or minutes.
+fn parse_time_of_day(frag: &str) -> Option<NaiveTime> {
+    let (clock, meridiem) = if frag.ends_with("am") || frag.ends_with("pm") {
+        frag.split_at(frag.len() - 2)
+    } else {
+        (frag, "")
+    };
+
+    if clock.is_empty() || !clock.chars().all(|c| c.is_ascii_digit() || c == ':') {
+        return None;
+    }
+
+    let mut parts = clock.split(':');
+    let hour = parts.next()?.parse::<u32>().ok()?;
+    let minute = parts.next().map(|m| m.parse::<u32>().ok()).unwrap_or(Some(0))?;
+    let second = parts.next().map(|s| s.parse::<u32>().ok()).unwrap_or(Some(0))?;
+    if parts.next().is_some() || (meridiem.is_empty() && !clock.contains(':')) {
+        return None;
+    }
+
+    let hour = match meridiem {
+        "am" if (1..=12).contains(&hour) => hour % 12,
+        "pm" if (1..=12).contains(&hour) => hour % 12 + 12,
+        "" => hour,
+        _ => return None,
+    };
+
+    NaiveTime::from_hms_opt(hour, minute, second)
+}
+
+/// Parse a Discord message link into its guild (`None` in DMs), channel and message ids.
+pub(crate) fn parse_message_link(frag: &str) -> Option<(Option<u64>, u64, u64)> {
+    const HOSTS: [&str; 6] = [
+        "discord.com",
+        "ptb.discord.com",
+        "canary.discord.com",
+        "discordapp.com",
+        "ptb.discordapp.com",
+        "canary.discordapp.com",
+    ];
+
+    let rest = frag
+        .strip_prefix("https://")
+        .or_else(|| frag.strip_prefix("http://"))?;
+    let mut parts = rest.split('/');
+
+    if !HOSTS.contains(&parts.next()?) || parts.next()? != "channels" {
+        return None;
+    }
+
+    let guild = match parts.next()? {
+        "@me" => None,
+        guild => Some(parse_snowflake(guild)?),
+    };
+    let channel = parse_snowflake(parts.next()?)?;
+    let message = parse_snowflake(parts.next()?)?;
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some((guild, channel, message))
+}
+
+/// Parse a raw Discord id, between 17 and 20 digits.
+pub(crate) fn parse_snowflake(frag: &str) -> Option<u64> {
+    if (17..=20).contains(&frag.len()) && frag.chars().all(|c| c.is_ascii_digit()) {
+        frag.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Parse a colour: `#ff8800`, `#f80`, `0xff8800` or `rgb(255, 136, 0)`.
+pub(crate) fn parse_colour(frag: &str) -> Option<u32> {
+    let lower = frag.trim().to_lowercase();
+
+    if let Some(hex) = lower
+        .strip_prefix('#')
+        .or_else(|| lower.strip_prefix("0x"))
+    {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        return match hex.len() {
+            6 => u32::from_str_radix(hex, 16).ok(),
+            // Short form, every digit is doubled: `#f80` is `#ff8800`
+            3 => hex
+                .chars()
+                .map(|c| c.to_digit(16))
+                .try_fold(0, |acc, digit| Some((acc << 8) | digit? * 0x11)),
+            _ => None,
+        };
+    }
+
+    let inner = lower.strip_prefix("rgb(")?.strip_suffix(')')?;
+    let channels = inner
+        .split(',')
+        .map(|channel| channel.trim().parse::<u8>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    match channels.as_slice() {
+        &[r, g, b] => Some((u32::from(r) << 16) | (u32::from(g) << 8) | u32::from(b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsers::{
+        parse_colour, parse_date_time, parse_date_time_in, parse_duration, parse_message_link,
+        parse_snowflake, parse_time_of_day,
+    };
+    use chrono::{DateTime, FixedOffset, NaiveTime, TimeZone, Utc};
+    use std::time::Duration;
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2020, 6, 1).and_hms(12, 0, 0)
+    }
+
+    #[test]
+    pub fn test_parse_duration_simple() {
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(5 * 60)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(2 * 3600)));
+        assert_eq!(parse_duration("2d"), Some(Duration::from_secs(2 * 86400)));
+        assert_eq!(parse_duration("1w"), Some(Duration::from_secs(7 * 86400)));
+        assert_eq!(parse_duration("10min"), Some(Duration::from_secs(600)));
+        assert_eq!(parse_duration("0s"), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    pub fn test_parse_duration_compound() {
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+        assert_eq!(
+            parse_duration("1d2h3m4s"),
+            Some(Duration::from_secs(86400 + 2 * 3600 + 3 * 60 + 4))
+        );
+        assert_eq!(parse_duration("30m1h"), Some(Duration::from_secs(5400)));
+    }
+
+    #[test]
+    pub fn test_parse_duration_invalid() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("90"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("1x"), None);
+        assert_eq!(parse_duration("-1h"), None);
+        assert_eq!(parse_duration("1h 30m"), None);
+        assert_eq!(parse_duration("1.5h"), None);
+        assert_eq!(parse_duration("h1"), None);
+        assert_eq!(parse_duration("99999999999999999999s"), None);
+        assert_eq!(parse_duration("9999999999999999w"), None);
+    }
+
+    #[test]
+    pub fn test_parse_time_of_day() {
+        assert_eq!(parse_time_of_day("9am"), Some(NaiveTime::from_hms(9, 0, 0)));
+        assert_eq!(parse_time_of_day("12am"), Some(NaiveTime::from_hms(0, 0, 0)));
+        assert_eq!(parse_time_of_day("12pm"), Some(NaiveTime::from_hms(12, 0, 0)));
+        assert_eq!(parse_time_of_day("9:30pm"), Some(NaiveTime::from_hms(21, 30, 0)));
+        assert_eq!(parse_time_of_day("18:00"), Some(NaiveTime::from_hms(18, 0, 0)));
+        assert_eq!(parse_time_of_day("18:00:30"), Some(NaiveTime::from_hms(18, 0, 30)));
+
+        assert_eq!(parse_time_of_day("18"), None);
+        assert_eq!(parse_time_of_day("13pm"), None);
+        assert_eq!(parse_time_of_day("0am"), None);
+        assert_eq!(parse_time_of_day("25:00"), None);
+        assert_eq!(parse_time_of_day("12:60"), None);
+        assert_eq!(parse_time_of_day("am"), None);
+        assert_eq!(parse_time_of_day("1:2:3:4"), None);
+    }
+
+    #[test]
+    pub fn test_parse_date_time_discord() {
+        let expected = Utc.timestamp(1700000000, 0);
+
+        assert_eq!(parse_date_time("<t:1700000000>", now()), Some(expected));
+        assert_eq!(parse_date_time("<t:1700000000:R>", now()), Some(expected));
+        assert_eq!(parse_date_time("<t:1700000000:f>", now()), Some(expected));
+
+        assert_eq!(parse_date_time("<t:1700000000:X>", now()), None);
+        assert_eq!(parse_date_time("<t:abc>", now()), None);
+        assert_eq!(parse_date_time("<t:>", now()), None);
+        assert_eq!(parse_date_time("<t:1700000000", now()), None);
+    }
+
+    #[test]
+    pub fn test_parse_date_time_iso() {
+        assert_eq!(
+            parse_date_time("2020-06-01T18:00:00Z", now()),
+            Some(Utc.ymd(2020, 6, 1).and_hms(18, 0, 0))
+        );
+        assert_eq!(
+            parse_date_time("2020-06-01T18:00:00+02:00", now()),
+            Some(Utc.ymd(2020, 6, 1).and_hms(16, 0, 0))
+        );
+        assert_eq!(
+            parse_date_time("2020-06-01T18:00", now()),
+            Some(Utc.ymd(2020, 6, 1).and_hms(18, 0, 0))
+        );
+        assert_eq!(
+            parse_date_time("2020-06-01 18:00:30", now()),
+            Some(Utc.ymd(2020, 6, 1).and_hms(18, 0, 30))
+        );
+        assert_eq!(
+            parse_date_time("2020-12-25", now()),
+            Some(Utc.ymd(2020, 12, 25).and_hms(0, 0, 0))
+        );
+
+        assert_eq!(parse_date_time("2020-13-01", now()), None);
+        assert_eq!(parse_date_time("2020-06-01T25:00", now()), None);
+    }
+
+    #[test]
+    pub fn test_parse_date_time_relative() {
+        assert_eq!(parse_date_time("now", now()), Some(now()));
+        assert_eq!(
+            parse_date_time("in 1h30m", now()),
+            Some(Utc.ymd(2020, 6, 1).and_hms(13, 30, 0))
+        );
+        assert_eq!(parse_date_time("today", now()), Some(now()));
+        assert_eq!(
+            parse_date_time("tomorrow", now()),
+            Some(Utc.ymd(2020, 6, 2).and_hms(12, 0, 0))
+        );
+        assert_eq!(
+            parse_date_time("tomorrow 9am", now()),
+            Some(Utc.ymd(2020, 6, 2).and_hms(9, 0, 0))
+        );
+        assert_eq!(
+            parse_date_time("Tomorrow 9AM", now()),
+            Some(Utc.ymd(2020, 6, 2).and_hms(9, 0, 0))
+        );
+        assert_eq!(
+            parse_date_time("today 18:30", now()),
+            Some(Utc.ymd(2020, 6, 1).and_hms(18, 30, 0))
+        );
+    }
+
+    #[test]
+    pub fn test_parse_date_time_next_occurrence() {
+        // Still ahead today
+        assert_eq!(
+            parse_date_time("6pm", now()),
+            Some(Utc.ymd(2020, 6, 1).and_hms(18, 0, 0))
+        );
+        // Already passed, so tomorrow
+        assert_eq!(
+            parse_date_time("9am", now()),
+            Some(Utc.ymd(2020, 6, 2).and_hms(9, 0, 0))
+        );
+        assert_eq!(
+            parse_date_time("12:00", now()),
+            Some(Utc.ymd(2020, 6, 2).and_hms(12, 0, 0))
+        );
+    }
+
+    #[test]
+    pub fn test_parse_date_time_in_timezone() {
+        // 14:00 in UTC+2
+        let now = now().with_timezone(&FixedOffset::east(2 * 3600));
+
+        assert_eq!(
+            parse_date_time_in("18:00", &now),
+            Some(Utc.ymd(2020, 6, 1).and_hms(16, 0, 0))
+        );
+        // Still ahead in UTC but not here
+        assert_eq!(
+            parse_date_time_in("13:00", &now),
+            Some(Utc.ymd(2020, 6, 2).and_hms(11, 0, 0))
+        );
+        assert_eq!(
+            parse_date_time_in("tomorrow 9am", &now),
+            Some(Utc.ymd(2020, 6, 2).and_hms(7, 0, 0))
+        );
+        assert_eq!(
+            parse_date_time_in("2020-06-01 18:00", &now),
+            Some(Utc.ymd(2020, 6, 1).and_hms(16, 0, 0))
+        );
+        // An explicit offset wins
+        assert_eq!(
+            parse_date_time_in("2020-06-01T18:00:00Z", &now),
+            Some(Utc.ymd(2020, 6, 1).and_hms(18, 0, 0))
+        );
+    }
+
+    #[test]
+    pub fn test_parse_date_time_invalid() {
+        assert_eq!(parse_date_time("", now()), None);
+        assert_eq!(parse_date_time("garbage", now()), None);
+        assert_eq!(parse_date_time("now please", now()), None);
+        assert_eq!(parse_date_time("in", now()), None);
+        assert_eq!(parse_date_time("in forever", now()), None);
+        assert_eq!(parse_date_time("tomorrow morning", now()), None);
+        assert_eq!(parse_date_time("tomorrow 9am sharp", now()), None);
+        assert_eq!(parse_date_time("9am tomorrow", now()), None);
+        assert_eq!(parse_date_time("1700000000", now()), None);
+    }
+
+    #[test]
+    pub fn test_parse_message_link() {
+        assert_eq!(
+            parse_message_link(
+                "https://discord.com/channels/81384788765712384/381889648827301889/712349587234958734"
+            ),
+            Some((
+                Some(81384788765712384),
+                381889648827301889,
+                712349587234958734
+            ))
+        );
+        assert_eq!(
+            parse_message_link(
+                "https://canary.discordapp.com/channels/@me/381889648827301889/712349587234958734"
+            ),
+            Some((None, 381889648827301889, 712349587234958734))
+        );
+
+        assert_eq!(
+            parse_message_link(
+                "https://discord.com/channels/@me/381889648827301889/712349587234958734/"
+            ),
+            None
+        );
+        assert_eq!(
+            parse_message_link(
+                "https://evil.com/channels/@me/381889648827301889/712349587234958734"
+            ),
+            None
+        );
+        assert_eq!(
+            parse_message_link("https://discord.com/channels/@me/381889648827301889"),
+            None
+        );
+        assert_eq!(
+            parse_message_link("discord.com/channels/@me/381889648827301889/712349587234958734"),
+            None
+        );
+    }
+
+    #[test]
+    pub fn test_parse_snowflake() {
+        assert_eq!(
+            parse_snowflake("81384788765712384"),
+            Some(81384788765712384)
+        );
+        assert_eq!(parse_snowflake("123"), None);
+        assert_eq!(parse_snowflake("-81384788765712384"), None);
+        assert_eq!(parse_snowflake("99999999999999999999"), None);
+    }
+
+    #[test]
+    pub fn test_parse_colour() {
+        assert_eq!(parse_colour("#ff8800"), Some(0xff8800));
+        assert_eq!(parse_colour("#F80"), Some(0xff8800));
+        assert_eq!(parse_colour("0xFF8800"), Some(0xff8800));
+        assert_eq!(parse_colour("rgb(255, 136, 0)"), Some(0xff8800));
+        assert_eq!(parse_colour("rgb(1,2,3)"), Some(0x010203));
+
+        assert_eq!(parse_colour("rgb(256,2,3)"), None);
+        assert_eq!(parse_colour("rgb(1,2)"), None);
+        assert_eq!(parse_colour("#ff880"), None);
+        assert_eq!(parse_colour("#gg8800"), None);
+        assert_eq!(parse_colour("#+f8800"), None);
+        assert_eq!(parse_colour("ff8800"), None);
+    }
+}
@@ -0,0 +1,438 @@
+use crate::cmd_tree::CmdNode;
+use crate::context::CmdContext;
+use crate::error::{CmdError, CmdResult};
+use crate::middleware::{Invocation, Middleware};
+use serenity::async_trait;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::model::permissions::Permissions;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::{Mutex, RwLock};
+
+/// Well known permission levels, any value in between can be used.
+pub struct PermLevel;
+
+impl PermLevel {
+    pub const EVERYONE: u32 = 0;
+    pub const MODERATOR: u32 = 50;
+    pub const ADMIN: u32 = 80;
+    pub const GUILD_OWNER: u32 = 100;
+    pub const BOT_OWNER: u32 = u32::MAX;
+}
+
+/// What a caller needs to execute a command.
+/// Declared on any node, it applies to the whole subtree unless a deeper node overrides it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PermRequirement {
+    pub level: u32,
+    pub permissions: Permissions,
+}
+
+impl PermRequirement {
+    pub fn level(level: u32) -> Self {
+        Self {
+            level,
+            permissions: Permissions::empty(),
+        }
+    }
+
+    pub fn permissions(permissions: Permissions) -> Self {
+        Self {
+            level: PermLevel::EVERYONE,
+            permissions,
+        }
+    }
+
+    pub fn check(&self, caller: &CallerPerms) -> CmdResult<()> {
+        if caller.level < self.level {
+            return Err(CmdError::MissingPerm { level: self.level });
+        }
+
+        let missing = self.permissions - caller.permissions;
+        if !missing.is_empty() {
+            return Err(CmdError::MissingPermissions { missing });
+        }
+
+        Ok(())
+    }
+
+    /// The requirement of the deepest node of the path declaring one.
+    pub fn of_path<'a>(path: &[&'a CmdNode]) -> Option<&'a PermRequirement> {
+        // Paths go from the leaf up
+        path.iter().find_map(|node| node.requirement.as_ref())
+    }
+}
+
+/// Level and Discord permissions of the author of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallerPerms {
+    pub level: u32,
+    pub permissions: Permissions,
+}
+
+/// Levels given to members of a guild on top of the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct GuildPermOverrides {
+    pub roles: HashMap<RoleId, u32>,
+    /// Takes precedence over the roles, even to lower a level.
+    pub users: HashMap<UserId, u32>,
+}
+
+impl GuildPermOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.roles.is_empty() && self.users.is_empty()
+    }
+}
+
+/// Keeps the overrides of each guild.
+pub trait PermStore: Send + Sync {
+    fn get(&self, guild: GuildId) -> Option<GuildPermOverrides>;
+    fn set(&self, guild: GuildId, overrides: GuildPermOverrides) -> io::Result<()>;
+    fn remove(&self, guild: GuildId) -> io::Result<()>;
+}
+
+/// Forgets every override on restart.
+#[derive(Debug, Default)]
+pub struct MemoryPermStore {
+    overrides: RwLock<HashMap<GuildId, GuildPermOverrides>>,
+}
+
+impl MemoryPermStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PermStore for MemoryPermStore {
+    fn get(&self, guild: GuildId) -> Option<GuildPermOverrides> {
+        self.overrides.read().unwrap().get(&guild).cloned()
+    }
+
+    fn set(&self, guild: GuildId, overrides: GuildPermOverrides) -> io::Result<()> {
+        self.overrides.write().unwrap().insert(guild, overrides);
+        Ok(())
+    }
+
+    fn remove(&self, guild: GuildId) -> io::Result<()> {
+        self.overrides.write().unwrap().remove(&guild);
+        Ok(())
+    }
+}
+
+/// Computes the level of a caller from the bot owners, guild ownership,
+/// Discord permissions, role levels and per user overrides.
+pub struct PermResolver {
+    owners: RwLock<HashSet<UserId>>,
+    store: Box<dyn PermStore>,
+    /// Held while the overrides of a guild are read, changed and stored back.
+    editing: Mutex<()>,
+}
+
+impl Default for PermResolver {
+    fn default() -> Self {
+        Self::with_owners(Vec::new())
+    }
+}
+
+impl PermResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_owners<I: IntoIterator<Item = UserId>>(owners: I) -> Self {
+        Self {
+            owners: RwLock::new(owners.into_iter().collect()),
+            store: Box::new(MemoryPermStore::new()),
+            editing: Mutex::default(),
+        }
+    }
+
+    pub fn with_store(mut self, store: Box<dyn PermStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    pub fn is_owner(&self, user: UserId) -> bool {
+        self.owners.read().unwrap().contains(&user)
+    }
+
+    /// Replace the bot owners, like when the configuration is reloaded.
+    pub fn set_owners<I: IntoIterator<Item = UserId>>(&self, owners: I) {
+        *self.owners.write().unwrap() = owners.into_iter().collect();
+    }
+
+    pub fn set_role_level(&self, guild: GuildId, role: RoleId, level: u32) -> io::Result<()> {
+        self.edit(guild, |overrides| {
+            overrides.roles.insert(role, level);
+        })
+    }
+
+    pub fn set_user_level(&self, guild: GuildId, user: UserId, level: u32) -> io::Result<()> {
+        self.edit(guild, |overrides| {
+            overrides.users.insert(user, level);
+        })
+    }
+
+    pub fn clear_role_level(&self, guild: GuildId, role: RoleId) -> io::Result<()> {
+        self.edit(guild, |overrides| {
+            overrides.roles.remove(&role);
+        })
+    }
+
+    pub fn clear_user_level(&self, guild: GuildId, user: UserId) -> io::Result<()> {
+        self.edit(guild, |overrides| {
+            overrides.users.remove(&user);
+        })
+    }
+
+    fn edit<F: FnOnce(&mut GuildPermOverrides)>(&self, guild: GuildId, f: F) -> io::Result<()> {
+        let _editing = self.editing.lock().unwrap();
+        let mut overrides = self.guild_overrides(guild);
+        f(&mut overrides);
+
+        if overrides.is_empty() {
+            self.store.remove(guild)
+        } else {
+            self.store.set(guild, overrides)
+        }
+    }
+
+    pub fn guild_overrides(&self, guild: GuildId) -> GuildPermOverrides {
+        self.store.get(guild).unwrap_or_default()
+    }
+
+    /// Level of a member, from the overrides the store keeps in memory.
+    pub fn level_of(
+        &self,
+        user: UserId,
+        guild: GuildId,
+        guild_owner: UserId,
+        roles: &[RoleId],
+        permissions: Permissions,
+    ) -> u32 {
+        if self.is_owner(user) {
+            return PermLevel::BOT_OWNER;
+        }
+        if user == guild_owner {
+            return PermLevel::GUILD_OWNER;
+        }
+
+        let overrides = self.store.get(guild);
+        let overrides = overrides.as_ref();
+
+        if let Some(&level) = overrides.and_then(|o| o.users.get(&user)) {
+            return level;
+        }
+
+        let base = if permissions.administrator() {
+            PermLevel::ADMIN
+        } else {
+            PermLevel::EVERYONE
+        };
+
+        roles
+            .iter()
+            .filter_map(|role| overrides?.roles.get(role))
+            .copied()
+            .fold(base, u32::max)
+    }
+
+    /// Resolve the level and permissions of the author of the command.
+    /// Outside of a guild, or of Discord, there is no Discord permission
+    /// and only owners get a level.
+    pub async fn resolve(&self, ctx: &dyn CmdContext) -> CallerPerms {
+        let author = ctx.author().id;
+
+        let guild = match ctx.discord() {
+            Some((ctx, message)) => message.guild(&ctx.cache).await,
+            None => None,
+        };
+        let guild = match guild {
+            Some(guild) => guild,
+            None => {
+                let level = if self.is_owner(author) {
+                    PermLevel::BOT_OWNER
+                } else {
+                    PermLevel::EVERYONE
+                };
+                return CallerPerms {
+                    level,
+                    permissions: Permissions::empty(),
+                };
+            }
+        };
+
+        let permissions = guild.member_permissions(author);
+        let roles = guild
+            .members
+            .get(&author)
+            .map(|member| member.roles.clone())
+            .unwrap_or_default();
+
+        CallerPerms {
+            level: self.level_of(author, guild.id, guild.owner_id, &roles, permissions),
+            permissions,
+        }
+    }
+}
+
+/// Checks the requirement of the invoked command, if any.
+#[async_trait]
+impl Middleware for PermResolver {
+    async fn before(&self, invocation: &Invocation<'_>) -> CmdResult<()> {
+        if let Some(requirement) = PermRequirement::of_path(&invocation.path) {
+            let caller = self.resolve(invocation.ctx).await;
+            requirement.check(&caller)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmd_tree::CmdNode;
+    use crate::error::CmdError;
+    use crate::matchers::ExactMatcher;
+    use crate::perms::{CallerPerms, PermLevel, PermRequirement, PermResolver};
+    use serenity::model::id::{GuildId, RoleId, UserId};
+    use serenity::model::permissions::Permissions;
+
+    const GUILD: GuildId = GuildId(1);
+    const GUILD_OWNER: UserId = UserId(10);
+    const BOT_OWNER: UserId = UserId(20);
+    const MEMBER: UserId = UserId(30);
+    const MOD_ROLE: RoleId = RoleId(100);
+    const ADMIN_ROLE: RoleId = RoleId(101);
+
+    fn make_resolver() -> PermResolver {
+        let resolver = PermResolver::with_owners(vec![BOT_OWNER]);
+        resolver
+            .set_role_level(GUILD, MOD_ROLE, PermLevel::MODERATOR)
+            .unwrap();
+        resolver
+            .set_role_level(GUILD, ADMIN_ROLE, PermLevel::ADMIN)
+            .unwrap();
+        resolver
+    }
+
+    fn level_of(resolver: &PermResolver, user: UserId, roles: &[RoleId]) -> u32 {
+        resolver.level_of(user, GUILD, GUILD_OWNER, roles, Permissions::empty())
+    }
+
+    #[test]
+    pub fn test_resolver_owners() {
+        let resolver = make_resolver();
+
+        assert_eq!(level_of(&resolver, BOT_OWNER, &[]), PermLevel::BOT_OWNER);
+        assert_eq!(level_of(&resolver, GUILD_OWNER, &[]), PermLevel::GUILD_OWNER);
+
+        resolver.set_owners(vec![MEMBER]);
+        assert_eq!(level_of(&resolver, MEMBER, &[]), PermLevel::BOT_OWNER);
+        assert_eq!(level_of(&resolver, BOT_OWNER, &[]), PermLevel::EVERYONE);
+    }
+
+    #[test]
+    pub fn test_resolver_roles() {
+        let resolver = make_resolver();
+
+        assert_eq!(level_of(&resolver, MEMBER, &[]), PermLevel::EVERYONE);
+        assert_eq!(level_of(&resolver, MEMBER, &[RoleId(999)]), PermLevel::EVERYONE);
+        assert_eq!(level_of(&resolver, MEMBER, &[MOD_ROLE]), PermLevel::MODERATOR);
+        assert_eq!(
+            level_of(&resolver, MEMBER, &[MOD_ROLE, ADMIN_ROLE]),
+            PermLevel::ADMIN
+        );
+
+        // Roles are per guild
+        assert_eq!(
+            resolver.level_of(
+                MEMBER,
+                GuildId(2),
+                GUILD_OWNER,
+                &[ADMIN_ROLE],
+                Permissions::empty()
+            ),
+            PermLevel::EVERYONE
+        );
+    }
+
+    #[test]
+    pub fn test_resolver_administrator() {
+        let resolver = make_resolver();
+
+        let level = resolver.level_of(MEMBER, GUILD, GUILD_OWNER, &[], Permissions::ADMINISTRATOR);
+        assert_eq!(level, PermLevel::ADMIN);
+    }
+
+    #[test]
+    pub fn test_resolver_user_override() {
+        let resolver = make_resolver();
+
+        resolver.set_user_level(GUILD, MEMBER, 60).unwrap();
+        assert_eq!(level_of(&resolver, MEMBER, &[]), 60);
+        // Overrides can also lower a level
+        assert_eq!(level_of(&resolver, MEMBER, &[ADMIN_ROLE]), 60);
+
+        resolver.clear_user_level(GUILD, MEMBER).unwrap();
+        assert_eq!(level_of(&resolver, MEMBER, &[ADMIN_ROLE]), PermLevel::ADMIN);
+
+        resolver.clear_role_level(GUILD, ADMIN_ROLE).unwrap();
+        assert_eq!(level_of(&resolver, MEMBER, &[ADMIN_ROLE]), PermLevel::EVERYONE);
+
+        // Emptied overrides leave the store
+        resolver.clear_role_level(GUILD, MOD_ROLE).unwrap();
+        assert!(resolver.guild_overrides(GUILD).is_empty());
+    }
+
+    #[test]
+    pub fn test_requirement_check() {
+        let caller = CallerPerms {
+            level: PermLevel::MODERATOR,
+            permissions: Permissions::SEND_MESSAGES | Permissions::KICK_MEMBERS,
+        };
+
+        assert!(PermRequirement::level(PermLevel::MODERATOR)
+            .check(&caller)
+            .is_ok());
+        assert!(PermRequirement::permissions(Permissions::KICK_MEMBERS)
+            .check(&caller)
+            .is_ok());
+
+        match PermRequirement::level(PermLevel::ADMIN).check(&caller) {
+            Err(CmdError::MissingPerm { level }) => assert_eq!(level, PermLevel::ADMIN),
+            _ => unreachable!(),
+        }
+        match PermRequirement::permissions(Permissions::KICK_MEMBERS | Permissions::BAN_MEMBERS)
+            .check(&caller)
+        {
+            Err(CmdError::MissingPermissions { missing }) => {
+                assert_eq!(missing, Permissions::BAN_MEMBERS)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    pub fn test_requirement_inheritance() {
+        let mut root = CmdNode::new(ExactMatcher::new("admin".into()));
+        root.requirement = Some(PermRequirement::level(PermLevel::ADMIN));
+        let mut sub = CmdNode::new(ExactMatcher::new("sub".into()));
+        let mut overridden = CmdNode::new(ExactMatcher::new("overridden".into()));
+        overridden.requirement = Some(PermRequirement::level(PermLevel::BOT_OWNER));
+
+        // Paths go from the leaf up
+        assert_eq!(
+            PermRequirement::of_path(&[&sub, &root]),
+            Some(&PermRequirement::level(PermLevel::ADMIN))
+        );
+        assert_eq!(
+            PermRequirement::of_path(&[&overridden, &root]),
+            Some(&PermRequirement::level(PermLevel::BOT_OWNER))
+        );
+
+        sub.requirement = None;
+        root.requirement = None;
+        assert_eq!(PermRequirement::of_path(&[&sub, &root]), None);
+    }
+}
@@ -1,5 +1,6 @@
-use std::any::TypeId;
-use std::collections::HashMap;
+use std::any::{Any, TypeId};
+use std::collections::{hash_map, HashMap};
+use std::marker::PhantomData;
 
 pub struct TypeMap<V>(HashMap<TypeId, V>);
 
@@ -50,9 +51,127 @@ impl<V> TypeMap<V> {
     }
 }
 
+/// Values of any type, each type being the key of its own value.
+#[derive(Default)]
+pub struct AnyTypeMap(HashMap<TypeId, Box<dyn Any + Send + Sync>>);
+
+impl AnyTypeMap {
+    #[inline]
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    #[inline]
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.0
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    #[inline]
+    pub fn get_mut<T: Any + Send + Sync>(&mut self) -> Option<&mut T> {
+        self.0
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    #[inline]
+    pub fn contains<T: Any + Send + Sync>(&self) -> bool {
+        self.0.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Store `value`, giving back the previous value of its type.
+    #[inline]
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<T> {
+        self.0
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    #[inline]
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<T> {
+        self.0
+            .remove(&TypeId::of::<T>())
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    #[inline]
+    pub fn entry<T: Any + Send + Sync>(&mut self) -> Entry<'_, T> {
+        Entry {
+            inner: self.0.entry(TypeId::of::<T>()),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The place of the value of type `T` in an `AnyTypeMap`, filled or not.
+pub struct Entry<'a, T> {
+    inner: hash_map::Entry<'a, TypeId, Box<dyn Any + Send + Sync>>,
+    marker: PhantomData<T>,
+}
+
+impl<'a, T: Any + Send + Sync> Entry<'a, T> {
+    // Values are only ever stored under their own type
+    fn cast(value: &mut Box<dyn Any + Send + Sync>) -> &mut T {
+        value
+            .downcast_mut()
+            .expect("AnyTypeMap value of the wrong type")
+    }
+
+    #[inline]
+    pub fn is_occupied(&self) -> bool {
+        matches!(self.inner, hash_map::Entry::Occupied(_))
+    }
+
+    #[inline]
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        self.or_insert_with(|| default)
+    }
+
+    #[inline]
+    pub fn or_insert_with<F: FnOnce() -> T>(self, default: F) -> &'a mut T {
+        Self::cast(self.inner.or_insert_with(|| Box::new(default())))
+    }
+
+    #[inline]
+    pub fn or_default(self) -> &'a mut T
+    where
+        T: Default,
+    {
+        self.or_insert_with(T::default)
+    }
+
+    /// Change the value if there is one.
+    #[inline]
+    pub fn and_modify<F: FnOnce(&mut T)>(self, f: F) -> Self {
+        Self {
+            inner: self.inner.and_modify(|value| f(Self::cast(value))),
+            marker: PhantomData,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::type_map::TypeMap;
+    use crate::type_map::{AnyTypeMap, TypeMap};
+    use std::sync::Arc;
 
     #[test]
     pub fn test_type_map_get_insert() {
@@ -85,4 +204,55 @@ mod tests {
         map.clear();
         assert_eq!(map.len(), 0);
     }
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Counter(u32);
+
+    #[test]
+    pub fn test_any_type_map_get_insert() {
+        let mut map = AnyTypeMap::new();
+
+        assert!(map.is_empty());
+        assert_eq!(map.get::<Counter>(), None);
+
+        assert_eq!(map.insert(Counter(1)), None);
+        assert_eq!(map.insert(String::from("db")), None);
+        assert_eq!(map.insert(Counter(2)), Some(Counter(1)));
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.get::<Counter>(), Some(&Counter(2)));
+        assert_eq!(map.get::<String>().map(String::as_str), Some("db"));
+        map.get_mut::<Counter>().unwrap().0 += 1;
+        assert_eq!(map.get::<Counter>(), Some(&Counter(3)));
+
+        // Shared services are usually behind an `Arc`, its own key
+        let shared = Arc::new(Counter(4));
+        map.insert(shared.clone());
+        assert!(map.contains::<Arc<Counter>>());
+        assert_eq!(map.get::<Counter>(), Some(&Counter(3)));
+
+        assert_eq!(map.remove::<Counter>(), Some(Counter(3)));
+        assert_eq!(map.remove::<Counter>(), None);
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    pub fn test_any_type_map_entry() {
+        let mut map = AnyTypeMap::new();
+
+        assert!(!map.entry::<Counter>().is_occupied());
+        map.entry::<Counter>().or_default().0 += 1;
+        map.entry::<Counter>().or_insert(Counter(10)).0 += 1;
+        assert_eq!(map.get::<Counter>(), Some(&Counter(2)));
+
+        map.entry::<Counter>()
+            .and_modify(|counter| counter.0 *= 10)
+            .or_insert_with(|| unreachable!());
+        map.entry::<u8>()
+            .and_modify(|_| unreachable!())
+            .or_insert(7);
+        assert_eq!(map.get::<Counter>(), Some(&Counter(20)));
+        assert_eq!(map.get::<u8>(), Some(&7));
+    }
 }
@@ -0,0 +1,54 @@
+# Messages of the bot commands, merged with the catalogs of ffirs-commands.
+help-title = Commands
+help-no-description = No description
+help-description = List the available commands
+shutdown-description = Stop the bot once the running commands are done
+restart-description = Restart the bot once the running commands are done
+shutdown-started = Shutting down...
+restart-started = Restarting...
+prefix-description = Change or reset the prefix of this server
+locale-description = Choose your language
+locale-guild-description = Choose the language of this server
+locale-user-set = Your language is now { $locale }
+locale-guild-set = The language of this server is now { $locale }
+module-description = Enable or disable a module, here or in a channel
+command-description = Enable or disable a command, here or in a channel
+toggle-enabled = { $name } enabled { $place }
+toggle-disabled = { $name } disabled { $place }
+toggle-reset = { $name } reset { $place }
+toggle-place-guild = on this server
+toggle-place-channel = in { $channel }
+toggle-state-enabled = enabled
+toggle-state-disabled = disabled
+shards-description = Show the state of every shard
+shards-title = Shards
+shards-line = Shard { $id }: { $stage }, { $latency }, { $guilds } guilds
+shards-latency-unknown = latency unknown
+reload-description = Read the configuration file again
+config-reloaded = Configuration reloaded
+config-restart-needed = Restart the bot to apply { $keys }
+remind-description = Remind you of something after a while
+remind-at-description = Remind you of something at a time of your timezone
+remind-list-description = List your reminders
+remind-cancel-description = Cancel one of your reminders
+remind-timezone-description = Choose your timezone, like Europe/Paris
+remind-set = Reminder { $id } set for { $when }
+remind-list-title = Your reminders
+remind-list-empty = You have no reminders
+remind-list-line = `{ $id }` { $when }: { $text }
+remind-cancelled = Reminder { $id } cancelled
+remind-timezone-set = Your timezone is now { $timezone }
+remind-delivered = { $user }, you asked me to remind you: { $text }
+
+# Errors of the core modules, see `CoreError`.
+error-disabled = { $name } is disabled here
+error-unknown-module = Unknown module { $name }
+error-module-locked = Module { $name } can't be disabled
+error-invalid-schedule = Invalid schedule: { $message }
+error-missing-time-of-day = { $day } has no time, quote the day and time together like "{ $day } 18:00"
+error-unknown-timezone = Unknown timezone { $timezone }, use a name like Europe/Paris
+error-unknown-reminder = You have no reminder { $id }
+error-too-many-reminders = You already have { $max } reminders, cancel one first
+error-prompt-timed-out = No answer after { $seconds } seconds, start over when you are ready
+error-prompt-cancelled = Cancelled
+error-invalid-config = Invalid configuration, { $key }: { $message }
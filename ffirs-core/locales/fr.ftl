@@ -0,0 +1,79 @@
+# Messages des commandes du bot, fusionnés avec les catalogues de ffirs-commands.
+help-title = Commandes
+help-no-description = Pas de description
+help-description = Lister les commandes disponibles
+shutdown-description = Arrêter le bot une fois les commandes en cours terminées
+restart-description = Redémarrer le bot une fois les commandes en cours terminées
+shutdown-started = Arrêt en cours...
+restart-started = Redémarrage en cours...
+prefix-description = Changer ou réinitialiser le préfixe de ce serveur
+locale-description = Choisir votre langue
+locale-guild-description = Choisir la langue de ce serveur
+locale-user-set = Votre langue est maintenant { $locale }
+locale-guild-set = La langue de ce serveur est maintenant { $locale }
+
+module-description = Activer ou désactiver un module, ici ou dans un salon
+command-description = Activer ou désactiver une commande, ici ou dans un salon
+toggle-enabled = { $name } activé { $place }
+toggle-disabled = { $name } désactivé { $place }
+toggle-reset = { $name } réinitialisé { $place }
+toggle-place-guild = sur ce serveur
+toggle-place-channel = dans { $channel }
+toggle-state-enabled = activé
+toggle-state-disabled = désactivé
+shards-description = Afficher l'état de chaque shard
+shards-title = Shards
+shards-line = Shard { $id } : { $stage }, { $latency }, { $guilds } serveurs
+shards-latency-unknown = latence inconnue
+reload-description = Relire le fichier de configuration
+config-reloaded = Configuration rechargée
+config-restart-needed = Redémarrez le bot pour appliquer { $keys }
+remind-description = Vous rappeler quelque chose dans un moment
+remind-at-description = Vous rappeler quelque chose à une heure de votre fuseau horaire
+remind-list-description = Lister vos rappels
+remind-cancel-description = Annuler un de vos rappels
+remind-timezone-description = Choisir votre fuseau horaire, comme Europe/Paris
+remind-set = Rappel { $id } prévu pour le { $when }
+remind-list-title = Vos rappels
+remind-list-empty = Vous n'avez aucun rappel
+remind-list-line = `{ $id }` { $when } : { $text }
+remind-cancelled = Rappel { $id } annulé
+remind-timezone-set = Votre fuseau horaire est maintenant { $timezone }
+remind-delivered = { $user }, vous m'avez demandé de vous rappeler : { $text }
+
+# Erreurs des modules du cœur, voir `CoreError`.
+error-disabled = { $name } est désactivé ici
+error-unknown-module = Module inconnu : { $name }
+error-module-locked = Le module { $name } ne peut pas être désactivé
+error-invalid-schedule = Planification invalide : { $message }
+error-missing-time-of-day = { $day } n'a pas d'heure, mettez le jour et l'heure entre guillemets comme "{ $day } 18:00"
+error-unknown-timezone = Fuseau horaire inconnu { $timezone }, utilisez un nom comme Europe/Paris
+error-unknown-reminder = Vous n'avez pas de rappel { $id }
+error-too-many-reminders = Vous avez déjà { $max } rappels, annulez-en un d'abord
+error-prompt-timed-out = Pas de réponse après { $seconds } secondes, recommencez quand vous serez prêt
+error-prompt-cancelled = Annulé
+error-invalid-config = Configuration invalide, { $key } : { $message }
+
+# Alias des commandes, voir `Locales::apply_aliases`.
+alias-help = aide
+alias-shutdown = arrêt
+alias-restart = redémarrer
+alias-prefix = préfixe
+alias-prefix-set = définir
+alias-prefix-reset = réinitialiser
+alias-locale = langue
+alias-locale-guild = serveur
+alias-module-list = liste
+alias-module-enable = activer
+alias-module-disable = désactiver
+alias-module-reset = réinitialiser
+alias-command = commande
+alias-command-enable = activer
+alias-command-disable = désactiver
+alias-command-reset = réinitialiser
+alias-reload = recharger
+alias-remind = rappel
+alias-remind-at = à
+alias-remind-list = liste
+alias-remind-cancel = annuler
+alias-remind-timezone = fuseau
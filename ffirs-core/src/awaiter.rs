@@ -0,0 +1,566 @@
+use crate::error::CoreError;
+use crate::transport::emoji_text;
+use ffirs_commands::context::CmdContext;
+use ffirs_commands::error::CmdResult;
+use ffirs_commands::matchers::FragMatcher;
+use serenity::model::{
+    channel::{Message, Reaction},
+    id::{ChannelId, MessageId, UserId},
+};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{oneshot, Notify},
+    time::timeout,
+};
+
+/// A message as waiters see it, whatever the transport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncomingMessage {
+    pub id: MessageId,
+    pub author: UserId,
+    pub channel: ChannelId,
+    pub content: String,
+}
+
+impl From<&Message> for IncomingMessage {
+    fn from(message: &Message) -> Self {
+        Self {
+            id: message.id,
+            author: message.author.id,
+            channel: message.channel_id,
+            content: message.content.clone(),
+        }
+    }
+}
+
+/// A reaction added to a message, the emoji written like `CmdContext::react` takes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncomingReaction {
+    pub message: MessageId,
+    pub user: UserId,
+    pub channel: ChannelId,
+    pub emoji: String,
+}
+
+impl From<&Reaction> for IncomingReaction {
+    fn from(reaction: &Reaction) -> Self {
+        Self {
+            message: reaction.message_id,
+            user: reaction.user_id,
+            channel: reaction.channel_id,
+            emoji: emoji_text(&reaction.emoji),
+        }
+    }
+}
+
+/// Which message a waiter wants: from a user in a channel, optionally matching
+/// a `FragMatcher` as a whole and a predicate.
+pub struct MessageFilter {
+    author: UserId,
+    channel: ChannelId,
+    matcher: Option<Box<dyn FragMatcher>>,
+    predicate: Option<Box<dyn Fn(&IncomingMessage) -> bool + Send + Sync>>,
+}
+
+impl MessageFilter {
+    pub fn new(author: UserId, channel: ChannelId) -> Self {
+        Self {
+            author,
+            channel,
+            matcher: None,
+            predicate: None,
+        }
+    }
+
+    /// The next message of the author of a command, where they sent it.
+    pub fn reply_to(ctx: &dyn CmdContext) -> Self {
+        Self::new(ctx.author().id, ctx.channel())
+    }
+
+    pub fn matcher<M: FragMatcher>(mut self, matcher: M) -> Self {
+        self.matcher = Some(Box::new(matcher));
+        self
+    }
+
+    pub fn predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&IncomingMessage) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Box::new(predicate));
+        self
+    }
+
+    pub fn accepts(&self, message: &IncomingMessage) -> bool {
+        message.author == self.author
+            && message.channel == self.channel
+            && self
+                .matcher
+                .as_ref()
+                .map_or(true, |matcher| matcher.matches(message.content.trim()))
+            && self
+                .predicate
+                .as_ref()
+                .map_or(true, |predicate| predicate(message))
+    }
+}
+
+/// Which reaction a waiter wants: on a message, optionally from a user and among some emojis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReactionFilter {
+    message: MessageId,
+    user: Option<UserId>,
+    emojis: Vec<String>,
+}
+
+impl ReactionFilter {
+    pub fn new(message: MessageId) -> Self {
+        Self {
+            message,
+            user: None,
+            emojis: Vec::new(),
+        }
+    }
+
+    pub fn user(mut self, user: UserId) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    pub fn emojis(mut self, emojis: &[&str]) -> Self {
+        self.emojis = emojis.iter().map(|&emoji| emoji.to_owned()).collect();
+        self
+    }
+
+    pub fn accepts(&self, reaction: &IncomingReaction) -> bool {
+        reaction.message == self.message
+            && self.user.map_or(true, |user| reaction.user == user)
+            && (self.emojis.is_empty() || self.emojis.contains(&reaction.emoji))
+    }
+}
+
+type Filter<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+
+struct Waiter<T> {
+    id: u64,
+    filter: Filter<T>,
+    sender: oneshot::Sender<T>,
+}
+
+/// The waiters of one kind of event, oldest first.
+struct Waiters<T> {
+    list: Mutex<Vec<Waiter<T>>>,
+}
+
+impl<T: Clone> Waiters<T> {
+    fn new() -> Self {
+        Self {
+            list: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn add(&self, id: u64, filter: Filter<T>) -> oneshot::Receiver<T> {
+        let (sender, receiver) = oneshot::channel();
+        self.list
+            .lock()
+            .unwrap()
+            .push(Waiter { id, filter, sender });
+        receiver
+    }
+
+    fn remove(&self, id: u64) -> bool {
+        let mut list = self.list.lock().unwrap();
+        let before = list.len();
+        list.retain(|waiter| waiter.id != id);
+        list.len() != before
+    }
+
+    /// Give `event` to the oldest waiter accepting it, `false` if none did.
+    fn route(&self, event: &T) -> bool {
+        let mut list = self.list.lock().unwrap();
+        match list.iter().position(|waiter| (waiter.filter)(event)) {
+            Some(index) => list.remove(index).sender.send(event.clone()).is_ok(),
+            None => false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.list.lock().unwrap().len()
+    }
+}
+
+/// Stops a wait from elsewhere, the waiting side getting `CoreError::PromptCancelled`.
+pub struct Canceller<T> {
+    id: u64,
+    waiters: Arc<Waiters<T>>,
+}
+
+impl<T: Clone> Canceller<T> {
+    /// `false` if the wait already ended.
+    pub fn cancel(&self) -> bool {
+        self.waiters.remove(self.id)
+    }
+}
+
+/// An event being waited for, registered as soon as it is created so nothing
+/// sent in between, like the answer to a prompt, is missed.
+///
+/// Dropping it stops waiting.
+pub struct Pending<T: Clone> {
+    id: u64,
+    waiters: Arc<Waiters<T>>,
+    receiver: oneshot::Receiver<T>,
+}
+
+impl<T: Clone> Pending<T> {
+    pub fn canceller(&self) -> Canceller<T> {
+        Canceller {
+            id: self.id,
+            waiters: self.waiters.clone(),
+        }
+    }
+
+    pub async fn wait(mut self, limit: Duration) -> CmdResult<T> {
+        match timeout(limit, &mut self.receiver).await {
+            Ok(Ok(event)) => Ok(event),
+            Ok(Err(_)) => Err(CoreError::PromptCancelled.into()),
+            Err(_) => Err(CoreError::PromptTimedOut {
+                seconds: limit.as_secs(),
+            }
+            .into()),
+        }
+    }
+}
+
+impl<T: Clone> Drop for Pending<T> {
+    fn drop(&mut self) {
+        self.waiters.remove(self.id);
+    }
+}
+
+/// Lets command handlers wait for the next message or reaction of a user.
+///
+/// The transports route every event here before dispatching it as a command,
+/// an event given to a waiter isn't dispatched.
+pub struct Awaiters {
+    next_id: AtomicU64,
+    messages: Arc<Waiters<IncomingMessage>>,
+    reactions: Arc<Waiters<IncomingReaction>>,
+    /// Notified every time a wait starts.
+    started: Notify,
+}
+
+impl Default for Awaiters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Awaiters {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            messages: Arc::new(Waiters::new()),
+            reactions: Arc::new(Waiters::new()),
+            started: Notify::new(),
+        }
+    }
+
+    fn pending<T: Clone>(&self, waiters: &Arc<Waiters<T>>, filter: Filter<T>) -> Pending<T> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let receiver = waiters.add(id, filter);
+        self.started.notify();
+
+        Pending {
+            id,
+            receiver,
+            waiters: waiters.clone(),
+        }
+    }
+
+    pub fn message(&self, filter: MessageFilter) -> Pending<IncomingMessage> {
+        let filter = Box::new(move |message: &IncomingMessage| filter.accepts(message));
+        self.pending(&self.messages, filter)
+    }
+
+    pub fn reaction(&self, filter: ReactionFilter) -> Pending<IncomingReaction> {
+        let filter = Box::new(move |reaction: &IncomingReaction| filter.accepts(reaction));
+        self.pending(&self.reactions, filter)
+    }
+
+    pub async fn next_message(
+        &self,
+        filter: MessageFilter,
+        limit: Duration,
+    ) -> CmdResult<IncomingMessage> {
+        self.message(filter).wait(limit).await
+    }
+
+    pub async fn next_reaction(
+        &self,
+        filter: ReactionFilter,
+        limit: Duration,
+    ) -> CmdResult<IncomingReaction> {
+        self.reaction(filter).wait(limit).await
+    }
+
+    /// Give `message` to the oldest waiter wanting it, `false` if it is a command to dispatch.
+    pub fn route_message(&self, message: &IncomingMessage) -> bool {
+        self.messages.route(message)
+    }
+
+    pub fn route_reaction(&self, reaction: &IncomingReaction) -> bool {
+        self.reactions.route(reaction)
+    }
+
+    /// Waits in progress, of both kinds.
+    pub fn waiting(&self) -> usize {
+        self.messages.len() + self.reactions.len()
+    }
+
+    /// Resolves once something waits for an event, right away if something already does.
+    pub async fn prompted(&self) {
+        while self.waiting() == 0 {
+            self.started.notified().await;
+        }
+    }
+}
+
+struct Step {
+    prompt: String,
+    matcher: Box<dyn FragMatcher>,
+}
+
+/// Questions asked in turn to the author of a command, each answer having to match
+/// the `FragMatcher` of its step. The question is asked again after an invalid answer.
+pub struct Wizard {
+    steps: Vec<Step>,
+    timeout: Duration,
+    cancel_word: Option<String>,
+}
+
+impl Wizard {
+    /// `timeout` is how long the author gets to answer each question.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            steps: Vec::new(),
+            timeout,
+            cancel_word: None,
+        }
+    }
+
+    pub fn step<S: Into<String>, M: FragMatcher>(mut self, prompt: S, matcher: M) -> Self {
+        self.steps.push(Step {
+            prompt: prompt.into(),
+            matcher: Box::new(matcher),
+        });
+        self
+    }
+
+    /// Answering `word` to any question stops the wizard.
+    pub fn cancel_word<S: Into<String>>(mut self, word: S) -> Self {
+        self.cancel_word = Some(word.into());
+        self
+    }
+
+    /// The answers, trimmed, in the order of the steps.
+    pub async fn run(&self, ctx: &dyn CmdContext, awaiters: &Awaiters) -> CmdResult<Vec<String>> {
+        let mut answers = Vec::with_capacity(self.steps.len());
+
+        for step in &self.steps {
+            loop {
+                let pending = awaiters.message(MessageFilter::reply_to(ctx));
+                ctx.reply(&step.prompt).await?;
+                let answer = pending.wait(self.timeout).await?;
+                let answer = answer.content.trim();
+
+                if let Some(word) = &self.cancel_word {
+                    if answer.eq_ignore_ascii_case(word) {
+                        return Err(CoreError::PromptCancelled.into());
+                    }
+                }
+                if step.matcher.matches(answer) {
+                    answers.push(answer.to_owned());
+                    break;
+                }
+            }
+        }
+
+        Ok(answers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::awaiter::{
+        Awaiters, IncomingMessage, IncomingReaction, MessageFilter, ReactionFilter, Wizard,
+    };
+    use crate::error::CoreError;
+    use crate::transport::TerminalContext;
+    use ffirs_commands::context::CmdUser;
+    use ffirs_commands::matchers::{RestMatcher, UnsignedMatcher};
+    use serenity::model::id::{ChannelId, MessageId, UserId};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::time::{delay_for, timeout};
+
+    const LONG: Duration = Duration::from_secs(5);
+
+    fn message(author: u64, channel: u64, content: &str) -> IncomingMessage {
+        IncomingMessage {
+            id: MessageId(1),
+            author: UserId(author),
+            channel: ChannelId(channel),
+            content: content.to_owned(),
+        }
+    }
+
+    /// Route `message` once someone waits for it.
+    async fn answer(awaiters: &Awaiters, message: IncomingMessage) {
+        while !awaiters.route_message(&message) {
+            delay_for(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_awaiter_message() {
+        let awaiters = Awaiters::new();
+        let filter = MessageFilter::new(UserId(7), ChannelId(20)).matcher(UnsignedMatcher);
+        let pending = awaiters.message(filter);
+
+        // Someone else, elsewhere, or not a number: left to command dispatch
+        assert!(!awaiters.route_message(&message(8, 20, "1")));
+        assert!(!awaiters.route_message(&message(7, 21, "1")));
+        assert!(!awaiters.route_message(&message(7, 20, "ping")));
+        assert!(awaiters.route_message(&message(7, 20, " 2 ")));
+        assert!(!awaiters.route_message(&message(7, 20, "3")));
+
+        assert_eq!(pending.wait(LONG).await.unwrap().content, " 2 ");
+        assert_eq!(awaiters.waiting(), 0);
+
+        let filter = MessageFilter::new(UserId(7), ChannelId(20))
+            .predicate(|message| message.content.starts_with('!'));
+        assert!(!awaiters.route_message(&message(7, 20, "!nobody")));
+        let pending = awaiters.message(filter);
+        assert!(awaiters.route_message(&message(7, 20, "!yes")));
+        assert_eq!(pending.wait(LONG).await.unwrap().content, "!yes");
+    }
+
+    #[tokio::test]
+    pub async fn test_awaiter_reaction() {
+        let awaiters = Awaiters::new();
+        let filter = ReactionFilter::new(MessageId(5))
+            .user(UserId(7))
+            .emojis(&["✅", "❌"]);
+        let pending = awaiters.reaction(filter);
+        let reaction = |message: u64, user: u64, emoji: &str| IncomingReaction {
+            message: MessageId(message),
+            user: UserId(user),
+            channel: ChannelId(20),
+            emoji: emoji.to_owned(),
+        };
+
+        assert!(!awaiters.route_reaction(&reaction(6, 7, "✅")));
+        assert!(!awaiters.route_reaction(&reaction(5, 8, "✅")));
+        assert!(!awaiters.route_reaction(&reaction(5, 7, "🦀")));
+        assert!(awaiters.route_reaction(&reaction(5, 7, "❌")));
+        assert_eq!(pending.wait(LONG).await.unwrap().emoji, "❌");
+    }
+
+    #[tokio::test]
+    pub async fn test_awaiter_timeout_and_cancel() {
+        let awaiters = Awaiters::new();
+        let filter = || MessageFilter::new(UserId(7), ChannelId(20));
+
+        assert!(matches!(
+            awaiters
+                .next_message(filter(), Duration::from_millis(10))
+                .await
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::PromptTimedOut { seconds: 0 })
+        ));
+        assert_eq!(awaiters.waiting(), 0);
+
+        let pending = awaiters.message(filter());
+        let canceller = pending.canceller();
+        assert!(canceller.cancel());
+        assert!(!canceller.cancel());
+        assert!(matches!(
+            pending
+                .wait(LONG)
+                .await
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::PromptCancelled)
+        ));
+
+        // Dropping a wait stops it
+        drop(awaiters.message(filter()));
+        assert_eq!(awaiters.waiting(), 0);
+        assert!(!awaiters.route_message(&message(7, 20, "late")));
+    }
+
+    #[tokio::test]
+    pub async fn test_awaiter_prompted() {
+        let awaiters = Awaiters::new();
+        let filter = || MessageFilter::new(UserId(7), ChannelId(20));
+
+        // A wait that ended before doesn't count
+        drop(awaiters.message(filter()));
+        let prompted = timeout(Duration::from_millis(10), awaiters.prompted()).await;
+        assert!(prompted.is_err());
+
+        let prompt = async {
+            delay_for(Duration::from_millis(10)).await;
+            awaiters.message(filter())
+        };
+        let (_, pending) = tokio::join!(awaiters.prompted(), prompt);
+        assert_eq!(awaiters.waiting(), 1);
+        timeout(LONG, awaiters.prompted()).await.unwrap();
+        drop(pending);
+    }
+
+    #[tokio::test]
+    pub async fn test_wizard() {
+        let awaiters = Awaiters::new();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let author = CmdUser {
+            id: UserId(7),
+            name: String::from("ferris"),
+            bot: false,
+        };
+        let ctx = TerminalContext::new(author, "roles")
+            .with_channel(ChannelId(20))
+            .with_output(output.clone());
+        let wizard = Wizard::new(LONG)
+            .step("Which role?", UnsignedMatcher)
+            .step("Why?", RestMatcher)
+            .cancel_word("cancel");
+
+        let answers = async {
+            answer(&awaiters, message(7, 20, "admin")).await;
+            answer(&awaiters, message(7, 20, "2")).await;
+            answer(&awaiters, message(7, 20, "  to help ")).await;
+        };
+        let (answers, _) = tokio::join!(wizard.run(&ctx, &awaiters), answers);
+        assert_eq!(answers.unwrap(), vec!["2", "to help"]);
+        assert_eq!(
+            String::from_utf8(output.lock().unwrap().clone()).unwrap(),
+            "Which role?\nWhich role?\nWhy?\n"
+        );
+
+        let cancel = answer(&awaiters, message(7, 20, "Cancel"));
+        let (answers, _) = tokio::join!(wizard.run(&ctx, &awaiters), cancel);
+        assert!(matches!(
+            answers.unwrap_err().downcast_ref::<CoreError>(),
+            Some(CoreError::PromptCancelled)
+        ));
+        assert_eq!(awaiters.waiting(), 0);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::config::{reload_command, LiveConfig};
+use crate::locale::locale_command;
+use crate::module_manager::Module;
+use crate::prefix::{prefix_command, PrefixResolver};
+use crate::shards::shards_command;
+use crate::shutdown::{shutdown_commands, Shutdown};
+use crate::toggles::{toggle_commands, Toggles};
+use ffirs_commands::{
+    cmd_args::CmdArgs,
+    cmd_creator::CmdCreator,
+    cmd_handler::CmdHandler,
+    cmd_tree::{CmdNode, Usage},
+    cmd_tree_builder::CmdTreeBuilder,
+    context::{CmdContext, Embed},
+    error::CmdResult,
+    locale::Locales,
+};
+use serenity::async_trait;
+use std::sync::Arc;
+
+/// Commands administrating the bot itself:
+/// `shutdown`, `restart`, `shards`, `reload`, `prefix`, `locale`, `module` and `command`.
+pub struct CoreModule {
+    config: Arc<LiveConfig>,
+    prefixes: Arc<PrefixResolver>,
+    locales: Arc<Locales>,
+    toggles: Arc<Toggles>,
+    shutdown: Arc<Shutdown>,
+}
+
+impl CoreModule {
+    pub fn new(
+        config: Arc<LiveConfig>,
+        prefixes: Arc<PrefixResolver>,
+        locales: Arc<Locales>,
+        toggles: Arc<Toggles>,
+        shutdown: Arc<Shutdown>,
+    ) -> Self {
+        Self {
+            config,
+            prefixes,
+            locales,
+            toggles,
+            shutdown,
+        }
+    }
+}
+
+#[async_trait]
+impl Module for CoreModule {
+    fn name(&self) -> &str {
+        "core"
+    }
+
+    fn can_be_disabled(&self) -> bool {
+        false
+    }
+
+    fn register_commands(&self, builder: &mut CmdTreeBuilder) -> CmdResult<()> {
+        for command in shutdown_commands(self.shutdown.clone(), self.locales.clone())? {
+            builder.command(command)?;
+        }
+        builder
+            .command(shards_command(self.locales.clone())?)?
+            .command(reload_command(self.config.clone(), self.locales.clone())?)?
+            .command(prefix_command(self.prefixes.clone())?)?
+            .command(locale_command(self.locales.clone())?)?;
+        for command in toggle_commands(self.toggles.clone(), self.locales.clone())? {
+            builder.command(command)?;
+        }
+        Ok(())
+    }
+}
+
+/// `help`: list the commands with their description, in the language of the author.
+pub struct HelpCmd {
+    locales: Arc<Locales>,
+    usages: Vec<Usage>,
+}
+
+impl HelpCmd {
+    /// The help lines, `usage - description`.
+    pub fn lines(&self, locale: &str) -> Vec<String> {
+        self.usages
+            .iter()
+            .map(|usage| {
+                let key = usage
+                    .description
+                    .as_deref()
+                    .unwrap_or("help-no-description");
+                format!("`{}` - {}", usage.line, self.locales.tr(locale, key, &[]))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl CmdHandler for HelpCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, _args: &mut CmdArgs) -> CmdResult<()> {
+        let locale = self.locales.locale_of(ctx.author().id, ctx.guild());
+        let title = self.locales.tr(&locale, "help-title", &[]);
+        let lines = self.lines(&locale);
+
+        let embed = Embed::new().title(title).description(lines.join("\n"));
+        ctx.send_embed(&embed).await?;
+
+        Ok(())
+    }
+}
+
+/// The help of `commands` and of itself.
+pub fn help_command(locales: Arc<Locales>, commands: &[CmdNode]) -> CmdResult<CmdNode> {
+    let mut usages = commands
+        .iter()
+        .flat_map(|command| command.usages(&[], usize::MAX))
+        .collect::<Vec<_>>();
+    usages.push(Usage {
+        line: String::from("help"),
+        description: Some(String::from("help-description")),
+    });
+
+    let mut help = CmdCreator::create_cmd("help", HelpCmd { locales, usages })?;
+    help.description = Some("help-description".into());
+
+    Ok(help)
+}
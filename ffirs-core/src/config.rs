@@ -0,0 +1,558 @@
+use crate::error::CoreError;
+use crate::prefix::MAX_PREFIX_LEN;
+use crate::shards::Sharding;
+use crate::LOG_LEVEL;
+use ffirs_commands::cmd_args::CmdArgs;
+use ffirs_commands::cmd_creator::CmdCreator;
+use ffirs_commands::cmd_handler::CmdHandler;
+use ffirs_commands::cmd_tree::CmdNode;
+use ffirs_commands::context::CmdContext;
+use ffirs_commands::error::CmdResult;
+use ffirs_commands::locale::Locales;
+use ffirs_commands::perms::{PermLevel, PermRequirement};
+use log::{error, info, warn, LevelFilter};
+use serde::Deserialize;
+use serenity::{async_trait, model::id::UserId};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+/// Environment variable giving the path of the configuration file.
+pub const CONFIG_PATH_VAR: &str = "FFIRS_CONFIG";
+pub const DEFAULT_CONFIG_PATH: &str = "ffirs.toml";
+pub const DEFAULT_DATABASE_PATH: &str = "ffirs.db";
+
+/// Settings of a module, its `[module.<name>]` table.
+pub type ModuleSettings = toml::value::Table;
+
+/// Everything the bot reads from its configuration file.
+/// Any top level value can be overridden by an `FFIRS_<KEY>` environment variable,
+/// lists being separated by commas, and any module setting by `FFIRS_MODULE_<NAME>_<KEY>`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub token: String,
+    pub owners: Vec<u64>,
+    pub prefix: String,
+    pub log_level: String,
+    /// Path of the SQLite database, `:memory:` to keep nothing.
+    pub database: String,
+    /// Modules to load, all of them when empty.
+    pub modules: Vec<String>,
+    pub module: BTreeMap<String, ModuleSettings>,
+    pub sharding: Sharding,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            token: String::new(),
+            owners: Vec::new(),
+            prefix: String::from("!"),
+            log_level: LOG_LEVEL.to_string(),
+            database: String::from(DEFAULT_DATABASE_PATH),
+            modules: Vec::new(),
+            module: BTreeMap::new(),
+            sharding: Sharding::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(source: &str) -> CmdResult<Self> {
+        toml::from_str(source).map_err(|err| {
+            CoreError::InvalidConfig {
+                key: String::from("file"),
+                message: err.to_string(),
+            }
+            .into()
+        })
+    }
+
+    /// Read `path`, the defaults if it doesn't exist, then apply the overrides of `vars`
+    /// and validate the result.
+    pub fn load<I: IntoIterator<Item = (String, String)>>(path: &Path, vars: I) -> CmdResult<Self> {
+        let mut config = match fs::read_to_string(path) {
+            Ok(source) => Self::parse(&source)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => Self::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        config.apply_env(vars)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Override the values given by `FFIRS_*` variables.
+    ///
+    /// In `FFIRS_MODULE_<NAME>_<KEY>`, the name of the module goes up to the first underscore
+    /// and the value is read as TOML, as a string when it isn't valid TOML.
+    pub fn apply_env<I: IntoIterator<Item = (String, String)>>(
+        &mut self,
+        vars: I,
+    ) -> CmdResult<()> {
+        let list = |value: &str| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+
+        for (key, value) in vars {
+            match key.as_str() {
+                "FFIRS_TOKEN" => self.token = value,
+                "FFIRS_PREFIX" => self.prefix = value,
+                "FFIRS_LOG_LEVEL" => self.log_level = value,
+                "FFIRS_DATABASE" => self.database = value,
+                "FFIRS_MODULES" => self.modules = list(&value),
+                "FFIRS_SHARDING" => {
+                    self.sharding = value
+                        .parse()
+                        .map_err(|message| CoreError::InvalidConfig { key, message })?;
+                }
+                "FFIRS_OWNERS" => {
+                    self.owners = list(&value)
+                        .iter()
+                        .map(|owner| owner.parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| CoreError::InvalidConfig {
+                            key,
+                            message: format!("expected user ids, got {}", value),
+                        })?;
+                }
+                _ => {
+                    if let Some(setting) = key.strip_prefix("FFIRS_MODULE_") {
+                        self.apply_module_env(setting, value);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_module_env(&mut self, setting: &str, value: String) {
+        let (module, key) = match setting.find('_') {
+            Some(split) if split > 0 && split + 1 < setting.len() => {
+                (&setting[..split], &setting[split + 1..])
+            }
+            _ => return,
+        };
+
+        let value = toml::from_str::<ModuleSettings>(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+            .unwrap_or(toml::Value::String(value));
+        self.module
+            .entry(module.to_lowercase())
+            .or_default()
+            .insert(key.to_lowercase(), value);
+    }
+
+    pub fn validate(&self) -> CmdResult<()> {
+        let invalid = |key: &str, message: &str| {
+            Err(CoreError::InvalidConfig {
+                key: key.to_owned(),
+                message: message.to_owned(),
+            }
+            .into())
+        };
+
+        if self.token.trim().is_empty() {
+            return invalid("token", "missing, set it in the file or with FFIRS_TOKEN");
+        }
+        if self.prefix.is_empty() || self.prefix.contains(char::is_whitespace) {
+            return invalid("prefix", "must be non-empty and without spaces");
+        }
+        if self.prefix.chars().count() > MAX_PREFIX_LEN {
+            return invalid("prefix", &format!("at most {} characters", MAX_PREFIX_LEN));
+        }
+        if self.database.trim().is_empty() {
+            return invalid("database", "missing, use :memory: to keep nothing");
+        }
+        if self.log_level.parse::<LevelFilter>().is_err() {
+            return invalid(
+                "log_level",
+                "expected off, error, warn, info, debug or trace",
+            );
+        }
+        if let Err(message) = self.sharding.validate() {
+            return invalid("sharding", &message);
+        }
+
+        Ok(())
+    }
+
+    pub fn level(&self) -> LevelFilter {
+        self.log_level.parse().unwrap_or(LOG_LEVEL)
+    }
+
+    pub fn owners(&self) -> impl Iterator<Item = UserId> + '_ {
+        self.owners.iter().map(|&owner| UserId(owner))
+    }
+
+    /// The settings of module `name`, empty if it has none.
+    pub fn module_settings(&self, name: &str) -> ModuleSettings {
+        self.module.get(name).cloned().unwrap_or_default()
+    }
+}
+
+type Validator = Box<dyn Fn(&Config) -> CmdResult<()> + Send + Sync>;
+type Subscriber = Box<dyn Fn(&Config) + Send + Sync>;
+
+/// The configuration in use, replaced when the file is reloaded.
+pub struct LiveConfig {
+    path: PathBuf,
+    current: RwLock<Arc<Config>>,
+    validators: RwLock<Vec<Validator>>,
+    subscribers: RwLock<Vec<Subscriber>>,
+}
+
+impl LiveConfig {
+    /// Load the file pointed by `FFIRS_CONFIG`, `ffirs.toml` by default.
+    pub fn from_env() -> CmdResult<Self> {
+        let path = env::var(CONFIG_PATH_VAR).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_owned());
+        Self::load(path)
+    }
+
+    pub fn load<P: Into<PathBuf>>(path: P) -> CmdResult<Self> {
+        let path = path.into();
+        let config = Config::load(&path, env::vars())?;
+
+        Ok(Self::new(path, config))
+    }
+
+    pub fn new<P: Into<PathBuf>>(path: P, config: Config) -> Self {
+        Self {
+            path: path.into(),
+            current: RwLock::new(Arc::new(config)),
+            validators: RwLock::default(),
+            subscribers: RwLock::default(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self) -> Arc<Config> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Have `validator` check every configuration before it is applied, any error refusing it.
+    pub fn validate_with<F: Fn(&Config) -> CmdResult<()> + Send + Sync + 'static>(
+        &self,
+        validator: F,
+    ) {
+        self.validators.write().unwrap().push(Box::new(validator));
+    }
+
+    /// Call `subscriber` with every configuration applied from now on.
+    pub fn subscribe<F: Fn(&Config) + Send + Sync + 'static>(&self, subscriber: F) {
+        self.subscribers.write().unwrap().push(Box::new(subscriber));
+    }
+
+    /// Read the file again and apply it if it is valid.
+    pub fn reload(&self) -> CmdResult<Vec<&'static str>> {
+        self.reload_with(env::vars())
+    }
+
+    /// Read the file again with the overrides of `vars` instead of the environment.
+    pub fn reload_with<I: IntoIterator<Item = (String, String)>>(
+        &self,
+        vars: I,
+    ) -> CmdResult<Vec<&'static str>> {
+        let config = Config::load(&self.path, vars)?;
+        self.apply(config)
+    }
+
+    /// Check `config` with every validator, then hand it to the subscribers and keep it.
+    /// Nothing is applied when it is refused.
+    /// Gives back the keys which changed but only apply after a restart.
+    pub fn apply(&self, config: Config) -> CmdResult<Vec<&'static str>> {
+        config.validate()?;
+        for validator in self.validators.read().unwrap().iter() {
+            validator(&config)?;
+        }
+
+        let mut current = self.current.write().unwrap();
+        for subscriber in self.subscribers.read().unwrap().iter() {
+            subscriber(&config);
+        }
+
+        let mut restart = Vec::new();
+        if current.token != config.token {
+            restart.push("token");
+        }
+        if current.database != config.database {
+            restart.push("database");
+        }
+        if current.modules != config.modules {
+            restart.push("modules");
+        }
+        if current.sharding != config.sharding {
+            restart.push("sharding");
+        }
+
+        *current = Arc::new(config);
+        Ok(restart)
+    }
+}
+
+/// Reload `config` every time the process receives SIGHUP.
+#[cfg(unix)]
+pub fn reload_on_hangup(config: Arc<LiveConfig>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(err) => {
+                warn!("Can't reload the configuration on SIGHUP: {}", err);
+                return;
+            }
+        };
+
+        while hangups.recv().await.is_some() {
+            match config.reload() {
+                Ok(restart) if restart.is_empty() => info!("Configuration reloaded"),
+                Ok(restart) => warn!(
+                    "Configuration reloaded, restart to apply {}",
+                    restart.join(", ")
+                ),
+                Err(err) => error!("Failed to reload the configuration: {}", err),
+            }
+        }
+    });
+}
+
+/// `reload`: read the configuration file again, for the bot owners.
+pub struct ReloadCmd {
+    config: Arc<LiveConfig>,
+    locales: Arc<Locales>,
+}
+
+#[async_trait]
+impl CmdHandler for ReloadCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, _args: &mut CmdArgs) -> CmdResult<()> {
+        let restart = self.config.reload()?;
+
+        let locale = self.locales.locale_of(ctx.author().id, ctx.guild());
+        let mut reply = self.locales.tr(&locale, "config-reloaded", &[]);
+        if !restart.is_empty() {
+            let keys = [("keys", restart.join(", "))];
+            reply.push('\n');
+            reply.push_str(&self.locales.tr(&locale, "config-restart-needed", &keys));
+        }
+        ctx.reply(&reply).await?;
+
+        Ok(())
+    }
+}
+
+pub fn reload_command(config: Arc<LiveConfig>, locales: Arc<Locales>) -> CmdResult<CmdNode> {
+    let mut reload = CmdCreator::create_cmd("reload", ReloadCmd { config, locales })?;
+    reload.requirement = Some(PermRequirement::level(PermLevel::BOT_OWNER));
+    reload.description = Some("reload-description".into());
+
+    Ok(reload)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, LiveConfig};
+    use crate::error::CoreError;
+    use crate::shards::Sharding;
+    use log::LevelFilter;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const FULL: &str = r#"
+        token = "abc"
+        owners = [1, 2]
+        prefix = "?"
+        log_level = "warn"
+        modules = ["core", "reminders"]
+
+        [module.reminders]
+        max_per_user = 10
+    "#;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn invalid_key(config: &Config) -> String {
+        match config.validate().unwrap_err().downcast_ref::<CoreError>() {
+            Some(CoreError::InvalidConfig { key, .. }) => key.clone(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    pub fn test_config_parse() {
+        let config = Config::parse(FULL).unwrap();
+
+        assert_eq!(config.token, "abc");
+        assert_eq!(config.owners, vec![1, 2]);
+        assert_eq!(config.prefix, "?");
+        assert_eq!(config.level(), LevelFilter::Warn);
+        assert_eq!(config.modules, vec!["core", "reminders"]);
+        assert_eq!(
+            config.module_settings("reminders")["max_per_user"].as_integer(),
+            Some(10)
+        );
+        assert!(config.module_settings("core").is_empty());
+
+        // Missing keys keep their default
+        let config = Config::parse("token = \"abc\"").unwrap();
+        assert_eq!(config.prefix, "!");
+        assert_eq!(config.database, "ffirs.db");
+        assert!(config.modules.is_empty());
+
+        assert!(matches!(
+            Config::parse("tokn = \"abc\"")
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::InvalidConfig { .. })
+        ));
+        assert!(matches!(
+            Config::parse("owners = \"me\"")
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::InvalidConfig { .. })
+        ));
+    }
+
+    #[test]
+    pub fn test_config_env() {
+        let mut config = Config::parse(FULL).unwrap();
+        config
+            .apply_env(vars(&[
+                ("FFIRS_TOKEN", "xyz"),
+                ("FFIRS_OWNERS", "3, 4"),
+                ("FFIRS_MODULES", "core,"),
+                ("FFIRS_SHARDING", "0-1/4"),
+                ("PATH", "/bin"),
+            ]))
+            .unwrap();
+
+        assert_eq!(config.token, "xyz");
+        assert_eq!(config.owners, vec![3, 4]);
+        assert_eq!(config.modules, vec!["core"]);
+        assert_eq!(config.prefix, "?");
+        assert_eq!(
+            config.sharding,
+            Sharding::Range {
+                first: 0,
+                last: 1,
+                total: 4
+            }
+        );
+
+        config
+            .apply_env(vars(&[
+                ("FFIRS_MODULE_REMINDERS_MAX_PER_USER", "5"),
+                ("FFIRS_MODULE_REMINDERS_GREETING", "hi there"),
+                ("FFIRS_MODULE_CORE_", "ignored"),
+            ]))
+            .unwrap();
+        let reminders = config.module_settings("reminders");
+        assert_eq!(reminders["max_per_user"].as_integer(), Some(5));
+        assert_eq!(reminders["greeting"].as_str(), Some("hi there"));
+        assert!(config.module_settings("core").is_empty());
+
+        match config
+            .apply_env(vars(&[("FFIRS_OWNERS", "me")]))
+            .unwrap_err()
+            .downcast_ref::<CoreError>()
+        {
+            Some(CoreError::InvalidConfig { key, .. }) => assert_eq!(key, "FFIRS_OWNERS"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    pub fn test_config_validate() {
+        let valid = Config::parse(FULL).unwrap();
+        assert!(valid.validate().is_ok());
+
+        let mut config = valid.clone();
+        config.token = String::from(" ");
+        assert_eq!(invalid_key(&config), "token");
+
+        let mut config = valid.clone();
+        config.prefix = String::from("a b");
+        assert_eq!(invalid_key(&config), "prefix");
+
+        let mut config = valid.clone();
+        config.prefix = "!".repeat(17);
+        assert_eq!(invalid_key(&config), "prefix");
+
+        let mut config = valid.clone();
+        config.database = String::new();
+        assert_eq!(invalid_key(&config), "database");
+
+        let mut config = valid.clone();
+        config.log_level = String::from("verbose");
+        assert_eq!(invalid_key(&config), "log_level");
+
+        let mut config = valid;
+        config.sharding = Sharding::Fixed { total: 0 };
+        assert_eq!(invalid_key(&config), "sharding");
+    }
+
+    #[test]
+    pub fn test_live_config_reload() {
+        let path = std::env::temp_dir().join(format!("ffirs-config-{}.toml", std::process::id()));
+        fs::write(&path, FULL).unwrap();
+
+        let live = LiveConfig::new(&path, Config::load(&path, Vec::new()).unwrap());
+        live.validate_with(|config| match config.prefix.as_str() {
+            "!!" => Err(CoreError::InvalidConfig {
+                key: "prefix".into(),
+                message: "refused".into(),
+            }
+            .into()),
+            _ => Ok(()),
+        });
+        let applied = Arc::new(AtomicUsize::new(0));
+        let counter = applied.clone();
+        live.subscribe(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        fs::write(&path, FULL.replace("\"?\"", "\"$\"")).unwrap();
+        assert!(live.reload_with(Vec::new()).unwrap().is_empty());
+        assert_eq!(live.get().prefix, "$");
+
+        fs::write(&path, FULL.replace("\"abc\"", "\"def\"")).unwrap();
+        assert_eq!(live.reload_with(Vec::new()).unwrap(), vec!["token"]);
+
+        // The overrides given replace the environment
+        let overrides = vars(&[("FFIRS_PREFIX", "%")]);
+        assert!(live.reload_with(overrides).unwrap().is_empty());
+        assert_eq!(live.get().prefix, "%");
+
+        // Invalid files and refused configurations leave the current one in place,
+        // without reaching any subscriber
+        fs::write(&path, "prefix = \"\"").unwrap();
+        assert!(live.reload_with(Vec::new()).is_err());
+        fs::write(&path, FULL.replace("\"?\"", "\"!!\"")).unwrap();
+        assert!(live.reload_with(Vec::new()).is_err());
+        assert_eq!(live.get().token, "def");
+        assert_eq!(live.get().prefix, "%");
+        assert_eq!(applied.load(Ordering::SeqCst), 3);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
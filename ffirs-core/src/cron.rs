@@ -0,0 +1,222 @@
+use crate::error::CoreError;
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc};
+use ffirs_commands::error::{CmdError, CmdResult};
+use std::str::FromStr;
+
+/// How far `Cron::next_after` looks, expressions like `0 0 30 2 *` never match.
+const SEARCH_DAYS: i64 = 366 * 5;
+
+fn invalid(expression: &str, message: &str) -> CmdError {
+    CoreError::InvalidSchedule {
+        message: format!("`{}`, {}", expression, message),
+    }
+    .into()
+}
+
+/// A cron expression, `minute hour day-of-month month day-of-week` in UTC.
+///
+/// Fields take `*`, values, ranges (`1-5`), lists (`1,15`) and steps (`*/15`, `10-30/5`).
+/// Sunday is either `0` or `7`. `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly`
+/// are shorthands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+}
+
+impl Cron {
+    fn field(expression: &str, field: &str, min: u32, max: u32) -> CmdResult<u64> {
+        let mut bits = 0;
+
+        for part in field.split(',') {
+            let mut split = part.splitn(2, '/');
+            let range = split.next().unwrap_or_default();
+            let step = match split.next() {
+                Some(step) => step
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|&step| step > 0)
+                    .ok_or_else(|| invalid(expression, &format!("invalid step `{}`", step)))?,
+                None => 1,
+            };
+
+            let value = |value: &str| {
+                value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|value| (min..=max).contains(value))
+                    .ok_or_else(|| {
+                        let message = format!("`{}` is not between {} and {}", value, min, max);
+                        invalid(expression, &message)
+                    })
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                _ => match range.find('-') {
+                    Some(dash) => (value(&range[..dash])?, value(&range[dash + 1..])?),
+                    // `5/15` goes from 5 to the end
+                    None if step > 1 => (value(range)?, max),
+                    None => (value(range)?, value(range)?),
+                },
+            };
+            if start > end {
+                return Err(invalid(expression, &format!("empty range `{}`", range)));
+            }
+
+            for value in (start..=end).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+
+        Ok(bits)
+    }
+
+    fn full(min: u32, max: u32) -> u64 {
+        (min..=max).fold(0, |bits, value| bits | 1 << value)
+    }
+
+    fn has(bits: u64, value: u32) -> bool {
+        bits & (1 << value) != 0
+    }
+
+    /// Like cron, a day matches either restricted day field when both are.
+    fn matches_day(&self, time: DateTime<Utc>) -> bool {
+        let day = Self::has(self.days, time.day());
+        let weekday = Self::has(self.weekdays, time.weekday().num_days_from_sunday());
+
+        match (
+            self.days == Self::full(1, 31),
+            self.weekdays == Self::full(0, 6),
+        ) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// The first minute strictly after `after` matching the expression.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let limit = after + Duration::days(SEARCH_DAYS);
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+
+        while time <= limit {
+            if !Self::has(self.months, time.month()) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = Utc.ymd(year, month, 1).and_hms(0, 0, 0);
+            } else if !self.matches_day(time) {
+                time = (time.date() + Duration::days(1)).and_hms(0, 0, 0);
+            } else if !Self::has(self.hours, time.hour()) {
+                time = time.with_minute(0)? + Duration::hours(1);
+            } else if !Self::has(self.minutes, time.minute()) {
+                time = time + Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+
+        None
+    }
+}
+
+impl FromStr for Cron {
+    type Err = CmdError;
+
+    fn from_str(expression: &str) -> CmdResult<Self> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" => "0 0 1 1 *",
+            expanded => expanded,
+        };
+        let fields = expanded.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(invalid(expression, "expected 5 fields"));
+        }
+
+        let weekdays = Self::field(expression, fields[4], 0, 7)?;
+        Ok(Self {
+            minutes: Self::field(expression, fields[0], 0, 59)?,
+            hours: Self::field(expression, fields[1], 0, 23)?,
+            days: Self::field(expression, fields[2], 1, 31)?,
+            months: Self::field(expression, fields[3], 1, 12)?,
+            // Sunday is also 7
+            weekdays: (weekdays | weekdays >> 7) & Self::full(0, 6),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cron::Cron;
+    use crate::error::CoreError;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    pub fn test_cron_parse() {
+        assert_eq!(
+            "@daily".parse::<Cron>().unwrap(),
+            "0 0 * * *".parse().unwrap()
+        );
+        assert_eq!(
+            "0 0 * * 7".parse::<Cron>().unwrap(),
+            "0 0 * * 0".parse().unwrap()
+        );
+
+        for invalid in &[
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(matches!(
+                invalid
+                    .parse::<Cron>()
+                    .unwrap_err()
+                    .downcast_ref::<CoreError>(),
+                Some(CoreError::InvalidSchedule { .. })
+            ));
+        }
+    }
+
+    #[test]
+    pub fn test_cron_next() {
+        // A Saturday
+        let after = Utc.ymd(2020, 6, 6).and_hms(12, 7, 30);
+        let next = |expression: &str| expression.parse::<Cron>().unwrap().next_after(after);
+
+        assert_eq!(
+            next("*/15 * * * *"),
+            Some(Utc.ymd(2020, 6, 6).and_hms(12, 15, 0))
+        );
+        assert_eq!(
+            next("* * * * *"),
+            Some(Utc.ymd(2020, 6, 6).and_hms(12, 8, 0))
+        );
+        assert_eq!(
+            next("0 9 * * 1-5"),
+            Some(Utc.ymd(2020, 6, 8).and_hms(9, 0, 0))
+        );
+        assert_eq!(
+            next("30 8 1 * *"),
+            Some(Utc.ymd(2020, 7, 1).and_hms(8, 30, 0))
+        );
+        assert_eq!(
+            next("0 0 29 2 *"),
+            Some(Utc.ymd(2024, 2, 29).and_hms(0, 0, 0))
+        );
+        // Either the 15th or a Monday
+        assert_eq!(
+            next("0 0 15 * 1"),
+            Some(Utc.ymd(2020, 6, 8).and_hms(0, 0, 0))
+        );
+        assert_eq!(next("0 0 30 2 *"), None);
+    }
+}
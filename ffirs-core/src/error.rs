@@ -0,0 +1,135 @@
+use ffirs_commands::error::LocalizedError;
+use std::any::Any;
+use thiserror::Error;
+
+/// Errors of the core modules, described by the messages of the core catalogs.
+/// They reach the commands as `CmdError::Module`.
+#[derive(Error, Debug)]
+pub enum CoreError {
+    #[error("{name} is disabled here")]
+    Disabled { name: String },
+    #[error("Unknown module {name}")]
+    UnknownModule { name: String },
+    #[error("Module {name} can't be disabled")]
+    ModuleLocked { name: String },
+    #[error("Invalid schedule: {message}")]
+    InvalidSchedule { message: String },
+    #[error("{day} has no time of day")]
+    MissingTimeOfDay { day: String },
+    #[error("Unknown timezone {timezone}")]
+    UnknownTimezone { timezone: String },
+    #[error("Unknown reminder {id}")]
+    UnknownReminder { id: u64 },
+    #[error("Too many reminders, at most {max} at a time")]
+    TooManyReminders { max: usize },
+    #[error("No answer after {seconds} seconds")]
+    PromptTimedOut { seconds: u64 },
+    #[error("Prompt cancelled")]
+    PromptCancelled,
+    #[error("Invalid configuration, {key}: {message}")]
+    InvalidConfig { key: String, message: String },
+
+    #[error("Module {name} registered twice")]
+    DuplicateModule { name: String },
+    #[error("Module {module} depends on unknown module {dependency}")]
+    UnknownDependency { module: String, dependency: String },
+    #[error("Circular dependency between modules: {modules}")]
+    DependencyCycle { modules: String },
+
+    #[error("Storage error: {message}")]
+    Storage { message: String },
+    #[error("Missing shared data: {name}")]
+    MissingData { name: String },
+}
+
+impl LocalizedError for CoreError {
+    fn message(&self) -> (&'static str, Vec<(&'static str, String)>) {
+        match self {
+            CoreError::Disabled { name } => ("error-disabled", vec![("name", name.clone())]),
+            CoreError::UnknownModule { name } => {
+                ("error-unknown-module", vec![("name", name.clone())])
+            }
+            CoreError::ModuleLocked { name } => {
+                ("error-module-locked", vec![("name", name.clone())])
+            }
+            CoreError::InvalidSchedule { message } => {
+                ("error-invalid-schedule", vec![("message", message.clone())])
+            }
+            CoreError::MissingTimeOfDay { day } => {
+                ("error-missing-time-of-day", vec![("day", day.clone())])
+            }
+            CoreError::UnknownTimezone { timezone } => (
+                "error-unknown-timezone",
+                vec![("timezone", timezone.clone())],
+            ),
+            CoreError::UnknownReminder { id } => {
+                ("error-unknown-reminder", vec![("id", id.to_string())])
+            }
+            CoreError::TooManyReminders { max } => {
+                ("error-too-many-reminders", vec![("max", max.to_string())])
+            }
+            CoreError::PromptTimedOut { seconds } => (
+                "error-prompt-timed-out",
+                vec![("seconds", seconds.to_string())],
+            ),
+            CoreError::PromptCancelled => ("error-prompt-cancelled", vec![]),
+            CoreError::InvalidConfig { key, message } => (
+                "error-invalid-config",
+                vec![("key", key.clone()), ("message", message.clone())],
+            ),
+            _ => ("error-internal", vec![]),
+        }
+    }
+
+    fn is_internal(&self) -> bool {
+        matches!(
+            self,
+            CoreError::DuplicateModule { .. }
+                | CoreError::UnknownDependency { .. }
+                | CoreError::DependencyCycle { .. }
+                | CoreError::Storage { .. }
+                | CoreError::MissingData { .. }
+        )
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::CoreError;
+    use crate::locale::core_locales;
+    use ffirs_commands::diagnostic::Diagnostic;
+    use ffirs_commands::error::CmdError;
+
+    #[test]
+    pub fn test_core_error_messages() {
+        let locales = core_locales().unwrap();
+        let error = CmdError::from(CoreError::TooManyReminders { max: 3 });
+
+        assert_eq!(
+            locales.error("en", &error),
+            "You already have 3 reminders, cancel one first"
+        );
+        assert_eq!(
+            locales.error("fr", &error),
+            "Vous avez déjà 3 rappels, annulez-en un d'abord"
+        );
+        assert!(matches!(
+            error.downcast_ref::<CoreError>(),
+            Some(CoreError::TooManyReminders { max: 3 })
+        ));
+        assert!(!Diagnostic::is_internal(&error));
+
+        let error = CmdError::from(CoreError::Storage {
+            message: String::from("disk full"),
+        });
+        assert!(Diagnostic::is_internal(&error));
+        assert_eq!(
+            locales.error("en", &error),
+            "Something went wrong while running this command."
+        );
+    }
+}
@@ -1,28 +1,138 @@
-use anyhow::Result;
-use log::error;
+use crate::awaiter::{Awaiters, IncomingMessage, IncomingReaction};
+use crate::module_manager::ModuleManager;
+use crate::prefix::PrefixResolver;
+use crate::shutdown::Shutdown;
+use crate::state::SharedState;
+use crate::transport::DiscordContext;
+use ffirs_commands::{
+    cmd_manager::CmdManager, cmd_tree::CmdTree, context::CmdContext, diagnostic::Diagnostic,
+    locale::Locales,
+};
+use log::{debug, error, warn};
 use serenity::{
     async_trait,
     client::{Context, EventHandler},
-    model::channel::Message,
+    model::{
+        channel::{Message, Reaction},
+        gateway::Ready,
+    },
 };
+use std::sync::Arc;
+
+/// Everything the handler needs to answer events
+pub struct Services {
+    pub manager: Arc<CmdManager>,
+    pub tree: Arc<CmdTree>,
+    pub prefixes: Arc<PrefixResolver>,
+    pub locales: Arc<Locales>,
+    pub modules: Arc<ModuleManager>,
+    pub shutdown: Arc<Shutdown>,
+    pub awaiters: Arc<Awaiters>,
+    pub state: SharedState,
+}
 
-pub struct Handler;
+pub struct Handler {
+    manager: Arc<CmdManager>,
+    tree: Arc<CmdTree>,
+    prefixes: Arc<PrefixResolver>,
+    locales: Arc<Locales>,
+    modules: Arc<ModuleManager>,
+    shutdown: Arc<Shutdown>,
+    awaiters: Arc<Awaiters>,
+    state: SharedState,
+}
+
+impl Handler {
+    pub fn new(services: Services) -> Self {
+        let Services {
+            manager,
+            tree,
+            prefixes,
+            locales,
+            modules,
+            shutdown,
+            awaiters,
+            state,
+        } = services;
+        Self {
+            manager,
+            tree,
+            prefixes,
+            locales,
+            modules,
+            shutdown,
+            awaiters,
+            state,
+        }
+    }
+}
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn message(&self, ctx: Context, message: Message) {
-        async fn handler(ctx: Context, message: Message) -> Result<()> {
-            if message.content.starts_with("!stop") {
-                message.channel_id.say(ctx.http, "Stopping...").await?;
-            }
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        self.modules.ready(&ctx, &ready).await;
+    }
 
-            ctx.shard.shutdown_clean();
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        if reaction.user_id == ctx.cache.current_user_id().await {
+            return;
+        }
+        // Reactions someone waits for aren't for the modules
+        let reaction = IncomingReaction::from(&reaction);
+        if self.awaiters.route_reaction(&reaction) {
+            return;
+        }
 
-            Ok(())
+        self.modules.reaction_add(&reaction).await;
+    }
+
+    async fn message(&self, ctx: Context, message: Message) {
+        // Never answer bots, including ourselves
+        if message.author.bot {
+            return;
+        }
+        let bot = ctx.cache.current_user_id().await;
+        if message.author.id == bot {
+            return;
+        }
+
+        // Answers to a prompt of a running command aren't commands,
+        // the command may be what a shutdown is waiting for
+        if self
+            .awaiters
+            .route_message(&IncomingMessage::from(&message))
+        {
+            return;
         }
 
-        if let Err(err) = handler(ctx, message).await {
-            error!("{}", err);
+        // Nothing new starts once shutting down, what started is waited for
+        let _in_flight = match self.shutdown.enter() {
+            Some(in_flight) => in_flight,
+            None => return,
+        };
+        let cmd_ctx = DiscordContext::new(&ctx, &message, self.state.data().clone());
+        self.modules.message(&cmd_ctx).await;
+
+        let raw = match self.prefixes.strip(&message.content, message.guild_id, bot) {
+            Some(raw) if !raw.is_empty() => raw,
+            _ => return,
+        };
+
+        if let Err(err) = self.manager.dispatch(&self.tree, &cmd_ctx, raw).await {
+            if Diagnostic::is_internal(&err) {
+                error!("{}", err);
+            }
+            // Most likely not meant for the bot, like "!!" or a prefix shared with another bot
+            if Diagnostic::is_unknown_command(&err, raw, &self.tree) {
+                debug!("Ignored {:?}, not a command", raw);
+                return;
+            }
+
+            let locale = self.locales.locale_of(message.author.id, message.guild_id);
+            let diagnostic = Diagnostic::new(&err, raw, &self.tree, &self.locales, &locale);
+            if let Err(err) = cmd_ctx.send_embed(&diagnostic.embed()).await {
+                warn!("Failed to report a command error: {}", err);
+            }
         }
     }
 }
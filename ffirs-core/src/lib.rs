@@ -2,8 +2,23 @@
 
 use log::LevelFilter;
 
+pub mod awaiter;
+pub mod commands;
+pub mod config;
+pub mod cron;
+pub mod error;
 pub mod event_handler;
+pub mod locale;
 pub mod module_manager;
+pub mod prefix;
+pub mod reminders;
+pub mod scheduler;
+pub mod shards;
+pub mod shutdown;
+pub mod state;
+pub mod storage;
+pub mod toggles;
+pub mod transport;
 
 #[cfg(debug_assertions)]
 pub const LOG_LEVEL: LevelFilter = LevelFilter::Trace;
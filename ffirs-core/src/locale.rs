@@ -0,0 +1,118 @@
+use ffirs_commands::cmd_args::CmdArgs;
+use ffirs_commands::cmd_creator::CmdCreator;
+use ffirs_commands::cmd_handler::CmdHandler;
+use ffirs_commands::cmd_tree::CmdNode;
+use ffirs_commands::context::CmdContext;
+use ffirs_commands::error::{CmdError, CmdResult};
+use ffirs_commands::locale::{Catalog, Locales};
+use ffirs_commands::perms::{PermLevel, PermRequirement};
+use serenity::async_trait;
+use std::sync::Arc;
+
+const CORE_EN: &str = include_str!("../locales/en.ftl");
+const CORE_FR: &str = include_str!("../locales/fr.ftl");
+
+/// The catalogs of the command framework completed with the ones of the bot commands.
+pub fn core_locales() -> CmdResult<Locales> {
+    Ok(Locales::builtin()
+        .with_catalog("en", Catalog::parse(CORE_EN)?)
+        .with_catalog("fr", Catalog::parse(CORE_FR)?))
+}
+
+/// `locale <locale>`: choose the language the bot answers the author in.
+pub struct UserLocaleCmd {
+    locales: Arc<Locales>,
+}
+
+#[async_trait]
+impl CmdHandler for UserLocaleCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()> {
+        let locale = args.take::<String>("locale").unwrap_or_default();
+        self.locales.set_user_locale(ctx.author().id, &locale)?;
+
+        let locale = self.locales.locale_of(ctx.author().id, ctx.guild());
+        let reply = self
+            .locales
+            .tr(&locale, "locale-user-set", &[("locale", locale.clone())]);
+        ctx.reply(&reply).await?;
+
+        Ok(())
+    }
+}
+
+/// `locale guild <locale>`: choose the language of a guild, for members without one.
+pub struct GuildLocaleCmd {
+    locales: Arc<Locales>,
+}
+
+#[async_trait]
+impl CmdHandler for GuildLocaleCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()> {
+        let guild = ctx.guild().ok_or(CmdError::GuildOnly)?;
+        let locale = args.take::<String>("locale").unwrap_or_default();
+        self.locales.set_guild_locale(guild, &locale)?;
+
+        let locale = locale.to_lowercase();
+        let reply = self
+            .locales
+            .tr(&locale, "locale-guild-set", &[("locale", locale.clone())]);
+        ctx.reply(&reply).await?;
+
+        Ok(())
+    }
+}
+
+pub fn locale_command(locales: Arc<Locales>) -> CmdResult<CmdNode> {
+    let mut locale = CmdCreator::create_cmd(
+        "locale <locale: Word>",
+        UserLocaleCmd {
+            locales: locales.clone(),
+        },
+    )?;
+    let mut guild = CmdCreator::create_cmd("guild <locale: Word>", GuildLocaleCmd { locales })?;
+    guild.requirement = Some(PermRequirement::level(PermLevel::ADMIN));
+    guild.description = Some("locale-guild-description".into());
+
+    // Tried first so `guild` isn't taken for a locale
+    locale.children.insert(0, guild);
+    locale.description = Some("locale-description".into());
+
+    Ok(locale)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::locale::{core_locales, CORE_EN, CORE_FR};
+    use ffirs_commands::locale::Catalog;
+
+    #[test]
+    pub fn test_core_catalogs() {
+        let locales = core_locales().unwrap();
+
+        assert_eq!(locales.tr("en", "help-title", &[]), "Commands");
+        assert_eq!(locales.tr("fr", "help-title", &[]), "Commandes");
+        // Framework messages are still there
+        assert_eq!(
+            locales.tr("fr", "hint-missing-arguments", &[]),
+            "Arguments manquants"
+        );
+        // Every message has its translation
+        for key in ["shutdown-description", "restart-started", "locale-user-set", "locale-guild-set"].iter() {
+            assert!(locales.lookup("fr", key, &[]) != locales.lookup("en", key, &[]));
+        }
+    }
+
+    #[test]
+    pub fn test_core_catalogs_complete() {
+        let en = Catalog::parse(CORE_EN).unwrap();
+        let fr = Catalog::parse(CORE_FR).unwrap();
+
+        // French also has aliases, English commands keep their names
+        let mut missing = en
+            .keys()
+            .filter(|key| fr.get(key).is_none())
+            .collect::<Vec<_>>();
+        missing.sort_unstable();
+        assert!(missing.is_empty(), "Missing in fr.ftl: {:?}", missing);
+    }
+}
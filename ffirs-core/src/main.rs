@@ -1,25 +1,181 @@
 use anyhow::Result;
-use dotenv_codegen::dotenv;
-use ffirs_core::{event_handler::Handler, LOG_LEVEL};
-use log::warn;
-use serenity::Client;
+use ffirs_commands::{
+    clock::SystemClock, cmd_manager::CmdManager, cooldown::Cooldowns, perms::PermResolver,
+};
+use ffirs_core::{
+    awaiter::Awaiters,
+    commands::{help_command, CoreModule},
+    config::LiveConfig,
+    event_handler::{Handler, Services},
+    locale::core_locales,
+    module_manager::ModuleManager,
+    prefix::{PrefixResolver, StoragePrefixStore},
+    reminders::ReminderModule,
+    scheduler::Scheduler,
+    shutdown::{restart, stop_on_signals, Shutdown, ShutdownKind},
+    state::SharedState,
+    storage::{Database, Storage, StorageCooldownStore, StorageLocaleStore, StoragePermStore},
+    toggles::{StorageToggleStore, Toggles},
+    transport::{DiscordMessenger, Messenger},
+};
+use log::{error, info, warn, LevelFilter};
+use serenity::{http::Http, Client};
 use simplelog::{Config, SimpleLogger, TermLogger, TerminalMode};
+use std::{iter, sync::Arc};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    dotenv::dotenv()?;
+    // Variables of a `.env` file override the configuration file, if there is one
+    dotenv::dotenv().ok();
+    let config = Arc::new(LiveConfig::from_env()?);
+    let current = config.get();
 
-    TermLogger::init(LOG_LEVEL, Config::default(), TerminalMode::Mixed).unwrap_or_else(|_| {
-        SimpleLogger::init(LOG_LEVEL, Config::default()).expect("Failed to setup a logger !");
-        warn!("Failed to setup TermLogger, using SimpleLogger.");
+    // The level is changed with `log::set_max_level`, the loggers must let everything through
+    TermLogger::init(LevelFilter::Trace, Config::default(), TerminalMode::Mixed).unwrap_or_else(
+        |_| {
+            SimpleLogger::init(LevelFilter::Trace, Config::default())
+                .expect("Failed to setup a logger !");
+            warn!("Failed to setup TermLogger, using SimpleLogger.");
+        },
+    );
+    log::set_max_level(current.level());
+
+    let token = current.token.clone();
+    let http = Arc::new(Http::new_with_token(&token));
+    let app_owner = http.get_current_application_info().await?.owner.id;
+    let owners = move |config: &ffirs_core::config::Config| {
+        config
+            .owners()
+            .chain(iter::once(app_owner))
+            .collect::<Vec<_>>()
+    };
+
+    let database = Arc::new(Database::open(&current.database)?);
+    let prefixes = Arc::new(
+        PrefixResolver::new(vec![current.prefix.clone()])
+            .with_store(Box::new(StoragePrefixStore::new(database.clone()))),
+    );
+    let perms = Arc::new(
+        PermResolver::with_owners(owners(&current))
+            .with_store(Box::new(StoragePermStore::new(database.clone()))),
+    );
+    let toggles =
+        Arc::new(Toggles::new().with_store(Box::new(StorageToggleStore::new(database.clone()))));
+    let manager = CmdManager::with_default_mappers()
+        .with_middleware(perms.clone())
+        .with_middleware(toggles.clone())
+        .with_middleware(Cooldowns::new(
+            Box::new(StorageCooldownStore::open(database.clone())?),
+            Arc::new(SystemClock),
+        ));
+    let locales =
+        Arc::new(core_locales()?.with_store(Box::new(StorageLocaleStore::new(database.clone()))));
+    let shutdown = Arc::new(Shutdown::default());
+    let scheduler = Arc::new(Scheduler::new(database.clone(), Arc::new(SystemClock))?);
+    let awaiters = Arc::new(Awaiters::new());
+    {
+        let database = database.clone();
+        shutdown.on_flush(move || database.flush());
+    }
+
+    let mut modules = ModuleManager::new()
+        .with_module(CoreModule::new(
+            config.clone(),
+            prefixes.clone(),
+            locales.clone(),
+            toggles.clone(),
+            shutdown.clone(),
+        ))
+        .with_module(ReminderModule::new());
+    modules.retain_enabled(&current.modules)?;
+    modules.check_settings(&current)?;
+    modules.configure(&current);
+    for migration in database.migrate(&modules.migrations()?)? {
+        info!(
+            "Migrated {} to version {}",
+            migration.module, migration.version
+        );
+    }
+
+    // Services modules and commands can reach, see `SharedState::of`
+    let state = SharedState::new();
+    state.insert(config.clone()).await;
+    state.insert(database.clone()).await;
+    state.insert(locales.clone()).await;
+    let messenger: Arc<dyn Messenger> = Arc::new(DiscordMessenger::new(http.clone()));
+    state.insert(messenger).await;
+    state.insert(http).await;
+    state.insert(shutdown.clone()).await;
+    state.insert(scheduler.clone()).await;
+    state.insert(awaiters.clone()).await;
+
+    let (mut tree, _) = modules.startup(&state).await?;
+
+    // Help lists the commands of every module
+    let help = help_command(locales.clone(), &tree.children)?;
+    tree.children.push(help);
+    locales.apply_aliases(&mut tree);
+    toggles.declare(&modules, &tree);
+    let modules = Arc::new(modules);
+
+    {
+        let prefixes = prefixes.clone();
+        let modules = modules.clone();
+        let checked = modules.clone();
+        config.validate_with(move |config| checked.check_settings(config));
+        config.subscribe(move |config| {
+            modules.configure(config);
+            prefixes.set_defaults(vec![config.prefix.clone()]);
+            perms.set_owners(owners(config));
+            log::set_max_level(config.level());
+        });
+    }
+    #[cfg(unix)]
+    ffirs_core::config::reload_on_hangup(config.clone());
+
+    let handler = Handler::new(Services {
+        manager: Arc::new(manager),
+        tree: Arc::new(tree),
+        prefixes,
+        locales,
+        modules: modules.clone(),
+        shutdown: shutdown.clone(),
+        awaiters,
+        state: state.clone(),
     });
 
-    let mut client = Client::new(dotenv!("TOKEN")).event_handler(Handler).await?;
+    let mut client = Client::new(&token).event_handler(handler).await?;
+    state.insert(client.shard_manager.clone()).await;
+
+    // Shards are closed last, once the commands are done and the modules stopped
+    {
+        let shutdown = shutdown.clone();
+        let modules = modules.clone();
+        let shard_manager = client.shard_manager.clone();
+        tokio::spawn(async move {
+            shutdown.requested().await;
+            shutdown.run(&modules).await;
+            shard_manager.lock().await.shutdown_all().await;
+        });
+    }
+    // Modules registered the handlers of their jobs on startup
+    {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move { scheduler.run(&shutdown).await });
+    }
+    stop_on_signals(shutdown.clone());
+
+    let result = current.sharding.start(&mut client).await;
+
+    // The connection may also end by itself
+    if let Err(err) = &result {
+        error!("Client stopped: {}", err);
+    }
+    shutdown.run(&modules).await;
 
-    #[cfg(not(feature = "sharded"))]
-    client.start().await?;
-    #[cfg(feature = "sharded")]
-    client.start_autosharded().await?;
+    if shutdown.kind() == Some(ShutdownKind::Restart) {
+        restart()?;
+    }
 
-    Ok(())
+    Ok(result?)
 }
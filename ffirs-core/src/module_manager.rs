@@ -1 +1,624 @@
+use crate::awaiter::IncomingReaction;
+use crate::config::{Config, ModuleSettings};
+use crate::error::CoreError;
+use crate::state::SharedState;
+use crate::storage::Migration;
+use ffirs_commands::cmd_tree::{CmdNode, CmdTree};
+use ffirs_commands::cmd_tree_builder::CmdTreeBuilder;
+use ffirs_commands::context::CmdContext;
+use ffirs_commands::error::CmdResult;
+use ffirs_commands::matchers::RootMatcher;
+use log::{info, warn};
+use serenity::{async_trait, client::Context, model::gateway::Ready};
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    sync::Arc,
+};
 
+/// A feature of the bot: its commands, the events it listens to and its lifecycle.
+#[async_trait]
+pub trait Module: Send + Sync {
+    /// Unique name, used by other modules to depend on this one.
+    fn name(&self) -> &str;
+
+    /// Modules to start before this one.
+    fn dependencies(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    /// Whether guilds get the module before enabling or disabling it.
+    fn enabled_by_default(&self) -> bool {
+        true
+    }
+
+    /// Modules that can't be disabled, like the one toggling the others.
+    fn can_be_disabled(&self) -> bool {
+        true
+    }
+
+    /// Check the `[module.<name>]` table of the configuration, before it is applied.
+    fn check_settings(&self, _settings: &ModuleSettings) -> CmdResult<()> {
+        Ok(())
+    }
+
+    /// Read the `[module.<name>]` table of the configuration once checked,
+    /// before startup and on reload.
+    fn configure(&self, _settings: &ModuleSettings) {}
+
+    /// Schema changes of the tables of the module, applied before startup.
+    fn migrations(&self) -> Vec<Migration> {
+        Vec::new()
+    }
+
+    /// Add the commands of the module under the last node of `builder`, with `command`.
+    fn register_commands(&self, _builder: &mut CmdTreeBuilder) -> CmdResult<()> {
+        Ok(())
+    }
+
+    /// Called before connecting, after the dependencies started,
+    /// with the services the bot and the dependencies shared.
+    async fn startup(&self, _state: &SharedState) -> CmdResult<()> {
+        Ok(())
+    }
+
+    /// Called in reverse startup order when the bot stops.
+    async fn shutdown(&self) -> CmdResult<()> {
+        Ok(())
+    }
+
+    async fn ready(&self, _ctx: &Context, _ready: &Ready) {}
+
+    /// Every message, whatever the transport, before it is dispatched as a command.
+    async fn message(&self, _ctx: &dyn CmdContext) {}
+
+    /// Reactions nobody waits for, answered through the `Messenger` of the state.
+    async fn reaction_add(&self, _reaction: &IncomingReaction) {}
+}
+
+/// What happened to a module during startup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleState {
+    Started {
+        commands: usize,
+    },
+    Failed {
+        error: String,
+    },
+    /// A dependency didn't start.
+    Skipped {
+        dependency: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartupReport {
+    /// Every module, in startup order.
+    pub modules: Vec<(String, ModuleState)>,
+}
+
+impl StartupReport {
+    pub fn started(&self) -> usize {
+        self.modules
+            .iter()
+            .filter(|(_, state)| matches!(state, ModuleState::Started { .. }))
+            .count()
+    }
+}
+
+impl Display for StartupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} modules started",
+            self.started(),
+            self.modules.len()
+        )?;
+
+        for (name, state) in &self.modules {
+            match state {
+                ModuleState::Started { commands } => {
+                    write!(f, "\n  {}: started, {} commands", name, commands)?
+                }
+                ModuleState::Failed { error } => write!(f, "\n  {}: failed, {}", name, error)?,
+                ModuleState::Skipped { dependency } => {
+                    write!(f, "\n  {}: skipped, {} didn't start", name, dependency)?
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Starts the modules in dependency order, combines their commands and
+/// forwards them the gateway events.
+#[derive(Default)]
+pub struct ModuleManager {
+    modules: Vec<Arc<dyn Module>>,
+    started: HashSet<String>,
+}
+
+impl ModuleManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_module<M: Module + 'static>(mut self, module: M) -> Self {
+        self.modules.push(Arc::new(module));
+        self
+    }
+
+    /// Names of the modules, in registration order until started.
+    pub fn names(&self) -> Vec<&str> {
+        self.modules.iter().map(|module| module.name()).collect()
+    }
+
+    pub fn modules(&self) -> &[Arc<dyn Module>] {
+        &self.modules
+    }
+
+    /// Only keep the modules listed, and those which can't be disabled.
+    /// An empty list keeps every module.
+    pub fn retain_enabled(&mut self, names: &[String]) -> CmdResult<()> {
+        if let Some(unknown) = names
+            .iter()
+            .find(|name| !self.modules.iter().any(|module| module.name() == *name))
+        {
+            return Err(CoreError::UnknownModule {
+                name: unknown.clone(),
+            }
+            .into());
+        }
+
+        if !names.is_empty() {
+            self.modules.retain(|module| {
+                !module.can_be_disabled() || names.iter().any(|name| name == module.name())
+            });
+        }
+        Ok(())
+    }
+
+    /// Check the settings of every module, so that none is configured when one is invalid.
+    pub fn check_settings(&self, config: &Config) -> CmdResult<()> {
+        for module in &self.modules {
+            module.check_settings(&config.module_settings(module.name()))?;
+        }
+        Ok(())
+    }
+
+    /// Give every module its settings, once checked.
+    pub fn configure(&self, config: &Config) {
+        for module in &self.modules {
+            module.configure(&config.module_settings(module.name()));
+        }
+    }
+
+    /// The migrations of every module in startup order, so that a module can use
+    /// the tables of its dependencies, for `Database::migrate`.
+    pub fn migrations(&self) -> CmdResult<Vec<Migration>> {
+        Ok(self
+            .startup_order()?
+            .iter()
+            .flat_map(|module| module.migrations())
+            .collect())
+    }
+
+    /// The module a command path, going from the leaf up, belongs to.
+    pub fn module_of<'a>(path: &[&'a CmdNode]) -> Option<&'a str> {
+        path.iter()
+            .find(|node| Self::is_module_node(node))
+            .and_then(|node| node.name.as_deref())
+    }
+
+    /// Modules group their commands under a named node consuming nothing.
+    pub fn is_module_node(node: &CmdNode) -> bool {
+        node.name.is_some()
+            && !node.matcher.consumes_fragment()
+            && node.matcher.context_arg().is_none()
+    }
+
+    /// The modules sorted so that each comes after its dependencies,
+    /// otherwise keeping the registration order.
+    pub fn startup_order(&self) -> CmdResult<Vec<Arc<dyn Module>>> {
+        let mut names = HashSet::new();
+        for module in &self.modules {
+            if !names.insert(module.name()) {
+                return Err(CoreError::DuplicateModule {
+                    name: module.name().to_owned(),
+                }
+                .into());
+            }
+        }
+        for module in &self.modules {
+            if let Some(dependency) = module
+                .dependencies()
+                .into_iter()
+                .find(|dependency| !names.contains(dependency))
+            {
+                return Err(CoreError::UnknownDependency {
+                    module: module.name().to_owned(),
+                    dependency: dependency.to_owned(),
+                }
+                .into());
+            }
+        }
+
+        let mut pending = self.modules.clone();
+        let mut ordered: Vec<Arc<dyn Module>> = Vec::with_capacity(pending.len());
+
+        while !pending.is_empty() {
+            let ready = pending.iter().position(|module| {
+                module
+                    .dependencies()
+                    .iter()
+                    .all(|dependency| ordered.iter().any(|done| done.name() == *dependency))
+            });
+
+            match ready {
+                Some(i) => ordered.push(pending.remove(i)),
+                None => {
+                    let modules = pending
+                        .iter()
+                        .map(|module| module.name())
+                        .collect::<Vec<_>>();
+                    return Err(CoreError::DependencyCycle {
+                        modules: modules.join(", "),
+                    }
+                    .into());
+                }
+            }
+        }
+
+        Ok(ordered)
+    }
+
+    /// Start every module and build the tree of the commands of those which started.
+    /// A module fails when its commands can't be built or its startup fails,
+    /// the modules depending on it are then skipped.
+    pub async fn startup(&mut self, state: &SharedState) -> CmdResult<(CmdTree, StartupReport)> {
+        let ordered = self.startup_order()?;
+        let mut commands = Vec::new();
+        let mut report = StartupReport {
+            modules: Vec::new(),
+        };
+
+        for module in &ordered {
+            let name = module.name().to_owned();
+            let missing = module
+                .dependencies()
+                .into_iter()
+                .find(|dependency| !self.started.contains(*dependency));
+
+            let state = match missing {
+                Some(dependency) => ModuleState::Skipped {
+                    dependency: dependency.to_owned(),
+                },
+                None => match Self::start(module.as_ref(), state).await {
+                    Ok(node) => {
+                        let state = ModuleState::Started {
+                            commands: node.usages(&[], usize::MAX).len(),
+                        };
+                        commands.push(node);
+                        self.started.insert(name.clone());
+                        state
+                    }
+                    Err(err) => ModuleState::Failed {
+                        error: err.to_string(),
+                    },
+                },
+            };
+
+            report.modules.push((name, state));
+        }
+
+        self.modules = ordered;
+        info!("{}", report);
+
+        Ok((CmdNode::root(commands), report))
+    }
+
+    /// The commands of `module` grouped under a node named after it, once started.
+    async fn start(module: &dyn Module, state: &SharedState) -> CmdResult<CmdNode> {
+        let mut builder = CmdTreeBuilder::new();
+        builder.raw_node(CmdNode::new_named(Box::new(RootMatcher), module.name()));
+        module.register_commands(&mut builder)?;
+        let node = builder.build()?;
+
+        module.startup(state).await?;
+
+        Ok(node)
+    }
+
+    /// Stop the started modules, dependents first.
+    pub async fn shutdown(&self) {
+        let running = self.running().collect::<Vec<_>>();
+
+        for module in running.into_iter().rev() {
+            if let Err(err) = module.shutdown().await {
+                warn!("Module {} failed to shut down: {}", module.name(), err);
+            }
+        }
+    }
+
+    fn running(&self) -> impl Iterator<Item = &Arc<dyn Module>> {
+        let started = &self.started;
+        self.modules
+            .iter()
+            .filter(move |module| started.contains(module.name()))
+    }
+
+    pub async fn ready(&self, ctx: &Context, ready: &Ready) {
+        for module in self.running() {
+            module.ready(ctx, ready).await;
+        }
+    }
+
+    pub async fn message(&self, ctx: &dyn CmdContext) {
+        for module in self.running() {
+            module.message(ctx).await;
+        }
+    }
+
+    pub async fn reaction_add(&self, reaction: &IncomingReaction) {
+        for module in self.running() {
+            module.reaction_add(reaction).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::CoreError;
+    use crate::module_manager::{Module, ModuleManager, ModuleState};
+    use crate::state::SharedState;
+    use crate::storage::Migration;
+    use crate::transport::TerminalContext;
+    use ffirs_commands::cmd_args::CmdArgs;
+    use ffirs_commands::cmd_creator::CmdCreator;
+    use ffirs_commands::cmd_handler::CmdHandler;
+    use ffirs_commands::cmd_manager::CmdManager;
+    use ffirs_commands::cmd_tree_builder::CmdTreeBuilder;
+    use ffirs_commands::context::{CmdContext, CmdUser};
+    use ffirs_commands::error::{CmdError, CmdResult};
+    use serenity::async_trait;
+    use serenity::model::id::UserId;
+
+    struct Noop;
+
+    #[async_trait]
+    impl CmdHandler for Noop {
+        async fn execute(&self, _: &dyn CmdContext, _: &mut CmdArgs) -> CmdResult<()> {
+            Ok(())
+        }
+    }
+
+    struct TestModule {
+        name: &'static str,
+        dependencies: Vec<&'static str>,
+        commands: Vec<&'static str>,
+        fails: bool,
+    }
+
+    impl TestModule {
+        fn new(name: &'static str, dependencies: Vec<&'static str>) -> Self {
+            Self {
+                name,
+                dependencies,
+                commands: Vec::new(),
+                fails: false,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Module for TestModule {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn dependencies(&self) -> Vec<&str> {
+            self.dependencies.clone()
+        }
+
+        fn migrations(&self) -> Vec<Migration> {
+            vec![Migration::new(self.name, 1, "SELECT 1;")]
+        }
+
+        fn register_commands(&self, builder: &mut CmdTreeBuilder) -> CmdResult<()> {
+            for command in &self.commands {
+                builder.command(CmdCreator::create_cmd(command, Noop)?)?;
+            }
+            Ok(())
+        }
+
+        async fn startup(&self, state: &SharedState) -> CmdResult<()> {
+            if self.fails {
+                return Err(CmdError::GuildOnly);
+            }
+
+            // Modules see what the ones started before them shared
+            let mut state = state.write().await;
+            state
+                .entry::<Vec<&'static str>>()
+                .or_default()
+                .push(self.name);
+            Ok(())
+        }
+
+        async fn message(&self, ctx: &dyn CmdContext) {
+            SharedState::of(ctx)
+                .write()
+                .await
+                .entry::<Vec<String>>()
+                .or_default()
+                .push(format!("{}: {}", self.name, ctx.content()));
+        }
+    }
+
+    fn names(manager: &ModuleManager) -> Vec<String> {
+        manager
+            .startup_order()
+            .unwrap()
+            .iter()
+            .map(|module| module.name().to_owned())
+            .collect()
+    }
+
+    #[test]
+    pub fn test_startup_order() {
+        let manager = ModuleManager::new()
+            .with_module(TestModule::new("reminders", vec!["scheduler", "storage"]))
+            .with_module(TestModule::new("fun", vec![]))
+            .with_module(TestModule::new("scheduler", vec!["storage"]))
+            .with_module(TestModule::new("storage", vec![]));
+
+        assert_eq!(
+            names(&manager),
+            vec!["fun", "storage", "scheduler", "reminders"]
+        );
+    }
+
+    #[test]
+    pub fn test_migrations_order() {
+        let manager = ModuleManager::new()
+            .with_module(TestModule::new("reminders", vec!["scheduler"]))
+            .with_module(TestModule::new("scheduler", vec![]));
+
+        let modules = manager
+            .migrations()
+            .unwrap()
+            .into_iter()
+            .map(|migration| migration.module)
+            .collect::<Vec<_>>();
+        assert_eq!(modules, vec!["scheduler", "reminders"]);
+    }
+
+    #[test]
+    pub fn test_startup_order_errors() {
+        let manager = ModuleManager::new()
+            .with_module(TestModule::new("a", vec![]))
+            .with_module(TestModule::new("a", vec![]));
+        assert!(matches!(
+            manager
+                .startup_order()
+                .err()
+                .unwrap()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::DuplicateModule { .. })
+        ));
+
+        let manager = ModuleManager::new().with_module(TestModule::new("a", vec!["b"]));
+        match manager
+            .startup_order()
+            .err()
+            .unwrap()
+            .downcast_ref::<CoreError>()
+        {
+            Some(CoreError::UnknownDependency { module, dependency }) => {
+                assert_eq!((module.as_str(), dependency.as_str()), ("a", "b"))
+            }
+            _ => unreachable!(),
+        }
+
+        let manager = ModuleManager::new()
+            .with_module(TestModule::new("root", vec![]))
+            .with_module(TestModule::new("a", vec!["b"]))
+            .with_module(TestModule::new("b", vec!["a", "root"]));
+        match manager
+            .startup_order()
+            .err()
+            .unwrap()
+            .downcast_ref::<CoreError>()
+        {
+            Some(CoreError::DependencyCycle { modules }) => assert_eq!(modules, "a, b"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    pub fn test_retain_enabled() {
+        let make_manager = || {
+            ModuleManager::new()
+                .with_module(TestModule::new("fun", vec![]))
+                .with_module(TestModule::new("music", vec![]))
+        };
+
+        let mut manager = make_manager();
+        manager.retain_enabled(&[]).unwrap();
+        assert_eq!(manager.names(), vec!["fun", "music"]);
+
+        manager.retain_enabled(&["music".into()]).unwrap();
+        assert_eq!(manager.names(), vec!["music"]);
+
+        let mut manager = make_manager();
+        assert!(matches!(
+            manager
+                .retain_enabled(&["nsfw".into()])
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::UnknownModule { .. })
+        ));
+    }
+
+    #[tokio::test]
+    pub async fn test_startup() {
+        let mut fun = TestModule::new("fun", vec![]);
+        fun.commands = vec!["roll <n: Unsigned>", "flip"];
+        let mut broken = TestModule::new("broken", vec![]);
+        broken.commands = vec!["broken"];
+        broken.fails = true;
+        let mut dependent = TestModule::new("dependent", vec!["broken"]);
+        dependent.commands = vec!["dependent"];
+
+        let mut manager = ModuleManager::new()
+            .with_module(fun)
+            .with_module(broken)
+            .with_module(dependent);
+        let state = SharedState::new();
+        let (tree, report) = manager.startup(&state).await.unwrap();
+
+        assert_eq!(report.started(), 1);
+        assert_eq!(
+            report.modules,
+            vec![
+                ("fun".into(), ModuleState::Started { commands: 2 }),
+                (
+                    "broken".into(),
+                    ModuleState::Failed {
+                        error: CmdError::GuildOnly.to_string()
+                    }
+                ),
+                (
+                    "dependent".into(),
+                    ModuleState::Skipped {
+                        dependency: "broken".into()
+                    }
+                ),
+            ]
+        );
+
+        // Commands are grouped by module and only started modules have theirs
+        assert_eq!(tree.children.len(), 1);
+        assert_eq!(tree.children[0].name.as_deref(), Some("fun"));
+        let path = CmdManager::try_find_cmd_path(&tree, "flip".into()).unwrap();
+        assert_eq!(ModuleManager::module_of(&path), Some("fun"));
+        assert!(CmdManager::try_find_cmd_path(&tree, "roll 3".into()).is_ok());
+        assert!(CmdManager::try_find_cmd_path(&tree, "broken".into()).is_err());
+        assert_eq!(manager.running().count(), 1);
+        assert_eq!(state.get::<Vec<&str>>().await, Some(vec!["fun"]));
+
+        // Only the started modules see the messages
+        let author = CmdUser {
+            id: UserId(7),
+            name: String::from("ferris"),
+            bot: false,
+        };
+        let ctx = TerminalContext::new(author, "hello").with_data(state.data().clone());
+        manager.message(&ctx).await;
+        assert_eq!(
+            state.get::<Vec<String>>().await,
+            Some(vec![String::from("fun: hello")])
+        );
+    }
+}
@@ -0,0 +1,501 @@
+use crate::storage::{Namespace, Storage};
+use ffirs_commands::cmd_args::CmdArgs;
+use ffirs_commands::cmd_creator::CmdCreator;
+use ffirs_commands::cmd_handler::CmdHandler;
+use ffirs_commands::cmd_tree::CmdNode;
+use ffirs_commands::context::CmdContext;
+use ffirs_commands::error::{CmdError, CmdResult};
+use ffirs_commands::perms::{PermLevel, PermRequirement};
+use log::warn;
+use serenity::{
+    async_trait,
+    model::id::{GuildId, UserId},
+};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+/// Longest prefix accepted by `prefix set`.
+pub const MAX_PREFIX_LEN: usize = 16;
+
+/// Keeps the prefixes guilds chose instead of the default ones.
+pub trait PrefixStore: Send + Sync {
+    fn get(&self, guild: GuildId) -> Option<Vec<String>>;
+    fn set(&self, guild: GuildId, prefixes: Vec<String>) -> io::Result<()>;
+    fn remove(&self, guild: GuildId) -> io::Result<()>;
+}
+
+/// Forgets every prefix on restart.
+#[derive(Debug, Default)]
+pub struct MemoryPrefixStore {
+    prefixes: RwLock<HashMap<GuildId, Vec<String>>>,
+}
+
+impl MemoryPrefixStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PrefixStore for MemoryPrefixStore {
+    fn get(&self, guild: GuildId) -> Option<Vec<String>> {
+        self.prefixes.read().unwrap().get(&guild).cloned()
+    }
+
+    fn set(&self, guild: GuildId, prefixes: Vec<String>) -> io::Result<()> {
+        self.prefixes.write().unwrap().insert(guild, prefixes);
+        Ok(())
+    }
+
+    fn remove(&self, guild: GuildId) -> io::Result<()> {
+        self.prefixes.write().unwrap().remove(&guild);
+        Ok(())
+    }
+}
+
+/// Saves the prefixes to a file, one guild per line: `<guild id> <prefix>...`.
+/// Prefixes never contain whitespace, they couldn't be typed before a command otherwise.
+#[derive(Debug)]
+pub struct FilePrefixStore {
+    path: PathBuf,
+    memory: MemoryPrefixStore,
+}
+
+impl FilePrefixStore {
+    /// Load the prefixes saved at `path`, starting empty if the file doesn't exist yet.
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let memory = MemoryPrefixStore::new();
+
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                let mut prefixes = memory.prefixes.write().unwrap();
+                for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                    let (guild, guild_prefixes) = Self::parse_line(line).ok_or_else(|| {
+                        io::Error::new(ErrorKind::InvalidData, format!("Invalid line: {}", line))
+                    })?;
+                    prefixes.insert(guild, guild_prefixes);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        Ok(Self { path, memory })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn parse_line(line: &str) -> Option<(GuildId, Vec<String>)> {
+        let mut words = line.split_whitespace();
+        let guild = GuildId(words.next()?.parse().ok()?);
+        let prefixes = words.map(str::to_owned).collect::<Vec<_>>();
+
+        if prefixes.is_empty() {
+            None
+        } else {
+            Some((guild, prefixes))
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let prefixes = self.memory.prefixes.read().unwrap();
+        let mut guilds = prefixes.iter().collect::<Vec<_>>();
+        guilds.sort_by_key(|(guild, _)| guild.0);
+
+        let content = guilds
+            .into_iter()
+            .map(|(guild, prefixes)| format!("{} {}\n", guild.0, prefixes.join(" ")))
+            .collect::<String>();
+
+        // Write next to the file first so a crash never leaves it half written
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+impl PrefixStore for FilePrefixStore {
+    fn get(&self, guild: GuildId) -> Option<Vec<String>> {
+        self.memory.get(guild)
+    }
+
+    fn set(&self, guild: GuildId, prefixes: Vec<String>) -> io::Result<()> {
+        self.memory.set(guild, prefixes)?;
+        self.save()
+    }
+
+    fn remove(&self, guild: GuildId) -> io::Result<()> {
+        self.memory.remove(guild)?;
+        self.save()
+    }
+}
+
+/// Keeps the prefixes in the `prefixes` namespace of a `Storage`.
+///
+/// Every message needs the prefixes of its guild, they are read once
+/// and kept in memory, changes being written through.
+pub struct StoragePrefixStore {
+    namespace: Namespace,
+    /// The prefixes read or written so far, `None` for the guilds using the defaults.
+    cache: RwLock<HashMap<GuildId, Option<Vec<String>>>>,
+}
+
+impl StoragePrefixStore {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            namespace: Namespace::new(storage, "prefixes"),
+            cache: RwLock::default(),
+        }
+    }
+}
+
+impl PrefixStore for StoragePrefixStore {
+    fn get(&self, guild: GuildId) -> Option<Vec<String>> {
+        if let Some(prefixes) = self.cache.read().unwrap().get(&guild) {
+            return prefixes.clone();
+        }
+
+        match self.namespace.get(&guild.0.to_string()) {
+            // A change made since the read is newer, it stays
+            Ok(prefixes) => self
+                .cache
+                .write()
+                .unwrap()
+                .entry(guild)
+                .or_insert(prefixes)
+                .clone(),
+            Err(err) => {
+                warn!("Failed to read the prefixes of {}: {}", guild.0, err);
+                None
+            }
+        }
+    }
+
+    fn set(&self, guild: GuildId, prefixes: Vec<String>) -> io::Result<()> {
+        self.namespace
+            .set(&guild.0.to_string(), &prefixes)
+            .map_err(|err| io::Error::new(ErrorKind::Other, err.to_string()))?;
+        self.cache.write().unwrap().insert(guild, Some(prefixes));
+        Ok(())
+    }
+
+    fn remove(&self, guild: GuildId) -> io::Result<()> {
+        self.namespace
+            .remove(&guild.0.to_string())
+            .map_err(|err| io::Error::new(ErrorKind::Other, err.to_string()))?;
+        self.cache.write().unwrap().insert(guild, None);
+        Ok(())
+    }
+}
+
+/// Finds which prefix a message starts with.
+///
+/// A message is a command when it starts with one of the prefixes of its guild
+/// (the defaults unless the guild set its own), with a mention of the bot,
+/// or with anything at all in direct messages if `dm_without_prefix` is set.
+pub struct PrefixResolver {
+    defaults: RwLock<Vec<String>>,
+    mention: bool,
+    dm_without_prefix: bool,
+    store: Box<dyn PrefixStore>,
+}
+
+impl PrefixResolver {
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(defaults: I) -> Self {
+        Self {
+            defaults: RwLock::new(defaults.into_iter().map(Into::into).collect()),
+            mention: true,
+            dm_without_prefix: false,
+            store: Box::new(MemoryPrefixStore::new()),
+        }
+    }
+
+    pub fn with_store(mut self, store: Box<dyn PrefixStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    pub fn with_mention(mut self, mention: bool) -> Self {
+        self.mention = mention;
+        self
+    }
+
+    pub fn with_dm_without_prefix(mut self, dm_without_prefix: bool) -> Self {
+        self.dm_without_prefix = dm_without_prefix;
+        self
+    }
+
+    /// Prefixes usable in `guild`, the defaults outside of guilds.
+    pub fn prefixes(&self, guild: Option<GuildId>) -> Vec<String> {
+        guild
+            .and_then(|guild| self.store.get(guild))
+            .unwrap_or_else(|| self.defaults.read().unwrap().clone())
+    }
+
+    /// Replace the prefixes of the guilds which didn't choose their own.
+    pub fn set_defaults<I: IntoIterator<Item = S>, S: Into<String>>(&self, defaults: I) {
+        *self.defaults.write().unwrap() = defaults.into_iter().map(Into::into).collect();
+    }
+
+    pub fn set_prefixes(&self, guild: GuildId, prefixes: Vec<String>) -> io::Result<()> {
+        self.store.set(guild, prefixes)
+    }
+
+    /// Go back to the default prefixes.
+    pub fn reset_prefixes(&self, guild: GuildId) -> io::Result<()> {
+        self.store.remove(guild)
+    }
+
+    /// The content of a message without its prefix, `None` if it isn't a command.
+    pub fn strip<'a>(
+        &self,
+        content: &'a str,
+        guild: Option<GuildId>,
+        bot: UserId,
+    ) -> Option<&'a str> {
+        if self.mention {
+            let mentions = [format!("<@{}>", bot.0), format!("<@!{}>", bot.0)];
+            let rest = mentions
+                .iter()
+                .find_map(|mention| content.strip_prefix(mention.as_str()));
+
+            if let Some(rest) = rest {
+                return Some(rest.trim_start());
+            }
+        }
+
+        // When a prefix starts another one, the longest must be tried first
+        let mut prefixes = self.prefixes(guild);
+        prefixes.sort_by_key(|prefix| Reverse(prefix.len()));
+
+        let rest = prefixes
+            .iter()
+            .filter(|prefix| !prefix.is_empty())
+            .find_map(|prefix| content.strip_prefix(prefix.as_str()));
+
+        match rest {
+            Some(rest) => Some(rest.trim_start()),
+            None if guild.is_none() && self.dm_without_prefix => Some(content.trim_start()),
+            None => None,
+        }
+    }
+}
+
+/// `prefix set <prefix>`: replace the prefixes of the guild.
+/// A quoted prefix could contain spaces, which the file store can't keep.
+pub struct SetPrefixCmd {
+    resolver: Arc<PrefixResolver>,
+}
+
+#[async_trait]
+impl CmdHandler for SetPrefixCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()> {
+        let guild = ctx.guild().ok_or(CmdError::GuildOnly)?;
+        let prefix = args.take::<String>("prefix").unwrap_or_default();
+
+        if prefix.chars().count() > MAX_PREFIX_LEN || prefix.contains(char::is_whitespace) {
+            return Err(CmdError::OutOfRange {
+                value: prefix,
+                range: format!("at most {} characters without spaces", MAX_PREFIX_LEN),
+            });
+        }
+
+        self.resolver.set_prefixes(guild, vec![prefix.clone()])?;
+        ctx.reply(&format!("Prefix set to `{}`", prefix)).await?;
+
+        Ok(())
+    }
+}
+
+/// `prefix reset`: go back to the default prefixes.
+pub struct ResetPrefixCmd {
+    resolver: Arc<PrefixResolver>,
+}
+
+#[async_trait]
+impl CmdHandler for ResetPrefixCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, _args: &mut CmdArgs) -> CmdResult<()> {
+        let guild = ctx.guild().ok_or(CmdError::GuildOnly)?;
+
+        self.resolver.reset_prefixes(guild)?;
+        let prefixes = self.resolver.prefixes(Some(guild)).join("`, `");
+        ctx.reply(&format!("Prefixes reset to `{}`", prefixes))
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// The `prefix` command and its subcommands, for admins only.
+pub fn prefix_command(resolver: Arc<PrefixResolver>) -> CmdResult<CmdNode> {
+    let mut prefix = CmdCreator::create_cmd(
+        "prefix set <prefix: Word>",
+        SetPrefixCmd {
+            resolver: resolver.clone(),
+        },
+    )?;
+    let reset = CmdCreator::create_cmd("reset", ResetPrefixCmd { resolver })?;
+
+    prefix.children.push(reset);
+    prefix.requirement = Some(PermRequirement::level(PermLevel::ADMIN));
+    prefix.description = Some("prefix-description".into());
+
+    Ok(prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prefix::{
+        prefix_command, FilePrefixStore, PrefixResolver, PrefixStore, StoragePrefixStore,
+    };
+    use crate::storage::{Database, Storage};
+    use crate::transport::TerminalContext;
+    use ffirs_commands::cmd_manager::CmdManager;
+    use ffirs_commands::cmd_tree::CmdNode;
+    use ffirs_commands::context::CmdUser;
+    use ffirs_commands::error::CmdError;
+    use serenity::model::id::{GuildId, UserId};
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    const BOT: UserId = UserId(42);
+    const GUILD: GuildId = GuildId(1);
+
+    #[test]
+    pub fn test_strip_default_prefixes() {
+        let resolver = PrefixResolver::new(vec!["!", "!!", "ffirs "]);
+
+        assert_eq!(resolver.strip("!ping", Some(GUILD), BOT), Some("ping"));
+        assert_eq!(resolver.strip("!! ping", Some(GUILD), BOT), Some("ping"));
+        assert_eq!(resolver.strip("ffirs ping", Some(GUILD), BOT), Some("ping"));
+        assert_eq!(resolver.strip("ping", Some(GUILD), BOT), None);
+        assert_eq!(resolver.strip("ping", None, BOT), None);
+    }
+
+    #[test]
+    pub fn test_strip_mention() {
+        let resolver = PrefixResolver::new(vec!["!"]);
+
+        assert_eq!(resolver.strip("<@42> ping", Some(GUILD), BOT), Some("ping"));
+        assert_eq!(resolver.strip("<@!42>ping", Some(GUILD), BOT), Some("ping"));
+        assert_eq!(resolver.strip("<@43> ping", Some(GUILD), BOT), None);
+
+        let resolver = resolver.with_mention(false);
+        assert_eq!(resolver.strip("<@42> ping", Some(GUILD), BOT), None);
+    }
+
+    #[test]
+    pub fn test_strip_dm_without_prefix() {
+        let resolver = PrefixResolver::new(vec!["!"]).with_dm_without_prefix(true);
+
+        assert_eq!(resolver.strip("ping", None, BOT), Some("ping"));
+        assert_eq!(resolver.strip("!ping", None, BOT), Some("ping"));
+        assert_eq!(resolver.strip("ping", Some(GUILD), BOT), None);
+    }
+
+    #[test]
+    pub fn test_guild_prefixes() {
+        let resolver = PrefixResolver::new(vec!["!"]);
+
+        resolver.set_prefixes(GUILD, vec!["?".into()]).unwrap();
+        assert_eq!(resolver.strip("?ping", Some(GUILD), BOT), Some("ping"));
+        assert_eq!(resolver.strip("!ping", Some(GUILD), BOT), None);
+        // Other guilds keep the defaults
+        assert_eq!(resolver.strip("!ping", Some(GuildId(2)), BOT), Some("ping"));
+
+        resolver.reset_prefixes(GUILD).unwrap();
+        assert_eq!(resolver.strip("!ping", Some(GUILD), BOT), Some("ping"));
+
+        resolver.set_defaults(vec![">"]);
+        assert_eq!(resolver.strip(">ping", Some(GUILD), BOT), Some("ping"));
+        assert_eq!(resolver.strip("!ping", Some(GUILD), BOT), None);
+    }
+
+    #[test]
+    pub fn test_file_store() {
+        let path = std::env::temp_dir().join(format!("ffirs-prefixes-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let store = FilePrefixStore::open(&path).unwrap();
+        assert_eq!(store.get(GUILD), None);
+        store.set(GUILD, vec!["?".into(), "$".into()]).unwrap();
+        store.set(GuildId(2), vec![">".into()]).unwrap();
+        store.remove(GuildId(2)).unwrap();
+
+        let reopened = FilePrefixStore::open(&path).unwrap();
+        assert_eq!(reopened.get(GUILD), Some(vec!["?".into(), "$".into()]));
+        assert_eq!(reopened.get(GuildId(2)), None);
+
+        fs::write(&path, "not a guild !\n").unwrap();
+        assert!(FilePrefixStore::open(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_set_prefix_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("ffirs-set-prefix-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let store = FilePrefixStore::open(&path).unwrap();
+        let resolver = Arc::new(PrefixResolver::new(vec!["!"]).with_store(Box::new(store)));
+        let tree = CmdNode::root(vec![prefix_command(resolver).unwrap()]);
+        let manager = CmdManager::with_default_mappers();
+        let author = CmdUser {
+            id: UserId(7),
+            name: String::from("ferris"),
+            bot: false,
+        };
+        let (manager, tree) = (&manager, &tree);
+        let run = move |raw: &'static str| {
+            let ctx = TerminalContext::new(author.clone(), raw)
+                .with_guild(GUILD)
+                .with_output(Arc::new(Mutex::new(Vec::new())));
+            async move { manager.dispatch(tree, &ctx, raw).await }
+        };
+
+        assert!(matches!(
+            run("prefix set 'f '").await,
+            Err(CmdError::OutOfRange { .. })
+        ));
+        run("prefix set f!").await.unwrap();
+
+        let reopened = FilePrefixStore::open(&path).unwrap();
+        assert_eq!(reopened.get(GUILD), Some(vec!["f!".into()]));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    pub fn test_storage_store() {
+        let database = Arc::new(Database::in_memory().unwrap());
+        let resolver = PrefixResolver::new(vec!["!"])
+            .with_store(Box::new(StoragePrefixStore::new(database.clone())));
+
+        resolver.set_prefixes(GUILD, vec!["?".into()]).unwrap();
+        assert_eq!(resolver.strip("?ping", Some(GUILD), BOT), Some("ping"));
+        assert_eq!(resolver.strip("!ping", Some(GuildId(2)), BOT), Some("ping"));
+
+        // Another store over the same database sees them
+        let store = StoragePrefixStore::new(database.clone());
+        assert_eq!(store.get(GUILD), Some(vec!["?".into()]));
+        resolver.reset_prefixes(GUILD).unwrap();
+        assert_eq!(StoragePrefixStore::new(database.clone()).get(GUILD), None);
+
+        // Once read, the prefixes come from memory
+        database
+            .set("prefixes", &GUILD.0.to_string(), "[\"$\"]")
+            .unwrap();
+        assert_eq!(store.get(GUILD), Some(vec!["?".into()]));
+        assert_eq!(resolver.strip("!ping", Some(GUILD), BOT), Some("ping"));
+    }
+}
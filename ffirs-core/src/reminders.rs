@@ -0,0 +1,631 @@
+use crate::config::ModuleSettings;
+use crate::error::CoreError;
+use crate::module_manager::Module;
+use crate::scheduler::{Job, JobHandler, MissedPolicy, Schedule, Scheduler};
+use crate::state::SharedState;
+use crate::storage::{Database, Namespace, Storage};
+use crate::transport::Messenger;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use ffirs_commands::cmd_args::CmdArgs;
+use ffirs_commands::cmd_creator::CmdCreator;
+use ffirs_commands::cmd_handler::CmdHandler;
+use ffirs_commands::cmd_tree::CmdNode;
+use ffirs_commands::cmd_tree_builder::CmdTreeBuilder;
+use ffirs_commands::context::{CmdContext, Embed};
+use ffirs_commands::error::CmdResult;
+use ffirs_commands::locale::Locales;
+use ffirs_commands::mappers::LocalDateTime;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    model::id::{ChannelId, GuildId, UserId},
+};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+pub const DEFAULT_MAX_PER_USER: usize = 25;
+/// The kind of the scheduler jobs delivering reminders.
+pub const REMINDER_JOB: &str = "reminder";
+
+/// What to send to whom once a reminder is due, the payload of its job.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reminder {
+    pub user: UserId,
+    pub guild: Option<GuildId>,
+    pub channel: ChannelId,
+    pub text: String,
+}
+
+/// The timezone of every user, UTC until they choose one.
+pub struct Timezones {
+    namespace: Namespace,
+}
+
+impl Timezones {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            namespace: Namespace::new(storage, "timezones"),
+        }
+    }
+
+    pub fn get(&self, user: UserId) -> CmdResult<Tz> {
+        let name = self.namespace.get::<String>(&user.0.to_string())?;
+        Ok(name.and_then(|name| name.parse().ok()).unwrap_or(Tz::UTC))
+    }
+
+    /// Store the timezone named `name`, like `Europe/Paris`.
+    pub fn set(&self, user: UserId, name: &str) -> CmdResult<Tz> {
+        let tz = name.parse::<Tz>().map_err(|_| CoreError::UnknownTimezone {
+            timezone: name.to_owned(),
+        })?;
+
+        self.namespace.set(&user.0.to_string(), &tz.name())?;
+        Ok(tz)
+    }
+}
+
+/// `at` as shown to a user in timezone `tz`.
+pub fn format_time(at: DateTime<Utc>, tz: &Tz) -> String {
+    at.with_timezone(tz).format("%Y-%m-%d %H:%M %Z").to_string()
+}
+
+/// How many reminders a user can have at a time.
+pub struct ReminderLimit {
+    max_per_user: AtomicUsize,
+    /// Held while counting the reminders of a user and adding one,
+    /// so concurrent commands can't both get under the limit.
+    adding: Mutex<()>,
+}
+
+impl ReminderLimit {
+    pub fn new(max_per_user: usize) -> Self {
+        Self {
+            max_per_user: AtomicUsize::new(max_per_user),
+            adding: Mutex::new(()),
+        }
+    }
+
+    pub fn max_per_user(&self) -> usize {
+        self.max_per_user.load(Ordering::Relaxed)
+    }
+
+    pub fn set_max_per_user(&self, max: usize) {
+        self.max_per_user.store(max, Ordering::Relaxed);
+    }
+}
+
+/// The services the reminder commands need, fetched when they run.
+struct Services {
+    scheduler: Arc<Scheduler>,
+    timezones: Arc<Timezones>,
+    locales: Arc<Locales>,
+}
+
+impl Services {
+    async fn of(ctx: &dyn CmdContext) -> CmdResult<Self> {
+        let state = SharedState::of(ctx);
+
+        Ok(Self {
+            scheduler: state.require::<Arc<Scheduler>>().await?,
+            timezones: state.require::<Arc<Timezones>>().await?,
+            locales: state.require::<Arc<Locales>>().await?,
+        })
+    }
+
+    fn locale(&self, ctx: &dyn CmdContext) -> String {
+        self.locales.locale_of(ctx.author().id, ctx.guild())
+    }
+
+    /// The reminders of `user` with their job, soonest first.
+    fn reminders_of(&self, user: UserId) -> CmdResult<Vec<(Job, Reminder)>> {
+        let mut reminders = Vec::new();
+        for job in self.scheduler.jobs()? {
+            if job.kind != REMINDER_JOB {
+                continue;
+            }
+
+            let reminder = job.payload::<Reminder>()?;
+            if reminder.user == user {
+                reminders.push((job, reminder));
+            }
+        }
+        reminders.sort_by_key(|(job, _)| job.next);
+
+        Ok(reminders)
+    }
+
+    async fn add(
+        &self,
+        ctx: &dyn CmdContext,
+        at: DateTime<Utc>,
+        text: String,
+        limit: &ReminderLimit,
+    ) -> CmdResult<()> {
+        let user = ctx.author().id;
+        let reminder = Reminder {
+            user,
+            guild: ctx.guild(),
+            channel: ctx.channel(),
+            text,
+        };
+
+        let handle = {
+            let _adding = limit.adding.lock().unwrap();
+            let max = limit.max_per_user();
+            if self.reminders_of(user)?.len() >= max {
+                return Err(CoreError::TooManyReminders { max }.into());
+            }
+
+            self.scheduler.add(
+                REMINDER_JOB,
+                &reminder,
+                Schedule::at(at),
+                MissedPolicy::Once,
+            )?
+        };
+
+        let tz = self.timezones.get(user)?;
+        let reply = self.locales.tr(
+            &self.locale(ctx),
+            "remind-set",
+            &[
+                ("id", handle.id().to_string()),
+                ("when", format_time(at, &tz)),
+            ],
+        );
+        ctx.reply(&reply).await?;
+
+        Ok(())
+    }
+}
+
+/// `remind <in: Duration> <text: Rest>`
+pub struct RemindInCmd {
+    limit: Arc<ReminderLimit>,
+}
+
+#[async_trait]
+impl CmdHandler for RemindInCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()> {
+        let services = Services::of(ctx).await?;
+        let delay = args.take::<Duration>("in").unwrap_or_default();
+        let text = args.take::<String>("text").unwrap_or_default();
+
+        let at = chrono::Duration::from_std(delay)
+            .ok()
+            .and_then(|delay| services.scheduler.now().checked_add_signed(delay))
+            .ok_or_else(|| CoreError::InvalidSchedule {
+                message: format!("{:?} is too far away", delay),
+            })?;
+
+        services.add(ctx, at, text, &self.limit).await
+    }
+}
+
+/// `remind at <when: LocalDateTime> <text: Rest>`, in the timezone of the author.
+pub struct RemindAtCmd {
+    limit: Arc<ReminderLimit>,
+}
+
+#[async_trait]
+impl CmdHandler for RemindAtCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()> {
+        let services = Services::of(ctx).await?;
+        let tz = services.timezones.get(ctx.author().id)?;
+        let now = services.scheduler.now();
+        let text = args.take::<String>("text").unwrap_or_default();
+        let when = args.take::<LocalDateTime>("when");
+
+        // `remind at tomorrow 9am ...` would take 9am as part of the text
+        if let Some(when) = when.as_ref().filter(|when| when.is_day_only()) {
+            return Err(CoreError::MissingTimeOfDay {
+                day: when.as_str().to_owned(),
+            }
+            .into());
+        }
+        let at = when
+            .and_then(|when| when.resolve(&now.with_timezone(&tz)))
+            .ok_or_else(|| CoreError::InvalidSchedule {
+                message: format!("this time doesn't exist in {}", tz.name()),
+            })?;
+        if at <= now {
+            return Err(CoreError::InvalidSchedule {
+                message: format!("{} is in the past", format_time(at, &tz)),
+            }
+            .into());
+        }
+
+        services.add(ctx, at, text, &self.limit).await
+    }
+}
+
+/// `remind list`
+pub struct ListRemindersCmd;
+
+#[async_trait]
+impl CmdHandler for ListRemindersCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, _args: &mut CmdArgs) -> CmdResult<()> {
+        let services = Services::of(ctx).await?;
+        let locale = services.locale(ctx);
+        let tz = services.timezones.get(ctx.author().id)?;
+
+        let reminders = services.reminders_of(ctx.author().id)?;
+        let description = if reminders.is_empty() {
+            services.locales.tr(&locale, "remind-list-empty", &[])
+        } else {
+            reminders
+                .iter()
+                .map(|(job, reminder)| {
+                    services.locales.tr(
+                        &locale,
+                        "remind-list-line",
+                        &[
+                            ("id", job.id.to_string()),
+                            ("when", format_time(job.next, &tz)),
+                            ("text", reminder.text.clone()),
+                        ],
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        let title = services.locales.tr(&locale, "remind-list-title", &[]);
+        ctx.send_embed(&Embed::new().title(title).description(description))
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// `remind cancel <id: Unsigned>`, only for the reminders of the author.
+pub struct CancelReminderCmd;
+
+#[async_trait]
+impl CmdHandler for CancelReminderCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()> {
+        let services = Services::of(ctx).await?;
+        let id = args.take::<u64>("id").unwrap_or_default();
+
+        let owned = services
+            .reminders_of(ctx.author().id)?
+            .iter()
+            .any(|(job, _)| job.id == id);
+        if !owned || !services.scheduler.cancel(id)? {
+            return Err(CoreError::UnknownReminder { id }.into());
+        }
+
+        let reply = services.locales.tr(
+            &services.locale(ctx),
+            "remind-cancelled",
+            &[("id", id.to_string())],
+        );
+        ctx.reply(&reply).await?;
+
+        Ok(())
+    }
+}
+
+/// `remind timezone <timezone: Word>`
+pub struct TimezoneCmd;
+
+#[async_trait]
+impl CmdHandler for TimezoneCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()> {
+        let services = Services::of(ctx).await?;
+        let name = args.take::<String>("timezone").unwrap_or_default();
+
+        let tz = services.timezones.set(ctx.author().id, &name)?;
+        let reply = services.locales.tr(
+            &services.locale(ctx),
+            "remind-timezone-set",
+            &[("timezone", tz.name().to_owned())],
+        );
+        ctx.reply(&reply).await?;
+
+        Ok(())
+    }
+}
+
+pub fn remind_command(limit: Arc<ReminderLimit>) -> CmdResult<CmdNode> {
+    let mut remind = CmdCreator::create_cmd(
+        "remind <in: Duration> <text: Rest>",
+        RemindInCmd {
+            limit: limit.clone(),
+        },
+    )?;
+    let mut at = CmdCreator::create_cmd(
+        "at <when: LocalDateTime> <text: Rest>",
+        RemindAtCmd { limit },
+    )?;
+    at.description = Some("remind-at-description".into());
+    let mut list = CmdCreator::create_cmd("list", ListRemindersCmd)?;
+    list.description = Some("remind-list-description".into());
+    let mut cancel = CmdCreator::create_cmd("cancel <id: Unsigned>", CancelReminderCmd)?;
+    cancel.description = Some("remind-cancel-description".into());
+    let mut timezone = CmdCreator::create_cmd("timezone <timezone: Word>", TimezoneCmd)?;
+    timezone.description = Some("remind-timezone-description".into());
+
+    // The literals go before the duration, tried first
+    remind
+        .children
+        .splice(0..0, vec![at, list, cancel, timezone]);
+    remind.description = Some("remind-description".into());
+
+    Ok(remind)
+}
+
+/// `text` with its mentions broken by a zero-width space, so it pings nobody:
+/// `@everyone`, `@here` and the users and roles in `<@id>`, `<@!id>` or `<@&id>`.
+pub fn escape_mentions(text: &str) -> String {
+    text.replace("@everyone", "@\u{200b}everyone")
+        .replace("@here", "@\u{200b}here")
+        .replace("<@", "<@\u{200b}")
+}
+
+/// Sends a due reminder where it was set, or in direct messages when that fails,
+/// like after the channel was deleted.
+pub struct ReminderJob {
+    messenger: Arc<dyn Messenger>,
+    locales: Arc<Locales>,
+}
+
+#[async_trait]
+impl JobHandler for ReminderJob {
+    async fn run(&self, job: &Job) -> CmdResult<()> {
+        let reminder = job.payload::<Reminder>()?;
+        let locale = self.locales.locale_of(reminder.user, reminder.guild);
+        // The text must not ping more people than the author
+        let text = escape_mentions(&reminder.text);
+        let content = self.locales.tr(
+            &locale,
+            "remind-delivered",
+            &[("user", format!("<@{}>", reminder.user.0)), ("text", text)],
+        );
+
+        if self
+            .messenger
+            .send(reminder.channel, &content)
+            .await
+            .is_err()
+        {
+            self.messenger.send_dm(reminder.user, &content).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reminders set by users, delivered by the scheduler.
+///
+/// `[module.reminders]` takes `max_per_user`, the reminders a user can have at a time.
+pub struct ReminderModule {
+    limit: Arc<ReminderLimit>,
+}
+
+impl ReminderModule {
+    pub fn new() -> Self {
+        Self {
+            limit: Arc::new(ReminderLimit::new(DEFAULT_MAX_PER_USER)),
+        }
+    }
+}
+
+impl Default for ReminderModule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn max_per_user(settings: &ModuleSettings) -> CmdResult<usize> {
+    match settings.get("max_per_user") {
+        None => Ok(DEFAULT_MAX_PER_USER),
+        Some(value) => value
+            .as_integer()
+            .filter(|&max| max > 0)
+            .map(|max| max as usize)
+            .ok_or_else(|| {
+                CoreError::InvalidConfig {
+                    key: String::from("module.reminders.max_per_user"),
+                    message: format!("expected a positive integer, got {}", value),
+                }
+                .into()
+            }),
+    }
+}
+
+#[async_trait]
+impl Module for ReminderModule {
+    fn name(&self) -> &str {
+        "reminders"
+    }
+
+    fn check_settings(&self, settings: &ModuleSettings) -> CmdResult<()> {
+        max_per_user(settings).map(|_| ())
+    }
+
+    fn configure(&self, settings: &ModuleSettings) {
+        if let Ok(max) = max_per_user(settings) {
+            self.limit.set_max_per_user(max);
+        }
+    }
+
+    fn register_commands(&self, builder: &mut CmdTreeBuilder) -> CmdResult<()> {
+        builder.command(remind_command(self.limit.clone())?)?;
+        Ok(())
+    }
+
+    async fn startup(&self, state: &SharedState) -> CmdResult<()> {
+        let scheduler = state.require::<Arc<Scheduler>>().await?;
+        let database = state.require::<Arc<Database>>().await?;
+        let messenger = state.require::<Arc<dyn Messenger>>().await?;
+        let locales = state.require::<Arc<Locales>>().await?;
+
+        state.insert(Arc::new(Timezones::new(database))).await;
+        scheduler.register(REMINDER_JOB, ReminderJob { messenger, locales });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::error::CoreError;
+    use crate::locale::core_locales;
+    use crate::module_manager::ModuleManager;
+    use crate::reminders::{escape_mentions, format_time, ReminderModule, Timezones};
+    use crate::scheduler::Scheduler;
+    use crate::state::SharedState;
+    use crate::storage::{Database, MemoryStorage};
+    use crate::transport::{Messenger, TerminalContext, TerminalMessenger};
+    use chrono::{Duration, TimeZone, Utc};
+    use chrono_tz::Tz;
+    use ffirs_commands::clock::MockClock;
+    use ffirs_commands::cmd_manager::CmdManager;
+    use ffirs_commands::context::CmdUser;
+    use serenity::model::id::{ChannelId, GuildId, UserId};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    pub fn test_timezones() {
+        let timezones = Timezones::new(Arc::new(MemoryStorage::new()));
+
+        assert_eq!(timezones.get(UserId(1)).unwrap(), Tz::UTC);
+        assert_eq!(
+            timezones.set(UserId(1), "Europe/Paris").unwrap(),
+            Tz::Europe__Paris
+        );
+        assert_eq!(timezones.get(UserId(1)).unwrap(), Tz::Europe__Paris);
+        assert!(matches!(
+            timezones
+                .set(UserId(1), "Mars/Olympus")
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::UnknownTimezone { .. })
+        ));
+
+        let at = Utc.ymd(2020, 6, 6).and_hms(12, 0, 0);
+        assert_eq!(format_time(at, &Tz::Europe__Paris), "2020-06-06 14:00 CEST");
+    }
+
+    #[test]
+    pub fn test_escape_mentions() {
+        assert_eq!(
+            escape_mentions("@here <@&5> <@!8> <@9> <#20> ferris@crab.rs"),
+            "@\u{200b}here <@\u{200b}&5> <@\u{200b}!8> <@\u{200b}9> <#20> ferris@crab.rs"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_reminders() {
+        let clock = Arc::new(MockClock::new(Utc.ymd(2020, 6, 6).and_hms(12, 0, 0)));
+        let database = Arc::new(Database::in_memory().unwrap());
+        let scheduler = Arc::new(Scheduler::new(database.clone(), clock.clone()).unwrap());
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let messenger: Arc<dyn Messenger> = Arc::new(TerminalMessenger::new(output.clone()));
+
+        let state = SharedState::new();
+        state.insert(database).await;
+        state.insert(scheduler.clone()).await;
+        state.insert(messenger).await;
+        state.insert(Arc::new(core_locales().unwrap())).await;
+
+        let mut modules = ModuleManager::new().with_module(ReminderModule::new());
+        let config = Config::parse("[module.reminders]\nmax_per_user = 2").unwrap();
+        modules.check_settings(&config).unwrap();
+        modules.configure(&config);
+        let zero = Config::parse("[module.reminders]\nmax_per_user = 0").unwrap();
+        assert!(modules.check_settings(&zero).is_err());
+        let (tree, _) = modules.startup(&state).await.unwrap();
+
+        let manager = CmdManager::with_default_mappers();
+        let data = state.data().clone();
+        let author = CmdUser {
+            id: UserId(7),
+            name: String::from("ferris"),
+            bot: false,
+        };
+        let (manager, tree, out) = (&manager, &tree, output.clone());
+        let run = move |raw: &'static str| {
+            let ctx = TerminalContext::new(author.clone(), raw)
+                .with_guild(GuildId(10))
+                .with_channel(ChannelId(20))
+                .with_data(data.clone())
+                .with_output(out.clone());
+            async move { manager.dispatch(tree, &ctx, raw).await }
+        };
+        let take_output = || String::from_utf8(output.lock().unwrap().split_off(0)).unwrap();
+
+        run("remind timezone Europe/Paris").await.unwrap();
+        run("remind 30m feed @everyone the crab, <@&5>")
+            .await
+            .unwrap();
+        run("remind at 2020-06-06T15:00 stretch").await.unwrap();
+        assert_eq!(
+            take_output(),
+            "Your timezone is now Europe/Paris\n\
+             Reminder 1 set for 2020-06-06 14:30 CEST\n\
+             Reminder 2 set for 2020-06-06 15:00 CEST\n"
+        );
+
+        assert!(matches!(
+            run("remind 1h too many")
+                .await
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::TooManyReminders { max: 2 })
+        ));
+        assert!(matches!(
+            run("remind at 2020-06-06T13:00 too late")
+                .await
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::InvalidSchedule { .. })
+        ));
+        assert!(matches!(
+            run("remind cancel 3")
+                .await
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::UnknownReminder { id: 3 })
+        ));
+        assert!(matches!(
+            run("remind timezone Mars/Olympus")
+                .await
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::UnknownTimezone { .. })
+        ));
+        assert!(matches!(
+            run("remind at tomorrow 9am take a break")
+                .await
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::MissingTimeOfDay { day }) if day == "tomorrow"
+        ));
+
+        clock.advance(Duration::minutes(30));
+        assert_eq!(scheduler.run_due().await.unwrap(), 1);
+        assert_eq!(
+            take_output(),
+            "[#20] <@7>, you asked me to remind you: feed @\u{200b}everyone the crab, <@\u{200b}&5>\n"
+        );
+
+        run("remind cancel 2").await.unwrap();
+        run("remind list").await.unwrap();
+        assert_eq!(
+            take_output(),
+            "Reminder 2 cancelled\n== Your reminders ==\nYou have no reminders\n"
+        );
+
+        run("remind at 'tomorrow 9am' take a break").await.unwrap();
+        assert_eq!(take_output(), "Reminder 3 set for 2020-06-07 09:00 CEST\n");
+    }
+}
@@ -0,0 +1,586 @@
+use crate::cron::Cron;
+use crate::error::CoreError;
+use crate::shutdown::Shutdown;
+use crate::storage::{Namespace, Storage};
+use chrono::{DateTime, Duration, Utc};
+use ffirs_commands::clock::Clock;
+use ffirs_commands::error::CmdResult;
+use log::{debug, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use serenity::async_trait;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time,
+};
+use tokio::{sync::Notify, time::delay_for};
+
+/// Runs late by at most this many seconds are on time.
+pub const GRACE_SECS: i64 = 60;
+/// Missed runs fired at most by `MissedPolicy::All`.
+pub const MAX_CATCH_UP: usize = 100;
+/// Due jobs are looked for at least this often.
+const MAX_SLEEP: time::Duration = time::Duration::from_secs(60);
+/// Key of the next job id, in the `scheduler_ids` namespace.
+const NEXT_ID_KEY: &str = "next";
+
+/// When a job runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Schedule {
+    Once {
+        at: DateTime<Utc>,
+    },
+    /// Every `seconds`, the first time one interval after being scheduled.
+    Every {
+        seconds: i64,
+    },
+    /// On the minutes matching a cron expression, see `Cron`.
+    Cron {
+        expression: String,
+    },
+}
+
+impl Schedule {
+    pub fn at(at: DateTime<Utc>) -> Self {
+        Schedule::Once { at }
+    }
+
+    pub fn every(interval: Duration) -> CmdResult<Self> {
+        match interval.num_seconds() {
+            seconds if seconds > 0 => Ok(Schedule::Every { seconds }),
+            _ => Err(CoreError::InvalidSchedule {
+                message: String::from("intervals last at least a second"),
+            }
+            .into()),
+        }
+    }
+
+    pub fn cron(expression: &str) -> CmdResult<Self> {
+        expression.parse::<Cron>()?;
+        Ok(Schedule::Cron {
+            expression: expression.to_owned(),
+        })
+    }
+
+    /// The first run strictly after `after`, `None` once there is none left
+    /// or when it is too far away to be represented.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Once { at } => Some(*at).filter(|&at| at > after),
+            Schedule::Every { seconds } => Some(*seconds)
+                .filter(|&seconds| seconds <= Duration::max_value().num_seconds())
+                .and_then(|seconds| after.checked_add_signed(Duration::seconds(seconds))),
+            Schedule::Cron { expression } => expression.parse::<Cron>().ok()?.next_after(after),
+        }
+    }
+}
+
+/// What to do with the runs of a job missed while the bot was down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissedPolicy {
+    /// Drop runs later than `GRACE_SECS`.
+    Skip,
+    /// Fire the last missed run only.
+    Once,
+    /// Fire every missed run, up to `MAX_CATCH_UP`.
+    All,
+}
+
+impl Default for MissedPolicy {
+    fn default() -> Self {
+        MissedPolicy::Once
+    }
+}
+
+/// A job as persisted, its payload telling its handler what to do.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    /// The name the handler of the job is registered under.
+    pub kind: String,
+    pub payload: Value,
+    pub schedule: Schedule,
+    pub missed: MissedPolicy,
+    /// When the job runs next, or while it runs when it was due.
+    pub next: DateTime<Utc>,
+}
+
+impl Job {
+    pub fn payload<T: DeserializeOwned>(&self) -> CmdResult<T> {
+        serde_json::from_value(self.payload.clone()).map_err(|err| {
+            CoreError::Storage {
+                message: format!("Invalid payload for job {}: {}", self.id, err),
+            }
+            .into()
+        })
+    }
+
+    /// The runs to fire at `now` following the missed policy, and the next run after them.
+    fn due_runs(&self, now: DateTime<Utc>) -> (Vec<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        let mut due = Vec::new();
+        let mut next = Some(self.next);
+
+        while let Some(at) = next.filter(|&at| at <= now) {
+            if due.len() == MAX_CATCH_UP {
+                next = self.schedule.next_after(now);
+                break;
+            }
+            due.push(at);
+            next = self.schedule.next_after(at);
+        }
+
+        let fired = match self.missed {
+            MissedPolicy::All => due,
+            MissedPolicy::Once => due.pop().into_iter().collect(),
+            MissedPolicy::Skip => due
+                .pop()
+                .filter(|&at| now - at <= Duration::seconds(GRACE_SECS))
+                .into_iter()
+                .collect(),
+        };
+
+        (fired, next)
+    }
+}
+
+/// The code run by the jobs of a kind.
+#[async_trait]
+pub trait JobHandler: Send + Sync {
+    async fn run(&self, job: &Job) -> CmdResult<()>;
+}
+
+fn job_key(id: u64) -> String {
+    // Padded so keys sort like ids
+    format!("{:020}", id)
+}
+
+fn cancel_job(jobs: &Namespace, changed: &Notify, id: u64) -> CmdResult<bool> {
+    if jobs.get::<Job>(&job_key(id))?.is_none() {
+        return Ok(false);
+    }
+
+    jobs.remove(&job_key(id))?;
+    changed.notify();
+    Ok(true)
+}
+
+/// Cancels the job it was given for.
+pub struct JobHandle {
+    id: u64,
+    jobs: Namespace,
+    changed: Arc<Notify>,
+}
+
+impl JobHandle {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// `false` if the job already ran for the last time or was cancelled.
+    pub fn cancel(&self) -> CmdResult<bool> {
+        cancel_job(&self.jobs, &self.changed, self.id)
+    }
+}
+
+/// Runs jobs later or repeatedly, keeping them in the `scheduler` namespace
+/// of a `Storage` so they survive restarts.
+///
+/// Ids are never given twice, the next one being kept in the `scheduler_ids` namespace.
+///
+/// Jobs only hold data, modules register the `JobHandler` of their kinds of jobs on startup.
+/// Jobs of a kind without handler wait for one.
+pub struct Scheduler {
+    clock: Arc<dyn Clock>,
+    jobs: Namespace,
+    handlers: RwLock<HashMap<String, Arc<dyn JobHandler>>>,
+    ids: Namespace,
+    next_id: Mutex<u64>,
+    changed: Arc<Notify>,
+}
+
+impl Scheduler {
+    pub fn new(storage: Arc<dyn Storage>, clock: Arc<dyn Clock>) -> CmdResult<Self> {
+        let jobs = Namespace::new(storage.clone(), "scheduler");
+        let ids = Namespace::new(storage, "scheduler_ids");
+        // Jobs stored before the counter was keep their ids
+        let last = jobs
+            .keys()?
+            .last()
+            .and_then(|key| key.parse::<u64>().ok())
+            .unwrap_or(0);
+        let next_id = ids.get::<u64>(NEXT_ID_KEY)?.unwrap_or(0).max(last + 1);
+
+        Ok(Self {
+            clock,
+            jobs,
+            handlers: RwLock::default(),
+            ids,
+            next_id: Mutex::new(next_id),
+            changed: Arc::new(Notify::new()),
+        })
+    }
+
+    /// A new id, stored as taken before it is given.
+    fn take_id(&self) -> CmdResult<u64> {
+        let mut next_id = self.next_id.lock().unwrap();
+        let id = *next_id;
+        self.ids.set(NEXT_ID_KEY, &(id + 1))?;
+        *next_id = id + 1;
+        Ok(id)
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    pub fn register<H: JobHandler + 'static>(&self, kind: &str, handler: H) {
+        let mut handlers = self.handlers.write().unwrap();
+        handlers.insert(kind.to_owned(), Arc::new(handler));
+        self.changed.notify();
+    }
+
+    fn handler(&self, kind: &str) -> Option<Arc<dyn JobHandler>> {
+        self.handlers.read().unwrap().get(kind).cloned()
+    }
+
+    pub fn add<T: Serialize>(
+        &self,
+        kind: &str,
+        payload: &T,
+        schedule: Schedule,
+        missed: MissedPolicy,
+    ) -> CmdResult<JobHandle> {
+        let next = schedule
+            .next_after(self.now())
+            .ok_or_else(|| CoreError::InvalidSchedule {
+                message: String::from("it never runs"),
+            })?;
+        let payload = serde_json::to_value(payload).map_err(|err| CoreError::Storage {
+            message: err.to_string(),
+        })?;
+        let job = Job {
+            id: self.take_id()?,
+            kind: kind.to_owned(),
+            payload,
+            schedule,
+            missed,
+            next,
+        };
+
+        self.jobs.set(&job_key(job.id), &job)?;
+        self.changed.notify();
+        Ok(self.handle(job.id))
+    }
+
+    /// Run a job once, after `delay`.
+    pub fn delay<T: Serialize>(
+        &self,
+        kind: &str,
+        payload: &T,
+        delay: Duration,
+    ) -> CmdResult<JobHandle> {
+        let now = self.now();
+        let at = now
+            .checked_add_signed(delay)
+            .ok_or_else(|| CoreError::InvalidSchedule {
+                message: format!("{} is too far away", delay),
+            })?;
+        self.add(kind, payload, Schedule::at(at), MissedPolicy::default())
+    }
+
+    pub fn handle(&self, id: u64) -> JobHandle {
+        JobHandle {
+            id,
+            jobs: self.jobs.clone(),
+            changed: self.changed.clone(),
+        }
+    }
+
+    pub fn cancel(&self, id: u64) -> CmdResult<bool> {
+        cancel_job(&self.jobs, &self.changed, id)
+    }
+
+    pub fn job(&self, id: u64) -> CmdResult<Option<Job>> {
+        self.jobs.get(&job_key(id))
+    }
+
+    /// Every job waiting to run, by id.
+    pub fn jobs(&self) -> CmdResult<Vec<Job>> {
+        let mut jobs = Vec::new();
+        for key in self.jobs.keys()? {
+            jobs.extend(self.jobs.get::<Job>(&key)?);
+        }
+
+        Ok(jobs)
+    }
+
+    /// Fire the jobs due by now, giving how many runs were fired.
+    /// Failed runs are logged, they count as run.
+    pub async fn run_due(&self) -> CmdResult<usize> {
+        let now = self.now();
+        let mut fired = 0;
+
+        for mut job in self.jobs()? {
+            if job.next > now {
+                continue;
+            }
+            let handler = match self.handler(&job.kind) {
+                Some(handler) => handler,
+                None => {
+                    debug!("No handler yet for job {} of kind {}", job.id, job.kind);
+                    continue;
+                }
+            };
+
+            let (runs, next) = job.due_runs(now);
+            for at in runs {
+                job.next = at;
+                if let Err(err) = handler.run(&job).await {
+                    warn!("Job {} of kind {} failed: {}", job.id, job.kind, err);
+                }
+                fired += 1;
+            }
+
+            // The job may have cancelled itself
+            if self.job(job.id)?.is_none() {
+                continue;
+            }
+            match next {
+                Some(next) => {
+                    job.next = next;
+                    self.jobs.set(&job_key(job.id), &job)?;
+                }
+                None => self.jobs.remove(&job_key(job.id))?,
+            }
+        }
+
+        Ok(fired)
+    }
+
+    /// How long until a job with a handler is due, at most `MAX_SLEEP`.
+    fn until_next(&self) -> CmdResult<time::Duration> {
+        let now = self.now();
+        let next = self
+            .jobs()?
+            .into_iter()
+            .filter(|job| self.handler(&job.kind).is_some())
+            .map(|job| job.next)
+            .min();
+
+        Ok(match next {
+            Some(next) => (next - now).to_std().unwrap_or_default().min(MAX_SLEEP),
+            None => MAX_SLEEP,
+        })
+    }
+
+    /// Fire jobs as they become due, until shutting down.
+    /// Running jobs are in flight, a shutdown waits for them.
+    pub async fn run(&self, shutdown: &Shutdown) {
+        loop {
+            match shutdown.enter() {
+                Some(_in_flight) => {
+                    if let Err(err) = self.run_due().await {
+                        warn!("Failed to run the scheduled jobs: {}", err);
+                    }
+                }
+                None => return,
+            }
+
+            let wait = self.until_next().unwrap_or(MAX_SLEEP);
+            tokio::select! {
+                _ = delay_for(wait) => {}
+                _ = self.changed.notified() => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::CoreError;
+    use crate::scheduler::{Job, JobHandler, MissedPolicy, Schedule, Scheduler};
+    use crate::storage::{MemoryStorage, Storage};
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use ffirs_commands::clock::MockClock;
+    use ffirs_commands::error::CmdResult;
+    use serde_json::Value;
+    use serenity::async_trait;
+    use std::sync::{Arc, Mutex};
+
+    /// Keeps the payload of every run, with when it was due.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<(String, DateTime<Utc>)>>>);
+
+    impl Recorder {
+        fn take(&self) -> Vec<(String, DateTime<Utc>)> {
+            self.0.lock().unwrap().drain(..).collect()
+        }
+    }
+
+    #[async_trait]
+    impl JobHandler for Recorder {
+        async fn run(&self, job: &Job) -> CmdResult<()> {
+            let payload = job.payload::<String>()?;
+            self.0.lock().unwrap().push((payload, job.next));
+            Ok(())
+        }
+    }
+
+    fn start() -> DateTime<Utc> {
+        Utc.ymd(2020, 6, 1).and_hms(12, 0, 0)
+    }
+
+    fn make_scheduler(storage: Arc<dyn Storage>, clock: Arc<MockClock>) -> (Scheduler, Recorder) {
+        let scheduler = Scheduler::new(storage, clock).unwrap();
+        let recorder = Recorder::default();
+        scheduler.register("record", recorder.clone());
+        (scheduler, recorder)
+    }
+
+    #[tokio::test]
+    pub async fn test_scheduler_run_due() {
+        let clock = Arc::new(MockClock::new(start()));
+        let (scheduler, recorder) = make_scheduler(Arc::new(MemoryStorage::new()), clock.clone());
+
+        scheduler
+            .delay("record", &"once", Duration::minutes(5))
+            .unwrap();
+        let every = Schedule::every(Duration::minutes(2)).unwrap();
+        scheduler
+            .add("record", &"every", every, MissedPolicy::default())
+            .unwrap();
+        let cancelled = scheduler
+            .delay("record", &"cancelled", Duration::minutes(1))
+            .unwrap();
+        scheduler
+            .delay("unknown", &"waiting", Duration::minutes(1))
+            .unwrap();
+
+        assert_eq!(scheduler.run_due().await.unwrap(), 0);
+        assert!(cancelled.cancel().unwrap());
+        assert!(!cancelled.cancel().unwrap());
+
+        clock.advance(Duration::minutes(2));
+        assert_eq!(scheduler.run_due().await.unwrap(), 1);
+        assert_eq!(
+            recorder.take(),
+            vec![("every".into(), start() + Duration::minutes(2))]
+        );
+
+        clock.advance(Duration::minutes(3));
+        assert_eq!(scheduler.run_due().await.unwrap(), 2);
+        assert_eq!(
+            recorder.take(),
+            vec![
+                ("once".into(), start() + Duration::minutes(5)),
+                ("every".into(), start() + Duration::minutes(4)),
+            ]
+        );
+
+        // The one-shot is gone, the other waits for its handler
+        let jobs = scheduler.jobs().unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].next, start() + Duration::minutes(6));
+        assert_eq!(jobs[1].kind, "unknown");
+
+        assert!(matches!(
+            Schedule::every(Duration::zero())
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::InvalidSchedule { .. })
+        ));
+        let past = Schedule::at(start());
+        assert!(scheduler
+            .add("record", &"past", past, MissedPolicy::default())
+            .is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_scheduler_far_future() {
+        let clock = Arc::new(MockClock::new(start()));
+        let (scheduler, _) = make_scheduler(Arc::new(MemoryStorage::new()), clock);
+
+        let endless = Schedule::Every { seconds: i64::MAX };
+        assert_eq!(endless.next_after(start()), None);
+        let forever = Schedule::every(Duration::max_value()).unwrap();
+        assert!(matches!(
+            scheduler
+                .add("record", &"forever", forever, MissedPolicy::default())
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::InvalidSchedule { .. })
+        ));
+        assert!(scheduler
+            .delay("record", &"forever", Duration::max_value())
+            .is_err());
+
+        // Runs once, the next run being past the last representable date
+        let at = start() + Duration::days(365 * 200_000);
+        let job = Job {
+            id: 1,
+            kind: "record".into(),
+            payload: Value::Null,
+            schedule: Schedule::every(Duration::days(365 * 200_000)).unwrap(),
+            missed: MissedPolicy::default(),
+            next: at,
+        };
+        assert_eq!(job.due_runs(at), (vec![at], None));
+    }
+
+    #[tokio::test]
+    pub async fn test_scheduler_missed_runs() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let clock = Arc::new(MockClock::new(start()));
+        let (scheduler, _) = make_scheduler(storage.clone(), clock.clone());
+
+        let hourly = Schedule::cron("0 * * * *").unwrap();
+        for &missed in &[MissedPolicy::Skip, MissedPolicy::Once, MissedPolicy::All] {
+            let payload = format!("{:?}", missed);
+            scheduler
+                .add("record", &payload, hourly.clone(), missed)
+                .unwrap();
+        }
+
+        // Down from 12:00 to 15:30, the runs of 13:00, 14:00 and 15:00 are missed
+        clock.advance(Duration::minutes(210));
+        let (scheduler, recorder) = make_scheduler(storage.clone(), clock.clone());
+        assert_eq!(scheduler.run_due().await.unwrap(), 4);
+
+        let hour = |hour: u32| Utc.ymd(2020, 6, 1).and_hms(hour, 0, 0);
+        assert_eq!(
+            recorder.take(),
+            vec![
+                ("Once".into(), hour(15)),
+                ("All".into(), hour(13)),
+                ("All".into(), hour(14)),
+                ("All".into(), hour(15)),
+            ]
+        );
+        assert!(scheduler
+            .jobs()
+            .unwrap()
+            .iter()
+            .all(|job| job.next == hour(16)));
+
+        // On time, every policy fires
+        clock.set(hour(16) + Duration::seconds(30));
+        assert_eq!(scheduler.run_due().await.unwrap(), 3);
+
+        // Ids go on after a restart
+        let handle = scheduler
+            .delay("record", &"later", Duration::hours(1))
+            .unwrap();
+        assert_eq!(handle.id(), 4);
+
+        // Even those of jobs which are gone
+        handle.cancel().unwrap();
+        let (scheduler, _) = make_scheduler(storage, clock);
+        let handle = scheduler
+            .delay("record", &"later", Duration::hours(1))
+            .unwrap();
+        assert_eq!(handle.id(), 5);
+    }
+}
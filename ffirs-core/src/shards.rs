@@ -0,0 +1,297 @@
+use crate::state::SharedState;
+use ffirs_commands::cmd_args::CmdArgs;
+use ffirs_commands::cmd_creator::CmdCreator;
+use ffirs_commands::cmd_handler::CmdHandler;
+use ffirs_commands::cmd_tree::CmdNode;
+use ffirs_commands::context::{CmdContext, Embed};
+use ffirs_commands::error::{CmdError, CmdResult};
+use ffirs_commands::locale::Locales;
+use ffirs_commands::perms::{PermLevel, PermRequirement};
+use serde::Deserialize;
+use serenity::{
+    async_trait, client::bridge::gateway::ShardManager, model::id::GuildId, prelude::Mutex, Client,
+};
+use std::{collections::BTreeMap, str::FromStr, sync::Arc, time::Duration};
+
+/// How the bot connects to the gateway, the `[sharding]` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum Sharding {
+    /// A single shard.
+    Single,
+    /// As many shards as Discord recommends.
+    Auto,
+    /// `total` shards, all run by this process.
+    Fixed { total: u64 },
+    /// Shards `first` to `last` of `total`, the others being run by other processes.
+    Range { first: u64, last: u64, total: u64 },
+}
+
+impl Default for Sharding {
+    #[cfg(feature = "sharded")]
+    fn default() -> Self {
+        Sharding::Auto
+    }
+
+    #[cfg(not(feature = "sharded"))]
+    fn default() -> Self {
+        Sharding::Single
+    }
+}
+
+/// `single`, `auto`, `<total>` or `<first>-<last>/<total>`, as in `FFIRS_SHARDING`.
+impl FromStr for Sharding {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "expected single, auto, <total> or <first>-<last>/<total>, got {}",
+                value
+            )
+        };
+        let number = |value: &str| value.trim().parse::<u64>().map_err(|_| invalid());
+
+        match value.trim() {
+            "single" => Ok(Sharding::Single),
+            "auto" => Ok(Sharding::Auto),
+            value => {
+                let mut parts = value.splitn(2, '/');
+                let range = parts.next().unwrap_or_default();
+                let total = match parts.next() {
+                    Some(total) => number(total)?,
+                    None => {
+                        return Ok(Sharding::Fixed {
+                            total: number(range)?,
+                        })
+                    }
+                };
+
+                let mut bounds = range.splitn(2, '-');
+                let first = number(bounds.next().unwrap_or_default())?;
+                let last = number(bounds.next().ok_or_else(invalid)?)?;
+                Ok(Sharding::Range { first, last, total })
+            }
+        }
+    }
+}
+
+impl Sharding {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Sharding::Fixed { total: 0 } | Sharding::Range { total: 0, .. } => {
+                Err(String::from("total must be at least 1"))
+            }
+            Sharding::Range { first, last, total } if first > last || last >= total => {
+                Err(format!(
+                    "expected first <= last < total, got {}-{}/{}",
+                    first, last, total
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Connect `client` the way this mode says.
+    pub async fn start(&self, client: &mut Client) -> serenity::Result<()> {
+        match *self {
+            Sharding::Single => client.start().await,
+            Sharding::Auto => client.start_autosharded().await,
+            Sharding::Fixed { total } => client.start_shards(total).await,
+            Sharding::Range { first, last, total } => {
+                client.start_shard_range([first, last], total).await
+            }
+        }
+    }
+}
+
+/// The shard handling the events of `guild`, out of `total`.
+pub fn shard_of(guild: GuildId, total: u64) -> u64 {
+    (guild.0 >> 22) % total.max(1)
+}
+
+/// What the `shards` command shows of a shard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardStatus {
+    pub id: u64,
+    pub stage: String,
+    pub latency: Option<Duration>,
+    pub guilds: usize,
+}
+
+impl ShardStatus {
+    pub fn line(&self, locales: &Locales, locale: &str) -> String {
+        let latency = match self.latency {
+            Some(latency) => format!("{} ms", latency.as_millis()),
+            None => locales.tr(locale, "shards-latency-unknown", &[]),
+        };
+
+        locales.tr(
+            locale,
+            "shards-line",
+            &[
+                ("id", self.id.to_string()),
+                ("stage", self.stage.clone()),
+                ("latency", latency),
+                ("guilds", self.guilds.to_string()),
+            ],
+        )
+    }
+}
+
+/// Count the guilds of every shard, out of `total`.
+pub fn guilds_per_shard(guilds: &[GuildId], total: u64) -> BTreeMap<u64, usize> {
+    let mut counts = BTreeMap::new();
+    for &guild in guilds {
+        *counts.entry(shard_of(guild, total)).or_insert(0) += 1;
+    }
+
+    counts
+}
+
+/// `shards`: the state of the shards run by this process, for the bot owners.
+pub struct ShardsCmd {
+    locales: Arc<Locales>,
+}
+
+impl ShardsCmd {
+    async fn statuses(ctx: &dyn CmdContext) -> CmdResult<Vec<ShardStatus>> {
+        // Without a gateway there is no shard to tell about
+        let (discord, _) = ctx.discord().ok_or(CmdError::DiscordOnly)?;
+        let manager = SharedState::of(ctx)
+            .require::<Arc<Mutex<ShardManager>>>()
+            .await?;
+
+        let total = discord.cache.shard_count().await;
+        let guilds = guilds_per_shard(&discord.cache.guilds().await, total);
+
+        let manager = manager.lock().await;
+        let runners = manager.runners.lock().await;
+        let mut statuses = runners
+            .iter()
+            .map(|(id, runner)| ShardStatus {
+                id: id.0,
+                stage: format!("{:?}", runner.stage),
+                latency: runner.latency,
+                guilds: guilds.get(&id.0).copied().unwrap_or(0),
+            })
+            .collect::<Vec<_>>();
+        statuses.sort_unstable_by_key(|status| status.id);
+
+        Ok(statuses)
+    }
+}
+
+#[async_trait]
+impl CmdHandler for ShardsCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, _args: &mut CmdArgs) -> CmdResult<()> {
+        let locale = self.locales.locale_of(ctx.author().id, ctx.guild());
+        let title = self.locales.tr(&locale, "shards-title", &[]);
+        let lines = Self::statuses(ctx)
+            .await?
+            .iter()
+            .map(|status| status.line(&self.locales, &locale))
+            .collect::<Vec<_>>();
+
+        let embed = Embed::new().title(title).description(lines.join("\n"));
+        ctx.send_embed(&embed).await?;
+
+        Ok(())
+    }
+}
+
+pub fn shards_command(locales: Arc<Locales>) -> CmdResult<CmdNode> {
+    let mut shards = CmdCreator::create_cmd("shards", ShardsCmd { locales })?;
+    shards.requirement = Some(PermRequirement::level(PermLevel::BOT_OWNER));
+    shards.description = Some("shards-description".into());
+
+    Ok(shards)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::locale::core_locales;
+    use crate::shards::{guilds_per_shard, shard_of, ShardStatus, Sharding};
+    use serenity::model::id::GuildId;
+    use std::time::Duration;
+
+    #[test]
+    pub fn test_sharding_parse() {
+        let config =
+            Config::parse("[sharding]\nmode = \"range\"\nfirst = 2\nlast = 3\ntotal = 8").unwrap();
+        assert_eq!(
+            config.sharding,
+            Sharding::Range {
+                first: 2,
+                last: 3,
+                total: 8
+            }
+        );
+        let config = Config::parse("[sharding]\nmode = \"auto\"").unwrap();
+        assert_eq!(config.sharding, Sharding::Auto);
+        assert!(Config::parse("[sharding]\nmode = \"fixed\"").is_err());
+
+        assert_eq!("single".parse(), Ok(Sharding::Single));
+        assert_eq!("4".parse(), Ok(Sharding::Fixed { total: 4 }));
+        assert_eq!(
+            "0-1/4".parse(),
+            Ok(Sharding::Range {
+                first: 0,
+                last: 1,
+                total: 4
+            })
+        );
+        assert!("0/4".parse::<Sharding>().is_err());
+        assert!("many".parse::<Sharding>().is_err());
+    }
+
+    #[test]
+    pub fn test_sharding_validate() {
+        assert!(Sharding::Fixed { total: 2 }.validate().is_ok());
+        assert!(Sharding::Fixed { total: 0 }.validate().is_err());
+        for &(first, last, total) in [(0, 3, 4), (2, 2, 4)].iter() {
+            assert!(Sharding::Range { first, last, total }.validate().is_ok());
+        }
+        for &(first, last, total) in [(0, 4, 4), (3, 2, 4), (0, 0, 0)].iter() {
+            assert!(Sharding::Range { first, last, total }.validate().is_err());
+        }
+    }
+
+    #[test]
+    pub fn test_guilds_per_shard() {
+        let guilds = [
+            GuildId(0),
+            GuildId(1 << 22),
+            GuildId(2 << 22),
+            GuildId(5 << 22),
+        ];
+
+        assert_eq!(shard_of(GuildId(5 << 22), 2), 1);
+        assert_eq!(shard_of(GuildId(5 << 22), 0), 0);
+        let counts = guilds_per_shard(&guilds, 2);
+        assert_eq!(counts.get(&0), Some(&2));
+        assert_eq!(counts.get(&1), Some(&2));
+    }
+
+    #[test]
+    pub fn test_shard_status_line() {
+        let locales = core_locales().unwrap();
+        let mut status = ShardStatus {
+            id: 1,
+            stage: String::from("Connected"),
+            latency: Some(Duration::from_millis(42)),
+            guilds: 3,
+        };
+
+        assert_eq!(
+            status.line(&locales, "en"),
+            "Shard 1: Connected, 42 ms, 3 guilds"
+        );
+        status.latency = None;
+        assert_eq!(
+            status.line(&locales, "fr"),
+            "Shard 1 : Connected, latence inconnue, 3 serveurs"
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use crate::module_manager::ModuleManager;
+use ffirs_commands::cmd_args::CmdArgs;
+use ffirs_commands::cmd_creator::CmdCreator;
+use ffirs_commands::cmd_handler::CmdHandler;
+use ffirs_commands::cmd_tree::CmdNode;
+use ffirs_commands::context::CmdContext;
+use ffirs_commands::error::CmdResult;
+use ffirs_commands::locale::Locales;
+use ffirs_commands::perms::{PermLevel, PermRequirement};
+use log::{error, info, warn};
+use serenity::async_trait;
+use std::{
+    env, io, process,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+use tokio::sync::Notify;
+use tokio::time::{delay_for, timeout};
+
+/// How long running commands and jobs get to finish by default.
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+const DRAIN_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownKind {
+    Stop,
+    Restart,
+}
+
+type FlushHook = Box<dyn Fn() -> CmdResult<()> + Send + Sync>;
+
+/// Coordinates stopping the bot: no new work is accepted once a shutdown is requested,
+/// the work in flight is drained, storage flushed and modules shut down.
+pub struct Shutdown {
+    requested: Mutex<Option<ShutdownKind>>,
+    notify: Notify,
+    in_flight: AtomicUsize,
+    drain_timeout: Duration,
+    flush_hooks: RwLock<Vec<FlushHook>>,
+    ran: AtomicBool,
+}
+
+/// Work in flight, which a shutdown waits for until it is dropped.
+pub struct InFlight<'a> {
+    shutdown: &'a Shutdown,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.shutdown.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Shutdown {
+    pub fn new(drain_timeout: Duration) -> Self {
+        Self {
+            requested: Mutex::new(None),
+            notify: Notify::new(),
+            in_flight: AtomicUsize::new(0),
+            drain_timeout,
+            flush_hooks: RwLock::default(),
+            ran: AtomicBool::new(false),
+        }
+    }
+
+    /// Ask the bot to stop, only the first request counts.
+    pub fn request(&self, kind: ShutdownKind) -> bool {
+        let mut requested = self.requested.lock().unwrap();
+        if requested.is_some() {
+            return false;
+        }
+
+        *requested = Some(kind);
+        self.notify.notify();
+        true
+    }
+
+    pub fn kind(&self) -> Option<ShutdownKind> {
+        *self.requested.lock().unwrap()
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.kind().is_some()
+    }
+
+    /// Wait for a shutdown request.
+    pub async fn requested(&self) -> ShutdownKind {
+        loop {
+            if let Some(kind) = self.kind() {
+                return kind;
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// Start some work, `None` once shutting down.
+    pub fn enter(&self) -> Option<InFlight<'_>> {
+        // Counted first so a drain starting in between waits for it
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlight { shutdown: self };
+
+        match self.is_shutting_down() {
+            true => None,
+            false => Some(guard),
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Wait for the work in flight to finish, `false` if it took too long.
+    pub async fn drain(&self) -> bool {
+        let drained = async {
+            while self.in_flight() > 0 {
+                delay_for(DRAIN_POLL).await;
+            }
+        };
+
+        timeout(self.drain_timeout, drained).await.is_ok()
+    }
+
+    /// Save what storage keeps in memory, run after draining.
+    pub fn on_flush<F: Fn() -> CmdResult<()> + Send + Sync + 'static>(&self, hook: F) {
+        self.flush_hooks.write().unwrap().push(Box::new(hook));
+    }
+
+    /// Drain, flush and shut the modules down, once.
+    /// Shards are closed by the caller, after this.
+    pub async fn run(&self, modules: &ModuleManager) {
+        if self.ran.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // Nothing new may start from now on
+        self.request(ShutdownKind::Stop);
+
+        info!("Shutting down, waiting for {} tasks", self.in_flight());
+        if !self.drain().await {
+            warn!(
+                "{} tasks still running after {:?}, stopping anyway",
+                self.in_flight(),
+                self.drain_timeout
+            );
+        }
+
+        for hook in self.flush_hooks.read().unwrap().iter() {
+            if let Err(err) = hook() {
+                error!("Failed to flush storage: {}", err);
+            }
+        }
+
+        modules.shutdown().await;
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new(DRAIN_TIMEOUT)
+    }
+}
+
+/// Request a shutdown on SIGINT or SIGTERM, exiting right away on a second signal.
+pub fn stop_on_signals(shutdown: Arc<Shutdown>) {
+    tokio::spawn(async move {
+        for signals in 0.. {
+            if let Err(err) = next_signal().await {
+                warn!("Can't listen to stop signals: {}", err);
+                return;
+            }
+
+            if signals == 0 {
+                info!("Stop signal received");
+                shutdown.request(ShutdownKind::Stop);
+            } else {
+                warn!("Second stop signal received, exiting now");
+                process::exit(130);
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn next_signal() -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = interrupt.recv() => {}
+        _ = terminate.recv() => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn next_signal() -> io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
+
+/// Replace the process by a new instance of the bot, with the same arguments.
+pub fn restart() -> io::Result<()> {
+    let exe = env::current_exe()?;
+    let mut command = process::Command::new(exe);
+    command.args(env::args_os().skip(1));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Only returns on failure
+        Err(command.exec())
+    }
+    #[cfg(not(unix))]
+    {
+        command.spawn()?;
+        process::exit(0)
+    }
+}
+
+/// `shutdown` and `restart`: stop the bot cleanly, for its owners only.
+pub struct ShutdownCmd {
+    shutdown: Arc<Shutdown>,
+    locales: Arc<Locales>,
+    kind: ShutdownKind,
+}
+
+#[async_trait]
+impl CmdHandler for ShutdownCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, _args: &mut CmdArgs) -> CmdResult<()> {
+        let locale = self.locales.locale_of(ctx.author().id, ctx.guild());
+        let key = match self.kind {
+            ShutdownKind::Stop => "shutdown-started",
+            ShutdownKind::Restart => "restart-started",
+        };
+        ctx.reply(&self.locales.tr(&locale, key, &[])).await?;
+
+        self.shutdown.request(self.kind);
+        Ok(())
+    }
+}
+
+pub fn shutdown_commands(
+    shutdown: Arc<Shutdown>,
+    locales: Arc<Locales>,
+) -> CmdResult<Vec<CmdNode>> {
+    let kinds = [
+        ("shutdown", ShutdownKind::Stop),
+        ("restart", ShutdownKind::Restart),
+    ];
+    let mut commands = Vec::new();
+
+    for &(name, kind) in kinds.iter() {
+        let handler = ShutdownCmd {
+            shutdown: shutdown.clone(),
+            locales: locales.clone(),
+            kind,
+        };
+        let mut command = CmdCreator::create_cmd(name, handler)?;
+        command.requirement = Some(PermRequirement::level(PermLevel::BOT_OWNER));
+        command.description = Some(format!("{}-description", name));
+        commands.push(command);
+    }
+
+    // `stop` used to be the name of `shutdown`
+    commands[0].aliases.push(String::from("stop"));
+
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shutdown::{Shutdown, ShutdownKind};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::time::delay_for;
+
+    #[test]
+    pub fn test_enter_after_request() {
+        let shutdown = Shutdown::default();
+
+        let guard = shutdown.enter();
+        assert!(guard.is_some());
+        assert_eq!(shutdown.in_flight(), 1);
+
+        assert!(shutdown.request(ShutdownKind::Restart));
+        assert!(!shutdown.request(ShutdownKind::Stop));
+        assert_eq!(shutdown.kind(), Some(ShutdownKind::Restart));
+
+        assert!(shutdown.enter().is_none());
+        assert_eq!(shutdown.in_flight(), 1);
+        drop(guard);
+        assert_eq!(shutdown.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    pub async fn test_drain() {
+        let shutdown = Arc::new(Shutdown::new(Duration::from_secs(5)));
+
+        let running = shutdown.clone();
+        tokio::spawn(async move {
+            let _guard = running.enter();
+            delay_for(Duration::from_millis(100)).await;
+        });
+        delay_for(Duration::from_millis(10)).await;
+
+        let waiter = shutdown.clone();
+        let requested = tokio::spawn(async move { waiter.requested().await });
+        shutdown.request(ShutdownKind::Stop);
+        assert_eq!(requested.await.unwrap(), ShutdownKind::Stop);
+
+        assert!(shutdown.drain().await);
+        assert_eq!(shutdown.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    pub async fn test_drain_timeout() {
+        let shutdown = Shutdown::new(Duration::from_millis(100));
+
+        let _stuck = shutdown.enter();
+        assert!(!shutdown.drain().await);
+    }
+}
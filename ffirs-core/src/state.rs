@@ -0,0 +1,88 @@
+use crate::error::CoreError;
+use ffirs_commands::context::CmdContext;
+use ffirs_commands::error::CmdResult;
+use ffirs_commands::type_map::AnyTypeMap;
+use std::{any::Any, sync::Arc};
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Services shared by the modules and the command handlers, like the database,
+/// the configuration or the HTTP client, one value per type.
+#[derive(Clone, Default)]
+pub struct SharedState(Arc<RwLock<AnyTypeMap>>);
+
+impl SharedState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The state shared with the command running in `ctx`, see `CmdContext::data`.
+    pub fn of(ctx: &dyn CmdContext) -> Self {
+        Self(ctx.data().clone())
+    }
+
+    /// The values of the state, to share with the contexts of the commands.
+    pub fn data(&self) -> &Arc<RwLock<AnyTypeMap>> {
+        &self.0
+    }
+
+    pub async fn read(&self) -> RwLockReadGuard<'_, AnyTypeMap> {
+        self.0.read().await
+    }
+
+    pub async fn write(&self) -> RwLockWriteGuard<'_, AnyTypeMap> {
+        self.0.write().await
+    }
+
+    /// A copy of the value of type `T`, services being shared behind an `Arc`.
+    pub async fn get<T: Any + Send + Sync + Clone>(&self) -> Option<T> {
+        self.read().await.get::<T>().cloned()
+    }
+
+    /// Same as `get`, failing when nothing of type `T` was shared.
+    pub async fn require<T: Any + Send + Sync + Clone>(&self) -> CmdResult<T> {
+        self.get::<T>().await.ok_or_else(|| {
+            CoreError::MissingData {
+                name: std::any::type_name::<T>().to_owned(),
+            }
+            .into()
+        })
+    }
+
+    pub async fn insert<T: Any + Send + Sync>(&self, value: T) -> Option<T> {
+        self.write().await.insert(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::CoreError;
+    use crate::state::SharedState;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    pub async fn test_shared_state() {
+        let state = SharedState::new();
+        let handle = state.clone();
+
+        assert_eq!(state.insert(Arc::new(String::from("db"))).await, None);
+        assert_eq!(
+            handle
+                .get::<Arc<String>>()
+                .await
+                .as_deref()
+                .map(String::as_str),
+            Some("db")
+        );
+        assert!(matches!(
+            handle
+                .require::<Arc<u64>>()
+                .await
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::MissingData { .. })
+        ));
+
+        *handle.write().await.entry::<u64>().or_default() += 2;
+        assert_eq!(state.read().await.get::<u64>(), Some(&2));
+    }
+}
@@ -0,0 +1,760 @@
+use crate::error::CoreError;
+use chrono::{DateTime, Utc};
+use ffirs_commands::cooldown::{CooldownKey, CooldownScope, CooldownStore, MemoryCooldownStore};
+use ffirs_commands::error::{CmdError, CmdResult};
+use ffirs_commands::locale::{LocaleOwner, LocaleStore};
+use ffirs_commands::perms::{GuildPermOverrides, PermStore};
+use log::warn;
+use rusqlite::{params, Connection, OptionalExtension, NO_PARAMS};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serenity::model::id::{GuildId, RoleId, UserId};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    io::{self, ErrorKind},
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+/// Key/value storage, every module keeping its values in its own namespace.
+pub trait Storage: Send + Sync {
+    fn get(&self, namespace: &str, key: &str) -> CmdResult<Option<String>>;
+    fn set(&self, namespace: &str, key: &str, value: &str) -> CmdResult<()>;
+    fn remove(&self, namespace: &str, key: &str) -> CmdResult<()>;
+    /// Keys of `namespace`, sorted.
+    fn keys(&self, namespace: &str) -> CmdResult<Vec<String>>;
+
+    /// Make sure everything written so far is on disk, called on shutdown.
+    fn flush(&self) -> CmdResult<()> {
+        Ok(())
+    }
+}
+
+fn storage_error<E: Display>(err: E) -> CmdError {
+    CoreError::Storage {
+        message: err.to_string(),
+    }
+    .into()
+}
+
+/// Forgets everything on restart, for tests.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    values: RwLock<BTreeMap<(String, String), String>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, namespace: &str, key: &str) -> CmdResult<Option<String>> {
+        let key = (namespace.to_owned(), key.to_owned());
+        Ok(self.values.read().unwrap().get(&key).cloned())
+    }
+
+    fn set(&self, namespace: &str, key: &str, value: &str) -> CmdResult<()> {
+        let key = (namespace.to_owned(), key.to_owned());
+        self.values.write().unwrap().insert(key, value.to_owned());
+        Ok(())
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> CmdResult<()> {
+        let key = (namespace.to_owned(), key.to_owned());
+        self.values.write().unwrap().remove(&key);
+        Ok(())
+    }
+
+    fn keys(&self, namespace: &str) -> CmdResult<Vec<String>> {
+        Ok(self
+            .values
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|(key_namespace, _)| key_namespace == namespace)
+            .map(|(_, key)| key.clone())
+            .collect())
+    }
+}
+
+/// The values of a namespace, serialized as JSON.
+#[derive(Clone)]
+pub struct Namespace {
+    storage: Arc<dyn Storage>,
+    name: String,
+}
+
+impl Namespace {
+    pub fn new<S: Into<String>>(storage: Arc<dyn Storage>, name: S) -> Self {
+        Self {
+            storage,
+            name: name.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> CmdResult<Option<T>> {
+        match self.storage.get(&self.name, key)? {
+            Some(value) => serde_json::from_str(&value)
+                .map(Some)
+                .map_err(storage_error),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set<T: Serialize>(&self, key: &str, value: &T) -> CmdResult<()> {
+        let value = serde_json::to_string(value).map_err(storage_error)?;
+        self.storage.set(&self.name, key, &value)
+    }
+
+    pub fn remove(&self, key: &str) -> CmdResult<()> {
+        self.storage.remove(&self.name, key)
+    }
+
+    pub fn keys(&self) -> CmdResult<Vec<String>> {
+        self.storage.keys(&self.name)
+    }
+}
+
+/// A change of the schema of a module, applied once and in version order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    pub module: String,
+    pub version: u32,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    pub fn new<S: Into<String>>(module: S, version: u32, sql: &'static str) -> Self {
+        Self {
+            module: module.into(),
+            version,
+            sql,
+        }
+    }
+}
+
+/// Tables of the database itself, the key/value storage being one of them.
+fn storage_migrations() -> Vec<Migration> {
+    vec![Migration::new(
+        "storage",
+        1,
+        "CREATE TABLE kv (
+            namespace TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (namespace, key)
+        );",
+    )]
+}
+
+/// A SQLite database, for modules needing relational access,
+/// which is also a key/value `Storage`.
+pub struct Database {
+    connection: Mutex<Connection>,
+}
+
+impl Database {
+    /// Open or create the database at `path`, `:memory:` for one only living in memory.
+    pub fn open<P: AsRef<Path>>(path: P) -> CmdResult<Self> {
+        let connection = Connection::open(path).map_err(storage_error)?;
+        Self::with_connection(connection)
+    }
+
+    pub fn in_memory() -> CmdResult<Self> {
+        let connection = Connection::open_in_memory().map_err(storage_error)?;
+        Self::with_connection(connection)
+    }
+
+    fn with_connection(connection: Connection) -> CmdResult<Self> {
+        let database = Self {
+            connection: Mutex::new(connection),
+        };
+
+        database.call(|connection| {
+            // Readers don't wait for writers, `flush` checkpoints the log
+            connection.query_row("PRAGMA journal_mode = WAL", NO_PARAMS, |_| Ok(()))?;
+            connection.execute_batch(
+                "CREATE TABLE IF NOT EXISTS migrations (
+                    module TEXT NOT NULL,
+                    version INTEGER NOT NULL,
+                    PRIMARY KEY (module, version)
+                );",
+            )
+        })?;
+        database.migrate(&storage_migrations())?;
+
+        Ok(database)
+    }
+
+    /// Run `f` with the connection, only one caller having it at a time.
+    pub fn call<T, F: FnOnce(&mut Connection) -> rusqlite::Result<T>>(&self, f: F) -> CmdResult<T> {
+        let mut connection = self.connection.lock().unwrap();
+        f(&mut connection).map_err(storage_error)
+    }
+
+    /// Last version of `module` applied.
+    pub fn version(&self, module: &str) -> CmdResult<Option<u32>> {
+        self.call(|connection| {
+            connection.query_row(
+                "SELECT MAX(version) FROM migrations WHERE module = ?1",
+                params![module],
+                |row| row.get(0),
+            )
+        })
+    }
+
+    /// Apply the migrations not applied yet, each in its own transaction.
+    /// The modules keep the order they are given in, like `ModuleManager::migrations`
+    /// gives them, and the migrations of a module go in version order.
+    /// Gives back the ones applied.
+    pub fn migrate(&self, migrations: &[Migration]) -> CmdResult<Vec<Migration>> {
+        let mut modules = Vec::new();
+        for migration in migrations {
+            if !modules.contains(&&migration.module) {
+                modules.push(&migration.module);
+            }
+        }
+        let position = |module: &String| modules.iter().position(|known| *known == module);
+
+        let mut migrations = migrations.to_vec();
+        migrations.sort_by_key(|migration| (position(&migration.module), migration.version));
+
+        self.call(|connection| {
+            let mut applied = Vec::new();
+
+            for migration in migrations {
+                let done = connection.query_row(
+                    "SELECT COUNT(*) FROM migrations WHERE module = ?1 AND version = ?2",
+                    params![migration.module, migration.version],
+                    |row| row.get::<_, i64>(0),
+                )? > 0;
+                if done {
+                    continue;
+                }
+
+                let transaction = connection.transaction()?;
+                transaction.execute_batch(migration.sql)?;
+                transaction.execute(
+                    "INSERT INTO migrations (module, version) VALUES (?1, ?2)",
+                    params![migration.module, migration.version],
+                )?;
+                transaction.commit()?;
+                applied.push(migration);
+            }
+
+            Ok(applied)
+        })
+    }
+}
+
+impl Storage for Database {
+    fn get(&self, namespace: &str, key: &str) -> CmdResult<Option<String>> {
+        self.call(|connection| {
+            connection
+                .query_row(
+                    "SELECT value FROM kv WHERE namespace = ?1 AND key = ?2",
+                    params![namespace, key],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+    }
+
+    fn set(&self, namespace: &str, key: &str, value: &str) -> CmdResult<()> {
+        self.call(|connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO kv (namespace, key, value) VALUES (?1, ?2, ?3)",
+                params![namespace, key, value],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> CmdResult<()> {
+        self.call(|connection| {
+            connection.execute(
+                "DELETE FROM kv WHERE namespace = ?1 AND key = ?2",
+                params![namespace, key],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn keys(&self, namespace: &str) -> CmdResult<Vec<String>> {
+        self.call(|connection| {
+            let mut statement =
+                connection.prepare("SELECT key FROM kv WHERE namespace = ?1 ORDER BY key")?;
+            let keys = statement
+                .query_map(params![namespace], |row| row.get(0))?
+                .collect();
+            keys
+        })
+    }
+
+    fn flush(&self) -> CmdResult<()> {
+        self.call(|connection| {
+            connection.query_row("PRAGMA wal_checkpoint(TRUNCATE)", NO_PARAMS, |_| Ok(()))
+        })
+    }
+}
+
+fn io_error(err: CmdError) -> io::Error {
+    io::Error::new(ErrorKind::Other, err.to_string())
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredPermOverrides {
+    roles: Vec<(u64, u32)>,
+    users: Vec<(u64, u32)>,
+}
+
+/// Keeps the permission overrides in the `perms` namespace.
+///
+/// They are needed for every command with a requirement, so they are read once
+/// and kept in memory, changes being written through.
+pub struct StoragePermStore {
+    namespace: Namespace,
+    /// The overrides read or written so far, `None` for the guilds without any.
+    cache: RwLock<HashMap<GuildId, Option<GuildPermOverrides>>>,
+}
+
+impl StoragePermStore {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            namespace: Namespace::new(storage, "perms"),
+            cache: RwLock::default(),
+        }
+    }
+}
+
+impl PermStore for StoragePermStore {
+    fn get(&self, guild: GuildId) -> Option<GuildPermOverrides> {
+        if let Some(overrides) = self.cache.read().unwrap().get(&guild) {
+            return overrides.clone();
+        }
+
+        match self
+            .namespace
+            .get::<StoredPermOverrides>(&guild.0.to_string())
+        {
+            Ok(stored) => {
+                let overrides = stored.map(|stored| GuildPermOverrides {
+                    roles: stored
+                        .roles
+                        .into_iter()
+                        .map(|(role, level)| (RoleId(role), level))
+                        .collect(),
+                    users: stored
+                        .users
+                        .into_iter()
+                        .map(|(user, level)| (UserId(user), level))
+                        .collect(),
+                });
+                // A change made since the read is newer, it stays
+                self.cache
+                    .write()
+                    .unwrap()
+                    .entry(guild)
+                    .or_insert(overrides)
+                    .clone()
+            }
+            Err(err) => {
+                warn!("Failed to read the permissions of {}: {}", guild.0, err);
+                None
+            }
+        }
+    }
+
+    fn set(&self, guild: GuildId, overrides: GuildPermOverrides) -> io::Result<()> {
+        let stored = StoredPermOverrides {
+            roles: overrides
+                .roles
+                .iter()
+                .map(|(role, &level)| (role.0, level))
+                .collect(),
+            users: overrides
+                .users
+                .iter()
+                .map(|(user, &level)| (user.0, level))
+                .collect(),
+        };
+        self.namespace
+            .set(&guild.0.to_string(), &stored)
+            .map_err(io_error)?;
+        self.cache.write().unwrap().insert(guild, Some(overrides));
+        Ok(())
+    }
+
+    fn remove(&self, guild: GuildId) -> io::Result<()> {
+        self.namespace
+            .remove(&guild.0.to_string())
+            .map_err(io_error)?;
+        self.cache.write().unwrap().insert(guild, None);
+        Ok(())
+    }
+}
+
+fn locale_key(owner: LocaleOwner) -> String {
+    match owner {
+        LocaleOwner::Guild(guild) => format!("guild:{}", guild.0),
+        LocaleOwner::User(user) => format!("user:{}", user.0),
+    }
+}
+
+/// Keeps the locales chosen by guilds and users in the `locales` namespace.
+///
+/// Every reply looks them up, so they are kept in memory once read,
+/// changes being written through.
+pub struct StorageLocaleStore {
+    namespace: Namespace,
+    /// The locales read or written so far, `None` for the owners without one.
+    cache: RwLock<HashMap<LocaleOwner, Option<String>>>,
+}
+
+impl StorageLocaleStore {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            namespace: Namespace::new(storage, "locales"),
+            cache: RwLock::default(),
+        }
+    }
+}
+
+impl LocaleStore for StorageLocaleStore {
+    fn get(&self, owner: LocaleOwner) -> Option<String> {
+        if let Some(locale) = self.cache.read().unwrap().get(&owner) {
+            return locale.clone();
+        }
+
+        match self.namespace.get::<String>(&locale_key(owner)) {
+            // A change made since the read is newer, it stays
+            Ok(locale) => self
+                .cache
+                .write()
+                .unwrap()
+                .entry(owner)
+                .or_insert(locale)
+                .clone(),
+            Err(err) => {
+                warn!("Failed to read the locale of {:?}: {}", owner, err);
+                None
+            }
+        }
+    }
+
+    fn set(&self, owner: LocaleOwner, locale: &str) -> io::Result<()> {
+        self.namespace
+            .set(&locale_key(owner), &locale)
+            .map_err(io_error)?;
+        self.cache
+            .write()
+            .unwrap()
+            .insert(owner, Some(locale.to_owned()));
+        Ok(())
+    }
+
+    fn remove(&self, owner: LocaleOwner) -> io::Result<()> {
+        self.namespace
+            .remove(&locale_key(owner))
+            .map_err(io_error)?;
+        self.cache.write().unwrap().insert(owner, None);
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredCooldown {
+    bucket: String,
+    scope: String,
+    target: u64,
+    /// In milliseconds.
+    per: u64,
+    uses: Vec<DateTime<Utc>>,
+}
+
+fn scope_name(scope: CooldownScope) -> &'static str {
+    match scope {
+        CooldownScope::User => "user",
+        CooldownScope::Channel => "channel",
+        CooldownScope::Guild => "guild",
+        CooldownScope::Global => "global",
+    }
+}
+
+fn scope_of_name(name: &str) -> Option<CooldownScope> {
+    match name {
+        "user" => Some(CooldownScope::User),
+        "channel" => Some(CooldownScope::Channel),
+        "guild" => Some(CooldownScope::Guild),
+        "global" => Some(CooldownScope::Global),
+        _ => None,
+    }
+}
+
+fn cooldown_key(key: &CooldownKey) -> String {
+    format!("{}:{}:{}", scope_name(key.scope), key.target, key.bucket)
+}
+
+/// Keeps the cooldowns in the `cooldowns` namespace, so a restart doesn't reset them.
+///
+/// The buckets are all read when opening and counted in memory,
+/// every use being written through.
+pub struct StorageCooldownStore {
+    namespace: Namespace,
+    memory: MemoryCooldownStore,
+    /// Held while a bucket changes and is written, so writes land in the same order.
+    writing: Mutex<()>,
+}
+
+impl StorageCooldownStore {
+    /// Load the buckets saved before, the stale ones leaving at the next eviction.
+    pub fn open(storage: Arc<dyn Storage>) -> CmdResult<Self> {
+        let namespace = Namespace::new(storage, "cooldowns");
+        let memory = MemoryCooldownStore::new();
+
+        for key in namespace.keys()? {
+            let stored = match namespace.get::<StoredCooldown>(&key)? {
+                Some(stored) => stored,
+                None => continue,
+            };
+            match scope_of_name(&stored.scope) {
+                Some(scope) => {
+                    let key = CooldownKey {
+                        bucket: stored.bucket,
+                        scope,
+                        target: stored.target,
+                    };
+                    memory.restore(key, Duration::from_millis(stored.per), stored.uses);
+                }
+                None => warn!("Unknown scope {} for the cooldown {}", stored.scope, key),
+            }
+        }
+
+        Ok(Self {
+            namespace,
+            memory,
+            writing: Mutex::default(),
+        })
+    }
+}
+
+impl CooldownStore for StorageCooldownStore {
+    fn try_acquire(
+        &self,
+        key: &CooldownKey,
+        uses: u32,
+        per: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<(), Duration> {
+        let _writing = self.writing.lock().unwrap();
+        self.memory.try_acquire(key, uses, per, now)?;
+
+        let stored = StoredCooldown {
+            bucket: key.bucket.clone(),
+            scope: scope_name(key.scope).to_owned(),
+            target: key.target,
+            per: per.as_millis().min(u64::MAX as u128) as u64,
+            uses: self.memory.uses(key),
+        };
+        if let Err(err) = self.namespace.set(&cooldown_key(key), &stored) {
+            warn!("Failed to save the cooldown {}: {}", cooldown_key(key), err);
+        }
+
+        Ok(())
+    }
+
+    fn evict(&self, now: DateTime<Utc>) {
+        let _writing = self.writing.lock().unwrap();
+        let before = self.memory.keys();
+        self.memory.evict(now);
+        let kept = self.memory.keys().into_iter().collect::<HashSet<_>>();
+
+        for key in before.iter().filter(|key| !kept.contains(key)) {
+            if let Err(err) = self.namespace.remove(&cooldown_key(key)) {
+                warn!(
+                    "Failed to remove the cooldown {}: {}",
+                    cooldown_key(key),
+                    err
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::{
+        Database, MemoryStorage, Migration, Namespace, Storage, StorageCooldownStore,
+        StorageLocaleStore, StoragePermStore,
+    };
+    use chrono::{Duration as ChronoDuration, TimeZone, Utc};
+    use ffirs_commands::cooldown::{CooldownKey, CooldownScope, CooldownStore};
+    use ffirs_commands::locale::Locales;
+    use ffirs_commands::perms::{PermLevel, PermResolver};
+    use serenity::model::id::{GuildId, RoleId, UserId};
+    use serenity::model::permissions::Permissions;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn check_storage(storage: Arc<dyn Storage>) {
+        let prefixes = Namespace::new(storage.clone(), "prefixes");
+        let tags = Namespace::new(storage, "tags");
+
+        assert_eq!(prefixes.get::<Vec<String>>("1").unwrap(), None);
+        prefixes.set("2", &vec!["?"]).unwrap();
+        prefixes.set("1", &vec!["!", "!!"]).unwrap();
+        tags.set("1", &String::from("tag")).unwrap();
+
+        assert_eq!(
+            prefixes.get::<Vec<String>>("1").unwrap(),
+            Some(vec![String::from("!"), String::from("!!")])
+        );
+        assert_eq!(prefixes.keys().unwrap(), vec!["1", "2"]);
+        assert_eq!(tags.keys().unwrap(), vec!["1"]);
+        // Values of another type can't be read
+        assert!(prefixes.get::<u64>("1").is_err());
+
+        prefixes.set("2", &vec!["$"]).unwrap();
+        prefixes.remove("1").unwrap();
+        assert_eq!(prefixes.keys().unwrap(), vec!["2"]);
+        assert_eq!(
+            prefixes.get::<Vec<String>>("2").unwrap(),
+            Some(vec![String::from("$")])
+        );
+    }
+
+    #[test]
+    pub fn test_memory_storage() {
+        check_storage(Arc::new(MemoryStorage::new()));
+    }
+
+    #[test]
+    pub fn test_database_storage() {
+        let database = Arc::new(Database::in_memory().unwrap());
+        check_storage(database.clone());
+        assert!(database.flush().is_ok());
+    }
+
+    #[test]
+    pub fn test_migrations() {
+        let database = Database::in_memory().unwrap();
+        let first = Migration::new("tags", 1, "CREATE TABLE tags (name TEXT PRIMARY KEY);");
+        let second = Migration::new("tags", 2, "ALTER TABLE tags ADD COLUMN content TEXT;");
+
+        assert_eq!(database.version("tags").unwrap(), None);
+        // Applied in version order whatever the order they are given in
+        let applied = database.migrate(&[second.clone(), first.clone()]).unwrap();
+        assert_eq!(applied, vec![first.clone(), second.clone()]);
+        assert_eq!(database.version("tags").unwrap(), Some(2));
+        assert!(database.migrate(&[first, second]).unwrap().is_empty());
+
+        database
+            .call(|connection| {
+                connection.execute(
+                    "INSERT INTO tags VALUES ('hello', 'world')",
+                    rusqlite::NO_PARAMS,
+                )
+            })
+            .unwrap();
+
+        // A failing migration leaves nothing behind
+        let broken = Migration::new(
+            "tags",
+            3,
+            "ALTER TABLE tags ADD COLUMN a; SELECT * FROM nope;",
+        );
+        assert!(database.migrate(&[broken]).is_err());
+        assert_eq!(database.version("tags").unwrap(), Some(2));
+
+        // A module can use the tables of the modules before it
+        let zoo = Migration::new("zoo", 1, "CREATE TABLE animals (name TEXT);");
+        let aquarium = Migration::new("aquarium", 1, "INSERT INTO animals VALUES ('crab');");
+        let applied = database.migrate(&[zoo.clone(), aquarium.clone()]).unwrap();
+        assert_eq!(applied, vec![zoo, aquarium]);
+    }
+
+    #[test]
+    pub fn test_storage_perm_store() {
+        let database = Arc::new(Database::in_memory().unwrap());
+        let resolver =
+            PermResolver::new().with_store(Box::new(StoragePermStore::new(database.clone())));
+        let (guild, owner, member) = (GuildId(1), UserId(10), UserId(30));
+
+        resolver
+            .set_role_level(guild, RoleId(100), PermLevel::MODERATOR)
+            .unwrap();
+        resolver.set_user_level(guild, member, 60).unwrap();
+
+        // Another resolver over the same database sees them
+        let resolver =
+            PermResolver::new().with_store(Box::new(StoragePermStore::new(database.clone())));
+        let level_of = |resolver: &PermResolver, roles: &[RoleId]| {
+            resolver.level_of(member, guild, owner, roles, Permissions::empty())
+        };
+        assert_eq!(level_of(&resolver, &[]), 60);
+        resolver.clear_user_level(guild, member).unwrap();
+        assert_eq!(level_of(&resolver, &[RoleId(100)]), PermLevel::MODERATOR);
+
+        resolver.clear_role_level(guild, RoleId(100)).unwrap();
+        let resolver = PermResolver::new().with_store(Box::new(StoragePermStore::new(database)));
+        assert!(resolver.guild_overrides(guild).is_empty());
+    }
+
+    #[test]
+    pub fn test_storage_locale_store() {
+        let database = Arc::new(Database::in_memory().unwrap());
+        let locales =
+            Locales::builtin().with_store(Box::new(StorageLocaleStore::new(database.clone())));
+        let (guild, user) = (GuildId(1), UserId(2));
+
+        locales.set_guild_locale(guild, "fr").unwrap();
+        locales.set_user_locale(user, "en").unwrap();
+
+        // Still chosen after a restart
+        let locales =
+            Locales::builtin().with_store(Box::new(StorageLocaleStore::new(database.clone())));
+        assert_eq!(locales.locale_of(user, Some(guild)), "en");
+        locales.clear_user_locale(user).unwrap();
+        assert_eq!(locales.locale_of(user, Some(guild)), "fr");
+
+        let locales = Locales::builtin().with_store(Box::new(StorageLocaleStore::new(database)));
+        assert_eq!(locales.locale_of(user, Some(guild)), "fr");
+        assert_eq!(locales.locale_of(UserId(3), None), "en");
+    }
+
+    #[test]
+    pub fn test_storage_cooldown_store() {
+        let database = Arc::new(Database::in_memory().unwrap());
+        let store = StorageCooldownStore::open(database.clone()).unwrap();
+        let start = Utc.ymd(2020, 6, 1).and_hms(12, 0, 0);
+        let per = Duration::from_secs(60);
+        let key = CooldownKey {
+            bucket: "roll:many".into(),
+            scope: CooldownScope::Guild,
+            target: 1,
+        };
+
+        assert!(store.try_acquire(&key, 1, per, start).is_ok());
+
+        // Still counted after a restart
+        let store = StorageCooldownStore::open(database.clone()).unwrap();
+        let later = start + ChronoDuration::seconds(20);
+        assert_eq!(
+            store.try_acquire(&key, 1, per, later),
+            Err(Duration::from_secs(40))
+        );
+
+        store.evict(start + ChronoDuration::seconds(60));
+        assert!(Namespace::new(database, "cooldowns")
+            .keys()
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,908 @@
+use crate::error::CoreError;
+use crate::module_manager::ModuleManager;
+use crate::storage::{Namespace, Storage};
+use ffirs_commands::cmd_args::CmdArgs;
+use ffirs_commands::cmd_creator::CmdCreator;
+use ffirs_commands::cmd_handler::CmdHandler;
+use ffirs_commands::cmd_tree::{CmdNode, CmdTree};
+use ffirs_commands::context::CmdContext;
+use ffirs_commands::error::{CmdError, CmdResult};
+use ffirs_commands::locale::Locales;
+use ffirs_commands::middleware::{Invocation, Middleware};
+use ffirs_commands::perms::{PermLevel, PermRequirement};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    async_trait,
+    model::id::{ChannelId, GuildId},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+
+/// Where an override applies, inside a guild.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ToggleScope {
+    Guild,
+    Channel(ChannelId),
+}
+
+/// What an override enables or disables.
+/// A command is named after its literals, `prefix set`, and covers its subcommands.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ToggleTarget {
+    Module(String),
+    Command(String),
+}
+
+/// The modules and commands a guild enabled or disabled.
+pub type GuildToggles = BTreeMap<(ToggleScope, ToggleTarget), bool>;
+
+/// Keeps the overrides of every guild.
+pub trait ToggleStore: Send + Sync {
+    fn get(&self, guild: GuildId) -> Option<GuildToggles>;
+    fn set(&self, guild: GuildId, toggles: GuildToggles) -> io::Result<()>;
+    fn remove(&self, guild: GuildId) -> io::Result<()>;
+}
+
+/// Forgets every override on restart.
+#[derive(Debug, Default)]
+pub struct MemoryToggleStore {
+    toggles: RwLock<HashMap<GuildId, GuildToggles>>,
+}
+
+impl MemoryToggleStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ToggleStore for MemoryToggleStore {
+    fn get(&self, guild: GuildId) -> Option<GuildToggles> {
+        self.toggles.read().unwrap().get(&guild).cloned()
+    }
+
+    fn set(&self, guild: GuildId, toggles: GuildToggles) -> io::Result<()> {
+        self.toggles.write().unwrap().insert(guild, toggles);
+        Ok(())
+    }
+
+    fn remove(&self, guild: GuildId) -> io::Result<()> {
+        self.toggles.write().unwrap().remove(&guild);
+        Ok(())
+    }
+}
+
+/// Saves the overrides to a file, one per line:
+/// `<guild id> <channel id or *> <module|command> <on|off> <name>`.
+#[derive(Debug)]
+pub struct FileToggleStore {
+    path: PathBuf,
+    memory: MemoryToggleStore,
+}
+
+impl FileToggleStore {
+    /// Load the overrides saved at `path`, starting empty if the file doesn't exist yet.
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let memory = MemoryToggleStore::new();
+
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                let mut toggles = memory.toggles.write().unwrap();
+                for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                    let (guild, key, enabled) = Self::parse_line(line).ok_or_else(|| {
+                        io::Error::new(ErrorKind::InvalidData, format!("Invalid line: {}", line))
+                    })?;
+                    toggles.entry(guild).or_default().insert(key, enabled);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        Ok(Self { path, memory })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn parse_line(line: &str) -> Option<(GuildId, (ToggleScope, ToggleTarget), bool)> {
+        let mut words = line.splitn(5, ' ');
+        let guild = GuildId(words.next()?.parse().ok()?);
+        let scope = match words.next()? {
+            "*" => ToggleScope::Guild,
+            channel => ToggleScope::Channel(ChannelId(channel.parse().ok()?)),
+        };
+        let kind = words.next()?;
+        let enabled = match words.next()? {
+            "on" => true,
+            "off" => false,
+            _ => return None,
+        };
+        // Command names contain spaces, they come last
+        let name = words.next().filter(|name| !name.is_empty())?.to_owned();
+        let target = match kind {
+            "module" => ToggleTarget::Module(name),
+            "command" => ToggleTarget::Command(name),
+            _ => return None,
+        };
+
+        Some((guild, (scope, target), enabled))
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let toggles = self.memory.toggles.read().unwrap();
+        let mut guilds = toggles.iter().collect::<Vec<_>>();
+        guilds.sort_by_key(|(guild, _)| guild.0);
+
+        let mut content = String::new();
+        for (guild, overrides) in guilds {
+            for ((scope, target), enabled) in overrides {
+                let scope = match scope {
+                    ToggleScope::Guild => String::from("*"),
+                    ToggleScope::Channel(channel) => channel.0.to_string(),
+                };
+                let (kind, name) = match target {
+                    ToggleTarget::Module(name) => ("module", name),
+                    ToggleTarget::Command(name) => ("command", name),
+                };
+                let enabled = if *enabled { "on" } else { "off" };
+                content.push_str(&format!(
+                    "{} {} {} {} {}\n",
+                    guild.0, scope, kind, enabled, name
+                ));
+            }
+        }
+
+        // Write next to the file first so a crash never leaves it half written
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+impl ToggleStore for FileToggleStore {
+    fn get(&self, guild: GuildId) -> Option<GuildToggles> {
+        self.memory.get(guild)
+    }
+
+    fn set(&self, guild: GuildId, toggles: GuildToggles) -> io::Result<()> {
+        self.memory.set(guild, toggles)?;
+        self.save()
+    }
+
+    fn remove(&self, guild: GuildId) -> io::Result<()> {
+        self.memory.remove(guild)?;
+        self.save()
+    }
+}
+
+/// Keeps the overrides in the `toggles` namespace of a `Storage`, as a list per guild.
+///
+/// They are checked before every command, so they are read once and kept in memory,
+/// changes being written through.
+pub struct StorageToggleStore {
+    namespace: Namespace,
+    /// The overrides read or written so far, `None` for the guilds without any.
+    cache: RwLock<HashMap<GuildId, Option<GuildToggles>>>,
+}
+
+impl StorageToggleStore {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            namespace: Namespace::new(storage, "toggles"),
+            cache: RwLock::default(),
+        }
+    }
+}
+
+type StoredToggles = Vec<(ToggleScope, ToggleTarget, bool)>;
+
+impl ToggleStore for StorageToggleStore {
+    fn get(&self, guild: GuildId) -> Option<GuildToggles> {
+        if let Some(toggles) = self.cache.read().unwrap().get(&guild) {
+            return toggles.clone();
+        }
+
+        match self.namespace.get::<StoredToggles>(&guild.0.to_string()) {
+            Ok(stored) => {
+                let toggles = stored.map(|stored| {
+                    stored
+                        .into_iter()
+                        .map(|(scope, target, enabled)| ((scope, target), enabled))
+                        .collect()
+                });
+                // A change made since the read is newer, it stays
+                self.cache
+                    .write()
+                    .unwrap()
+                    .entry(guild)
+                    .or_insert(toggles)
+                    .clone()
+            }
+            Err(err) => {
+                warn!("Failed to read the toggles of {}: {}", guild.0, err);
+                None
+            }
+        }
+    }
+
+    fn set(&self, guild: GuildId, toggles: GuildToggles) -> io::Result<()> {
+        let stored = toggles
+            .iter()
+            .map(|((scope, target), enabled)| (*scope, target.clone(), *enabled))
+            .collect::<StoredToggles>();
+        self.namespace
+            .set(&guild.0.to_string(), &stored)
+            .map_err(|err| io::Error::new(ErrorKind::Other, err.to_string()))?;
+        self.cache.write().unwrap().insert(guild, Some(toggles));
+        Ok(())
+    }
+
+    fn remove(&self, guild: GuildId) -> io::Result<()> {
+        self.namespace
+            .remove(&guild.0.to_string())
+            .map_err(|err| io::Error::new(ErrorKind::Other, err.to_string()))?;
+        self.cache.write().unwrap().insert(guild, None);
+        Ok(())
+    }
+}
+
+/// Defaults a module declares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleDefaults {
+    pub enabled: bool,
+    pub can_be_disabled: bool,
+}
+
+/// Decides whether a command can run in a channel.
+///
+/// The most specific override wins: the channel ones before the guild ones and, in a scope,
+/// the command ones (the longest name first) before the module ones.
+/// Without any override, the default of the module applies.
+pub struct Toggles {
+    store: Box<dyn ToggleStore>,
+    modules: RwLock<BTreeMap<String, ModuleDefaults>>,
+    /// Name and module of every command, under every way to spell it with aliases.
+    commands: RwLock<HashMap<String, (String, String)>>,
+    /// Held while an override is changed, so concurrent changes don't lose each other.
+    editing: Mutex<()>,
+}
+
+impl Toggles {
+    pub fn new() -> Self {
+        Self {
+            store: Box::new(MemoryToggleStore::new()),
+            modules: RwLock::default(),
+            commands: RwLock::default(),
+            editing: Mutex::default(),
+        }
+    }
+
+    pub fn with_store(mut self, store: Box<dyn ToggleStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Learn the defaults of `modules` and the commands of `tree`, once modules started.
+    pub fn declare(&self, modules: &ModuleManager, tree: &CmdTree) {
+        let mut defaults = self.modules.write().unwrap();
+        for module in modules.modules() {
+            defaults.insert(
+                module.name().to_owned(),
+                ModuleDefaults {
+                    enabled: module.enabled_by_default(),
+                    can_be_disabled: module.can_be_disabled(),
+                },
+            );
+        }
+
+        let mut commands = self.commands.write().unwrap();
+        Self::collect_commands(tree, None, Vec::new(), vec![Vec::new()], &mut commands);
+    }
+
+    fn collect_commands(
+        node: &CmdNode,
+        module: Option<&str>,
+        mut words: Vec<String>,
+        mut spellings: Vec<Vec<String>>,
+        commands: &mut HashMap<String, (String, String)>,
+    ) {
+        let module = match ModuleManager::is_module_node(node) {
+            true => node.name.as_deref(),
+            false => module,
+        };
+        if let Some(literal) = node.matcher.literal() {
+            words.push(literal.to_owned());
+            let names = std::iter::once(literal).chain(node.aliases.iter().map(String::as_str));
+            spellings = names
+                .flat_map(|name| {
+                    spellings.iter().map(move |spelling| {
+                        let mut spelling = spelling.clone();
+                        spelling.push(name.to_owned());
+                        spelling
+                    })
+                })
+                .collect();
+
+            if let Some(module) = module {
+                for spelling in &spellings {
+                    commands.insert(spelling.join(" "), (words.join(" "), module.to_owned()));
+                }
+            }
+        }
+
+        for child in &node.children {
+            Self::collect_commands(child, module, words.clone(), spellings.clone(), commands);
+        }
+    }
+
+    /// Names of the declared modules, sorted.
+    pub fn modules(&self) -> Vec<String> {
+        self.modules.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Enable or disable `target` in `scope`, `None` going back to what applies above.
+    /// Commands can be named with aliases, the override is kept under their literals.
+    pub fn set(
+        &self,
+        guild: GuildId,
+        scope: ToggleScope,
+        target: ToggleTarget,
+        enabled: Option<bool>,
+    ) -> CmdResult<()> {
+        let (target, module) = match target {
+            ToggleTarget::Module(name) => (ToggleTarget::Module(name.clone()), name),
+            ToggleTarget::Command(name) => {
+                let (command, module) = self
+                    .commands
+                    .read()
+                    .unwrap()
+                    .get(&name)
+                    .cloned()
+                    .ok_or(CmdError::NotFound { name })?;
+                (ToggleTarget::Command(command), module)
+            }
+        };
+        let defaults = self
+            .modules
+            .read()
+            .unwrap()
+            .get(&module)
+            .copied()
+            .ok_or_else(|| CoreError::UnknownModule {
+                name: module.clone(),
+            })?;
+        if !defaults.can_be_disabled {
+            return Err(CoreError::ModuleLocked { name: module }.into());
+        }
+
+        let _editing = self.editing.lock().unwrap();
+        let mut toggles = self.store.get(guild).unwrap_or_default();
+        match enabled {
+            Some(enabled) => toggles.insert((scope, target), enabled),
+            None => toggles.remove(&(scope, target)),
+        };
+
+        if toggles.is_empty() {
+            self.store.remove(guild)?;
+        } else {
+            self.store.set(guild, toggles)?;
+        }
+        Ok(())
+    }
+
+    /// Every override of a guild.
+    pub fn overrides(&self, guild: GuildId) -> GuildToggles {
+        self.store.get(guild).unwrap_or_default()
+    }
+
+    /// Whether `command`, the literals of a path from the root down, can run in `channel`.
+    pub fn is_enabled(
+        &self,
+        guild: Option<GuildId>,
+        channel: ChannelId,
+        module: Option<&str>,
+        command: &[&str],
+    ) -> bool {
+        let default = module
+            .and_then(|module| self.modules.read().unwrap().get(module).copied())
+            .map_or(true, |defaults| defaults.enabled);
+        let toggles = match guild.and_then(|guild| self.store.get(guild)) {
+            Some(toggles) => toggles,
+            None => return default,
+        };
+
+        for scope in [ToggleScope::Channel(channel), ToggleScope::Guild].iter() {
+            let commands = (1..=command.len())
+                .rev()
+                .map(|len| ToggleTarget::Command(command[..len].join(" ")));
+            let module = module.map(|module| ToggleTarget::Module(module.to_owned()));
+
+            for target in commands.chain(module) {
+                if let Some(&enabled) = toggles.get(&(*scope, target)) {
+                    return enabled;
+                }
+            }
+        }
+
+        default
+    }
+
+    /// Modules enabled in `channel`.
+    pub fn module_states(&self, guild: GuildId, channel: ChannelId) -> Vec<(String, bool)> {
+        self.modules()
+            .into_iter()
+            .map(|module| {
+                let enabled = self.is_enabled(Some(guild), channel, Some(&module), &[]);
+                (module, enabled)
+            })
+            .collect()
+    }
+}
+
+impl Default for Toggles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Refuses the commands disabled where they are invoked.
+#[async_trait]
+impl Middleware for Toggles {
+    async fn before(&self, invocation: &Invocation<'_>) -> CmdResult<()> {
+        let ctx = invocation.ctx;
+        let module = ModuleManager::module_of(&invocation.path);
+        // Paths go from the leaf up
+        let command = invocation
+            .path
+            .iter()
+            .rev()
+            .filter_map(|node| node.matcher.literal())
+            .collect::<Vec<_>>();
+
+        if self.is_enabled(ctx.guild(), ctx.channel(), module, &command) {
+            Ok(())
+        } else {
+            Err(CoreError::Disabled {
+                name: command.join(" "),
+            }
+            .into())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TargetKind {
+    Module,
+    Command,
+}
+
+/// `module|command enable|disable|reset <name> [<channel>]`.
+#[derive(Clone)]
+pub struct ToggleCmd {
+    toggles: Arc<Toggles>,
+    locales: Arc<Locales>,
+    kind: TargetKind,
+    enabled: Option<bool>,
+}
+
+#[async_trait]
+impl CmdHandler for ToggleCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()> {
+        let guild = ctx.guild().ok_or(CmdError::GuildOnly)?;
+        let name = args.take::<String>("name").unwrap_or_default();
+        let channel = args.take::<ChannelId>("channel");
+
+        let scope = channel.map_or(ToggleScope::Guild, ToggleScope::Channel);
+        let target = match self.kind {
+            TargetKind::Module => ToggleTarget::Module(name.clone()),
+            TargetKind::Command => ToggleTarget::Command(name.clone()),
+        };
+        self.toggles.set(guild, scope, target, self.enabled)?;
+
+        let locale = self.locales.locale_of(ctx.author().id, ctx.guild());
+        let place = match channel {
+            Some(channel) => self.locales.tr(
+                &locale,
+                "toggle-place-channel",
+                &[("channel", format!("<#{}>", channel.0))],
+            ),
+            None => self.locales.tr(&locale, "toggle-place-guild", &[]),
+        };
+        let key = match self.enabled {
+            Some(true) => "toggle-enabled",
+            Some(false) => "toggle-disabled",
+            None => "toggle-reset",
+        };
+        let reply = self
+            .locales
+            .tr(&locale, key, &[("name", name), ("place", place)]);
+        ctx.reply(&reply).await?;
+
+        Ok(())
+    }
+}
+
+/// `module list`: the modules enabled in the channel and the overrides of the guild.
+pub struct ListTogglesCmd {
+    toggles: Arc<Toggles>,
+    locales: Arc<Locales>,
+}
+
+impl ListTogglesCmd {
+    pub fn lines(&self, locale: &str, guild: GuildId, channel: ChannelId) -> Vec<String> {
+        let state = |enabled: bool| match enabled {
+            true => self.locales.tr(locale, "toggle-state-enabled", &[]),
+            false => self.locales.tr(locale, "toggle-state-disabled", &[]),
+        };
+
+        let mut lines = self
+            .toggles
+            .module_states(guild, channel)
+            .into_iter()
+            .map(|(module, enabled)| format!("`{}`: {}", module, state(enabled)))
+            .collect::<Vec<_>>();
+
+        for ((scope, target), enabled) in self.toggles.overrides(guild) {
+            let name = match target {
+                ToggleTarget::Module(name) | ToggleTarget::Command(name) => name,
+            };
+            let place = match scope {
+                ToggleScope::Guild => self.locales.tr(locale, "toggle-place-guild", &[]),
+                ToggleScope::Channel(channel) => self.locales.tr(
+                    locale,
+                    "toggle-place-channel",
+                    &[("channel", format!("<#{}>", channel.0))],
+                ),
+            };
+            lines.push(format!("`{}`: {} {}", name, state(enabled), place));
+        }
+
+        lines
+    }
+}
+
+#[async_trait]
+impl CmdHandler for ListTogglesCmd {
+    async fn execute(&self, ctx: &dyn CmdContext, _args: &mut CmdArgs) -> CmdResult<()> {
+        let guild = ctx.guild().ok_or(CmdError::GuildOnly)?;
+        let locale = self.locales.locale_of(ctx.author().id, ctx.guild());
+        let lines = self.lines(&locale, guild, ctx.channel());
+
+        ctx.reply(&lines.join("\n")).await?;
+
+        Ok(())
+    }
+}
+
+/// `module ...` and `command ...`, for admins.
+pub fn toggle_commands(toggles: Arc<Toggles>, locales: Arc<Locales>) -> CmdResult<Vec<CmdNode>> {
+    let mut module = CmdCreator::create_cmd(
+        "module list",
+        ListTogglesCmd {
+            toggles: toggles.clone(),
+            locales: locales.clone(),
+        },
+    )?;
+    module.description = Some("module-description".into());
+    add_actions(&mut module, TargetKind::Module, &toggles, &locales)?;
+
+    let mut command = CmdCreator::create_cmd_branch("command")?;
+    command.description = Some("command-description".into());
+    add_actions(&mut command, TargetKind::Command, &toggles, &locales)?;
+
+    Ok(vec![module, command])
+}
+
+fn add_actions(
+    root: &mut CmdNode,
+    kind: TargetKind,
+    toggles: &Arc<Toggles>,
+    locales: &Arc<Locales>,
+) -> CmdResult<()> {
+    let actions = [
+        ("enable", Some(true)),
+        ("disable", Some(false)),
+        ("reset", None),
+    ];
+
+    for &(action, enabled) in actions.iter() {
+        let handler = ToggleCmd {
+            toggles: toggles.clone(),
+            locales: locales.clone(),
+            kind,
+            enabled,
+        };
+        let mut node =
+            CmdCreator::create_cmd(&format!("{} <name: Word>", action), handler.clone())?;
+        let channel = CmdCreator::create_cmd("<channel: ChannelMention>", handler)?;
+        node.children[0].children.push(channel);
+        root.children.push(node);
+    }
+
+    root.requirement = Some(PermRequirement::level(PermLevel::ADMIN));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::CoreError;
+    use crate::storage::Database;
+    use crate::toggles::{
+        FileToggleStore, ModuleDefaults, StorageToggleStore, ToggleScope, ToggleStore,
+        ToggleTarget, Toggles,
+    };
+    use ffirs_commands::cmd_creator::CmdCreator;
+    use ffirs_commands::cmd_tree::CmdNode;
+    use ffirs_commands::error::CmdError;
+    use ffirs_commands::matchers::RootMatcher;
+    use serenity::model::id::{ChannelId, GuildId};
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs;
+    use std::sync::Arc;
+    use std::thread;
+
+    const GUILD: GuildId = GuildId(1);
+    const GENERAL: ChannelId = ChannelId(10);
+    const MEMES: ChannelId = ChannelId(11);
+
+    fn make_toggles() -> Toggles {
+        let toggles = Toggles::new();
+        {
+            let mut modules = toggles.modules.write().unwrap();
+            for (name, enabled, can_be_disabled) in [
+                ("core", true, false),
+                ("fun", true, true),
+                ("nsfw", false, true),
+            ]
+            .iter()
+            {
+                modules.insert(
+                    name.to_string(),
+                    ModuleDefaults {
+                        enabled: *enabled,
+                        can_be_disabled: *can_be_disabled,
+                    },
+                );
+            }
+            let mut commands = toggles.commands.write().unwrap();
+            *commands = vec![
+                ("roll", "fun"),
+                ("roll dice", "fun"),
+                ("flip", "fun"),
+                ("module", "core"),
+            ]
+            .into_iter()
+            .map(|(command, module)| (command.to_owned(), (command.to_owned(), module.to_owned())))
+            .collect::<HashMap<_, _>>();
+        }
+        toggles
+    }
+
+    fn enabled(toggles: &Toggles, channel: ChannelId, module: &str, command: &[&str]) -> bool {
+        toggles.is_enabled(Some(GUILD), channel, Some(module), command)
+    }
+
+    #[test]
+    pub fn test_module_defaults() {
+        let toggles = make_toggles();
+
+        assert!(enabled(&toggles, GENERAL, "fun", &["roll"]));
+        assert!(!enabled(&toggles, GENERAL, "nsfw", &["anything"]));
+        // Direct messages only get the defaults
+        assert!(!toggles.is_enabled(None, GENERAL, Some("nsfw"), &["anything"]));
+    }
+
+    #[test]
+    pub fn test_overrides_precedence() {
+        let toggles = make_toggles();
+        let module = || ToggleTarget::Module("fun".into());
+        let command = |name: &str| ToggleTarget::Command(name.into());
+
+        toggles
+            .set(GUILD, ToggleScope::Guild, module(), Some(false))
+            .unwrap();
+        assert!(!enabled(&toggles, GENERAL, "fun", &["flip"]));
+
+        // Channel overrides come first
+        let memes = ToggleScope::Channel(MEMES);
+        toggles.set(GUILD, memes, module(), Some(true)).unwrap();
+        assert!(enabled(&toggles, MEMES, "fun", &["flip"]));
+        assert!(!enabled(&toggles, GENERAL, "fun", &["flip"]));
+
+        // Then commands, the longest name first
+        toggles
+            .set(GUILD, memes, command("roll"), Some(false))
+            .unwrap();
+        toggles
+            .set(GUILD, memes, command("roll dice"), Some(true))
+            .unwrap();
+        assert!(!enabled(&toggles, MEMES, "fun", &["roll"]));
+        assert!(enabled(&toggles, MEMES, "fun", &["roll", "dice"]));
+        assert!(enabled(&toggles, MEMES, "fun", &["flip"]));
+
+        // Resetting goes back to what applies above
+        toggles.set(GUILD, memes, module(), None).unwrap();
+        assert!(!enabled(&toggles, MEMES, "fun", &["flip"]));
+        toggles
+            .set(GUILD, ToggleScope::Guild, module(), None)
+            .unwrap();
+        assert!(enabled(&toggles, GENERAL, "fun", &["flip"]));
+    }
+
+    #[test]
+    pub fn test_set_errors() {
+        let toggles = make_toggles();
+
+        match toggles
+            .set(
+                GUILD,
+                ToggleScope::Guild,
+                ToggleTarget::Module("core".into()),
+                Some(false),
+            )
+            .unwrap_err()
+            .downcast_ref::<CoreError>()
+        {
+            Some(CoreError::ModuleLocked { name }) => assert_eq!(name, "core"),
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            toggles
+                .set(
+                    GUILD,
+                    ToggleScope::Guild,
+                    ToggleTarget::Command("module".into()),
+                    Some(false)
+                )
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::ModuleLocked { .. })
+        ));
+        assert!(matches!(
+            toggles
+                .set(
+                    GUILD,
+                    ToggleScope::Guild,
+                    ToggleTarget::Module("music".into()),
+                    Some(false)
+                )
+                .unwrap_err()
+                .downcast_ref::<CoreError>(),
+            Some(CoreError::UnknownModule { .. })
+        ));
+        assert!(matches!(
+            toggles.set(
+                GUILD,
+                ToggleScope::Guild,
+                ToggleTarget::Command("dance".into()),
+                Some(false)
+            ),
+            Err(CmdError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    pub fn test_set_with_aliases() {
+        let toggles = make_toggles();
+        let mut roll = CmdCreator::create_cmd_branch("roll dice").unwrap();
+        roll.aliases = vec![String::from("lancer")];
+        roll.children[0].aliases = vec![String::from("dé")];
+        let mut module = CmdNode::new_named(Box::new(RootMatcher), "fun");
+        module.children.push(roll);
+        let tree = CmdNode::root(vec![module]);
+
+        let mut commands = toggles.commands.write().unwrap();
+        Toggles::collect_commands(&tree, None, Vec::new(), vec![Vec::new()], &mut commands);
+        drop(commands);
+
+        toggles
+            .set(
+                GUILD,
+                ToggleScope::Guild,
+                ToggleTarget::Command("lancer dé".into()),
+                Some(false),
+            )
+            .unwrap();
+        assert!(!enabled(&toggles, GENERAL, "fun", &["roll", "dice"]));
+        assert!(enabled(&toggles, GENERAL, "fun", &["roll"]));
+
+        toggles
+            .set(
+                GUILD,
+                ToggleScope::Guild,
+                ToggleTarget::Command("roll dé".into()),
+                None,
+            )
+            .unwrap();
+        assert!(enabled(&toggles, GENERAL, "fun", &["roll", "dice"]));
+        assert!(toggles.overrides(GUILD).is_empty());
+    }
+
+    #[test]
+    pub fn test_concurrent_set() {
+        let toggles = Arc::new(make_toggles());
+
+        let threads = (0..8)
+            .map(|channel| {
+                let toggles = toggles.clone();
+                thread::spawn(move || {
+                    let scope = ToggleScope::Channel(ChannelId(channel));
+                    let target = ToggleTarget::Module("fun".into());
+                    toggles.set(GUILD, scope, target, Some(false)).unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(toggles.overrides(GUILD).len(), 8);
+    }
+
+    #[test]
+    pub fn test_file_store() {
+        let path = std::env::temp_dir().join(format!("ffirs-toggles-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut toggles = BTreeMap::new();
+        toggles.insert(
+            (ToggleScope::Guild, ToggleTarget::Module("fun".into())),
+            false,
+        );
+        toggles.insert(
+            (
+                ToggleScope::Channel(MEMES),
+                ToggleTarget::Command("prefix set".into()),
+            ),
+            true,
+        );
+
+        let store = FileToggleStore::open(&path).unwrap();
+        assert_eq!(store.get(GUILD), None);
+        store.set(GUILD, toggles.clone()).unwrap();
+        store.set(GuildId(2), toggles.clone()).unwrap();
+        store.remove(GuildId(2)).unwrap();
+
+        let reopened = FileToggleStore::open(&path).unwrap();
+        assert_eq!(reopened.get(GUILD), Some(toggles));
+        assert_eq!(reopened.get(GuildId(2)), None);
+
+        fs::write(&path, "1 * module maybe fun\n").unwrap();
+        assert!(FileToggleStore::open(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    pub fn test_storage_store() {
+        let database = Arc::new(Database::in_memory().unwrap());
+        let toggles =
+            make_toggles().with_store(Box::new(StorageToggleStore::new(database.clone())));
+        let memes = ToggleScope::Channel(MEMES);
+        let roll = ToggleTarget::Command("roll dice".into());
+
+        toggles
+            .set(GUILD, memes, roll.clone(), Some(false))
+            .unwrap();
+        assert!(!enabled(&toggles, MEMES, "fun", &["roll", "dice"]));
+
+        // Another store over the same database sees them
+        let store = StorageToggleStore::new(database.clone());
+        assert_eq!(
+            store.get(GUILD).unwrap().get(&(memes, roll.clone())),
+            Some(&false)
+        );
+        assert_eq!(store.get(GuildId(2)), None);
+
+        toggles.set(GUILD, memes, roll, None).unwrap();
+        assert_eq!(StorageToggleStore::new(database).get(GUILD), None);
+    }
+}
@@ -0,0 +1,409 @@
+use ffirs_commands::context::{CmdContext, CmdUser, Embed};
+use ffirs_commands::error::CmdResult;
+use ffirs_commands::type_map::AnyTypeMap;
+use serenity::{
+    async_trait,
+    client::Context,
+    http::Http,
+    model::{
+        channel::{Message, ReactionType},
+        id::{ChannelId, EmojiId, GuildId, MessageId, UserId},
+        user::User,
+    },
+    prelude::RwLock,
+};
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+fn cmd_user(user: &User) -> CmdUser {
+    CmdUser {
+        id: user.id,
+        name: user.name.clone(),
+        bot: user.bot,
+    }
+}
+
+/// `name:id` for a custom emoji, anything else being unicode.
+fn reaction_type(emoji: &str) -> ReactionType {
+    let mut parts = emoji.rsplitn(2, ':');
+    match (parts.next().map(str::parse::<u64>), parts.next()) {
+        (Some(Ok(id)), Some(name)) => ReactionType::Custom {
+            animated: false,
+            id: EmojiId(id),
+            name: Some(name.to_owned()),
+        },
+        _ => ReactionType::Unicode(emoji.to_owned()),
+    }
+}
+
+/// The reverse of `reaction_type`.
+pub(crate) fn emoji_text(reaction: &ReactionType) -> String {
+    match reaction {
+        ReactionType::Custom { id, name, .. } => {
+            format!("{}:{}", name.as_deref().unwrap_or_default(), id.0)
+        }
+        ReactionType::Unicode(emoji) => emoji.clone(),
+        _ => String::new(),
+    }
+}
+
+/// A command sent in a Discord message.
+pub struct DiscordContext<'a> {
+    ctx: &'a Context,
+    message: &'a Message,
+    author: CmdUser,
+    data: Arc<RwLock<AnyTypeMap>>,
+}
+
+impl<'a> DiscordContext<'a> {
+    /// `data` is shared with the command handlers, like the values of a `SharedState`.
+    pub fn new(ctx: &'a Context, message: &'a Message, data: Arc<RwLock<AnyTypeMap>>) -> Self {
+        Self {
+            ctx,
+            message,
+            author: cmd_user(&message.author),
+            data,
+        }
+    }
+}
+
+#[async_trait]
+impl CmdContext for DiscordContext<'_> {
+    fn author(&self) -> &CmdUser {
+        &self.author
+    }
+
+    fn guild(&self) -> Option<GuildId> {
+        self.message.guild_id
+    }
+
+    fn channel(&self) -> ChannelId {
+        self.message.channel_id
+    }
+
+    fn message_id(&self) -> MessageId {
+        self.message.id
+    }
+
+    fn content(&self) -> &str {
+        &self.message.content
+    }
+
+    fn data(&self) -> &Arc<RwLock<AnyTypeMap>> {
+        &self.data
+    }
+
+    async fn reply(&self, content: &str) -> CmdResult<()> {
+        self.message.channel_id.say(&self.ctx.http, content).await?;
+        Ok(())
+    }
+
+    async fn send_embed(&self, embed: &Embed) -> CmdResult<()> {
+        self.message
+            .channel_id
+            .send_message(&self.ctx.http, |m| m.embed(|e| embed.fill(e)))
+            .await?;
+        Ok(())
+    }
+
+    async fn react(&self, emoji: &str) -> CmdResult<()> {
+        self.message
+            .channel_id
+            .create_reaction(&self.ctx.http, self.message.id, reaction_type(emoji))
+            .await?;
+        Ok(())
+    }
+
+    async fn fetch_user(&self, user: UserId) -> CmdResult<CmdUser> {
+        let user = self.ctx.http.get_user(user.0).await?;
+        Ok(cmd_user(&user))
+    }
+
+    fn discord(&self) -> Option<(&Context, &Message)> {
+        Some((self.ctx, self.message))
+    }
+}
+
+/// A command typed in a terminal, answers being written as text to an output.
+pub struct TerminalContext {
+    author: CmdUser,
+    guild: Option<GuildId>,
+    channel: ChannelId,
+    message_id: MessageId,
+    content: String,
+    data: Arc<RwLock<AnyTypeMap>>,
+    output: Arc<Mutex<dyn Write + Send>>,
+}
+
+impl TerminalContext {
+    /// `content` sent by `author` in direct messages, answered on the standard output.
+    pub fn new<S: Into<String>>(author: CmdUser, content: S) -> Self {
+        Self {
+            author,
+            guild: None,
+            channel: ChannelId(1),
+            message_id: MessageId(1),
+            content: content.into(),
+            data: Arc::default(),
+            output: Arc::new(Mutex::new(io::stdout())),
+        }
+    }
+
+    pub fn with_guild(mut self, guild: GuildId) -> Self {
+        self.guild = Some(guild);
+        self
+    }
+
+    pub fn with_channel(mut self, channel: ChannelId) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    pub fn with_message_id(mut self, message_id: MessageId) -> Self {
+        self.message_id = message_id;
+        self
+    }
+
+    /// Data shared between the commands typed in the same session.
+    pub fn with_data(mut self, data: Arc<RwLock<AnyTypeMap>>) -> Self {
+        self.data = data;
+        self
+    }
+
+    pub fn with_output(mut self, output: Arc<Mutex<dyn Write + Send>>) -> Self {
+        self.output = output;
+        self
+    }
+
+    fn write(&self, text: &str) -> CmdResult<()> {
+        let mut output = self.output.lock().unwrap();
+        writeln!(output, "{}", text)?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CmdContext for TerminalContext {
+    fn author(&self) -> &CmdUser {
+        &self.author
+    }
+
+    fn guild(&self) -> Option<GuildId> {
+        self.guild
+    }
+
+    fn channel(&self) -> ChannelId {
+        self.channel
+    }
+
+    fn message_id(&self) -> MessageId {
+        self.message_id
+    }
+
+    fn content(&self) -> &str {
+        &self.content
+    }
+
+    fn data(&self) -> &Arc<RwLock<AnyTypeMap>> {
+        &self.data
+    }
+
+    async fn reply(&self, content: &str) -> CmdResult<()> {
+        self.write(content)
+    }
+
+    async fn send_embed(&self, embed: &Embed) -> CmdResult<()> {
+        self.write(&embed.to_text())
+    }
+
+    async fn react(&self, emoji: &str) -> CmdResult<()> {
+        self.write(&format!("[{}]", emoji))
+    }
+
+    /// Only the author is known, anyone else gets a made up name.
+    async fn fetch_user(&self, user: UserId) -> CmdResult<CmdUser> {
+        if user == self.author.id {
+            return Ok(self.author.clone());
+        }
+
+        Ok(CmdUser {
+            id: user,
+            name: format!("user{}", user.0),
+            bot: false,
+        })
+    }
+}
+
+/// Sends messages outside of any command, like scheduled jobs do.
+#[async_trait]
+pub trait Messenger: Send + Sync {
+    async fn send(&self, channel: ChannelId, content: &str) -> CmdResult<()>;
+
+    /// Send in the direct messages of `user`.
+    async fn send_dm(&self, user: UserId, content: &str) -> CmdResult<()>;
+}
+
+/// Sends messages to Discord.
+pub struct DiscordMessenger {
+    http: Arc<Http>,
+}
+
+impl DiscordMessenger {
+    pub fn new(http: Arc<Http>) -> Self {
+        Self { http }
+    }
+}
+
+#[async_trait]
+impl Messenger for DiscordMessenger {
+    async fn send(&self, channel: ChannelId, content: &str) -> CmdResult<()> {
+        channel.say(&self.http, content).await?;
+        Ok(())
+    }
+
+    async fn send_dm(&self, user: UserId, content: &str) -> CmdResult<()> {
+        let channel = user.create_dm_channel(&*self.http).await?;
+        channel.say(&self.http, content).await?;
+        Ok(())
+    }
+}
+
+/// Writes messages as text to an output, prefixed with where they would go.
+pub struct TerminalMessenger {
+    output: Arc<Mutex<dyn Write + Send>>,
+}
+
+impl TerminalMessenger {
+    pub fn new(output: Arc<Mutex<dyn Write + Send>>) -> Self {
+        Self { output }
+    }
+
+    fn write(&self, to: &str, content: &str) -> CmdResult<()> {
+        let mut output = self.output.lock().unwrap();
+        writeln!(output, "[{}] {}", to, content)?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+impl Default for TerminalMessenger {
+    fn default() -> Self {
+        Self::new(Arc::new(Mutex::new(io::stdout())))
+    }
+}
+
+#[async_trait]
+impl Messenger for TerminalMessenger {
+    async fn send(&self, channel: ChannelId, content: &str) -> CmdResult<()> {
+        self.write(&format!("#{}", channel.0), content)
+    }
+
+    async fn send_dm(&self, user: UserId, content: &str) -> CmdResult<()> {
+        self.write(&format!("@{}", user.0), content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::locale::core_locales;
+    use crate::shards::shards_command;
+    use crate::transport::{
+        emoji_text, reaction_type, Messenger, TerminalContext, TerminalMessenger,
+    };
+    use ffirs_commands::cmd_args::CmdArgs;
+    use ffirs_commands::cmd_creator::CmdCreator;
+    use ffirs_commands::cmd_handler::CmdHandler;
+    use ffirs_commands::cmd_manager::CmdManager;
+    use ffirs_commands::cmd_tree::CmdNode;
+    use ffirs_commands::context::{CmdContext, CmdUser, Embed};
+    use ffirs_commands::error::{CmdError, CmdResult};
+    use ffirs_commands::perms::PermResolver;
+    use serenity::async_trait;
+    use serenity::model::channel::ReactionType;
+    use serenity::model::id::{ChannelId, EmojiId, GuildId, UserId};
+    use std::sync::{Arc, Mutex};
+
+    struct WhoCmd;
+
+    #[async_trait]
+    impl CmdHandler for WhoCmd {
+        async fn execute(&self, ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()> {
+            let user = args.take::<UserId>("user").unwrap_or(ctx.author().id);
+            let user = ctx.fetch_user(user).await?;
+            let guild = ctx.guild().map_or(0, |guild| guild.0);
+
+            ctx.reply(&format!("{} in {}", user.name, guild)).await?;
+            ctx.send_embed(&Embed::new().title("Who").description(user.id.0.to_string()))
+                .await?;
+            ctx.react("✅").await
+        }
+    }
+
+    #[test]
+    pub fn test_reaction_type() {
+        assert_eq!(
+            reaction_type("✅"),
+            ReactionType::Unicode(String::from("✅"))
+        );
+        assert_eq!(
+            reaction_type("ferris:42"),
+            ReactionType::Custom {
+                animated: false,
+                id: EmojiId(42),
+                name: Some(String::from("ferris")),
+            }
+        );
+        for emoji in &["✅", "ferris:42"] {
+            assert_eq!(emoji_text(&reaction_type(emoji)), *emoji);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_terminal_dispatch() {
+        let manager = CmdManager::with_default_mappers()
+            .with_middleware(PermResolver::with_owners(vec![UserId(7)]));
+        let locales = Arc::new(core_locales().unwrap());
+        let tree = CmdNode::root(vec![
+            CmdCreator::create_cmd("who <user: UserMention>", WhoCmd).unwrap(),
+            shards_command(locales).unwrap(),
+        ]);
+        let author = CmdUser {
+            id: UserId(7),
+            name: String::from("ferris"),
+            bot: false,
+        };
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let (manager, tree, out) = (&manager, &tree, output.clone());
+        let run = move |raw: &'static str| {
+            let ctx = TerminalContext::new(author.clone(), raw)
+                .with_guild(GuildId(10))
+                .with_output(out.clone());
+            async move { manager.dispatch(tree, &ctx, raw).await }
+        };
+
+        run("who <@7>").await.unwrap();
+        run("who <@12>").await.unwrap();
+        assert_eq!(
+            String::from_utf8(output.lock().unwrap().clone()).unwrap(),
+            "ferris in 10\n== Who ==\n7\n[✅]\nuser12 in 10\n== Who ==\n12\n[✅]\n"
+        );
+
+        assert!(matches!(run("shards").await, Err(CmdError::DiscordOnly)));
+    }
+
+    #[tokio::test]
+    pub async fn test_terminal_messenger() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let messenger = TerminalMessenger::new(output.clone());
+
+        messenger.send(ChannelId(20), "hello").await.unwrap();
+        messenger.send_dm(UserId(7), "psst").await.unwrap();
+        assert_eq!(
+            String::from_utf8(output.lock().unwrap().clone()).unwrap(),
+            "[#20] hello\n[@7] psst\n"
+        );
+    }
+}
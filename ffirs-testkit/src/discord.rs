@@ -0,0 +1,314 @@
+use crate::message::{user_json, MessageBuilder};
+use hyper::{service::service_fn, Body, Method, Request, Response, StatusCode};
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use serenity::{
+    http::Http,
+    model::id::{ChannelId, MessageId, UserId},
+};
+use std::{
+    convert::Infallible,
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::{Certificate, NoClientAuth, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+/// The hosts the certificate of the mock is made for.
+const HOSTS: [&str; 3] = ["discord.com", "discordapp.com", "cdn.discordapp.com"];
+
+/// The author of the messages the bot sends.
+pub const BOT: UserId = UserId(1);
+
+/// A request the bot sent to Discord.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    /// A message, with its text and the fields of its embed.
+    Send {
+        channel: ChannelId,
+        content: Option<String>,
+        embed: Option<Value>,
+    },
+    React {
+        channel: ChannelId,
+        message: MessageId,
+        emoji: String,
+    },
+    /// Any other route, answered with a 404.
+    Other { method: String, path: String },
+}
+
+impl Call {
+    pub fn content(&self) -> Option<&str> {
+        match self {
+            Call::Send { content, .. } => content.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// A text field of the embed, like `title` or `description`.
+    pub fn embed_field(&self, field: &str) -> Option<&str> {
+        match self {
+            Call::Send {
+                embed: Some(embed), ..
+            } => embed.get(field).and_then(Value::as_str),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps every call made to the mock, in order.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    calls: Mutex<Vec<Call>>,
+}
+
+impl Recorder {
+    pub fn record(&self, call: Call) {
+        self.calls.lock().unwrap().push(call);
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// The calls made since there were `len` of them.
+    pub fn since(&self, len: usize) -> Vec<Call> {
+        self.calls.lock().unwrap()[len..].to_vec()
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.calls.lock().unwrap().clear();
+    }
+}
+
+/// The Discord REST API, served locally.
+///
+/// It listens as an HTTP proxy: the `Http` clients it gives tunnel their requests to it,
+/// which it decrypts with its own certificate, records and answers.
+/// The certificate is made when the mock starts and only its clients trust it.
+pub struct MockDiscord {
+    addr: SocketAddr,
+    certificate: Vec<u8>,
+    recorder: Arc<Recorder>,
+}
+
+struct Routes {
+    recorder: Arc<Recorder>,
+    next_id: AtomicU64,
+}
+
+impl MockDiscord {
+    pub async fn start() -> io::Result<Self> {
+        let mut listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+        let (certificate, key) = Self::self_signed()?;
+        let config = Self::tls_config(certificate.clone(), key)?;
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let recorder = Arc::new(Recorder::default());
+        let routes = Arc::new(Routes {
+            recorder: recorder.clone(),
+            next_id: AtomicU64::new(1_000),
+        });
+
+        tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(_) => return,
+                };
+                let acceptor = acceptor.clone();
+                let routes = routes.clone();
+
+                tokio::spawn(async move {
+                    // Failed connections only make the request of the client fail
+                    let _ = Self::serve(stream, acceptor, routes).await;
+                });
+            }
+        });
+
+        Ok(Self {
+            addr,
+            certificate,
+            recorder,
+        })
+    }
+
+    /// A new certificate for the hosts of Discord and its key, both DER encoded.
+    fn self_signed() -> io::Result<(Vec<u8>, Vec<u8>)> {
+        let hosts = HOSTS
+            .iter()
+            .map(|host| host.to_string())
+            .collect::<Vec<_>>();
+        let invalid = |err: rcgen::RcgenError| io::Error::new(ErrorKind::InvalidData, err);
+        let certificate = rcgen::generate_simple_self_signed(hosts).map_err(invalid)?;
+
+        Ok((
+            certificate.serialize_der().map_err(invalid)?,
+            certificate.serialize_private_key_der(),
+        ))
+    }
+
+    fn tls_config(certificate: Vec<u8>, key: Vec<u8>) -> io::Result<ServerConfig> {
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config
+            .set_single_cert(vec![Certificate(certificate)], PrivateKey(key))
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err.to_string()))?;
+        Ok(config)
+    }
+
+    async fn serve(
+        mut stream: TcpStream,
+        acceptor: TlsAcceptor,
+        routes: Arc<Routes>,
+    ) -> io::Result<()> {
+        // `CONNECT discord.com:443 HTTP/1.1`, the client waits for the answer to go on
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0; 1];
+            if stream.read(&mut byte).await? == 0 {
+                return Ok(());
+            }
+            head.push(byte[0]);
+        }
+        if !head.starts_with(b"CONNECT ") {
+            stream
+                .write_all(b"HTTP/1.1 405 Method Not Allowed\r\n\r\n")
+                .await?;
+            return Ok(());
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .await?;
+
+        let stream = acceptor.accept(stream).await?;
+        let service = service_fn(move |request| {
+            let routes = routes.clone();
+            async move { Ok::<_, Infallible>(routes.answer(request).await) }
+        });
+
+        hyper::server::conn::Http::new()
+            .http1_only(true)
+            .serve_connection(stream, service)
+            .await
+            .map_err(|err| io::Error::new(ErrorKind::Other, err))
+    }
+
+    pub fn recorder(&self) -> &Arc<Recorder> {
+        &self.recorder
+    }
+
+    /// A client sending its requests to the mock, trusting its certificate and no other.
+    pub fn http(&self) -> Arc<Http> {
+        let certificate = reqwest::Certificate::from_der(&self.certificate)
+            .expect("Invalid certificate of the mock");
+        let client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all(&format!("http://{}", self.addr)).unwrap())
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(certificate)
+            .build()
+            .expect("Failed to build the client of the mock");
+
+        Arc::new(Http::new(Arc::new(client), "Bot mock"))
+    }
+}
+
+impl Routes {
+    async fn answer(&self, request: Request<Body>) -> Response<Body> {
+        let method = request.method().clone();
+        let path = request.uri().path().to_owned();
+        let body = hyper::body::to_bytes(request.into_body())
+            .await
+            .ok()
+            .and_then(|body| serde_json::from_slice::<Value>(&body).ok())
+            .unwrap_or(Value::Null);
+
+        // `/api/v6/channels/1/messages` gives `["channels", "1", "messages"]`
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .skip_while(|segment| *segment == "api" || Self::is_version(segment))
+            .collect::<Vec<_>>();
+        let id = |segment: &str| segment.parse::<u64>().unwrap_or_default();
+
+        match (&method, segments.as_slice()) {
+            (&Method::POST, ["channels", channel, "messages"]) => {
+                let channel = ChannelId(id(channel));
+                let content = body.get("content").and_then(Value::as_str);
+                self.recorder.record(Call::Send {
+                    channel,
+                    content: content.map(str::to_owned),
+                    embed: body.get("embed").cloned(),
+                });
+
+                let message_id = MessageId(self.next_id.fetch_add(1, Ordering::SeqCst));
+                let mut message = MessageBuilder::new(message_id, channel, BOT)
+                    .bot(true)
+                    .content(content.unwrap_or_default())
+                    .to_json();
+                message["author"] = user_json(BOT, "ffirs", true);
+                Self::json(StatusCode::OK, message)
+            }
+            (
+                &Method::PUT,
+                ["channels", channel, "messages", message, "reactions", emoji, "@me"],
+            ) => {
+                self.recorder.record(Call::React {
+                    channel: ChannelId(id(channel)),
+                    message: MessageId(id(message)),
+                    emoji: percent_decode_str(emoji).decode_utf8_lossy().into_owned(),
+                });
+                Self::empty(StatusCode::NO_CONTENT)
+            }
+            (&Method::POST, ["channels", _, "typing"]) => Self::empty(StatusCode::NO_CONTENT),
+            (&Method::GET, ["users", user]) => {
+                let user = UserId(id(user));
+                let name = format!("user{}", user.0);
+                Self::json(StatusCode::OK, user_json(user, &name, false))
+            }
+            _ => {
+                self.recorder.record(Call::Other {
+                    method: method.to_string(),
+                    path,
+                });
+                let error = json!({ "code": 0, "message": "404: Not Found" });
+                Self::json(StatusCode::NOT_FOUND, error)
+            }
+        }
+    }
+
+    fn is_version(segment: &str) -> bool {
+        segment.len() > 1
+            && segment.starts_with('v')
+            && segment[1..].chars().all(|c| c.is_ascii_digit())
+    }
+
+    fn json(status: StatusCode, value: Value) -> Response<Body> {
+        Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .body(Body::from(value.to_string()))
+            .unwrap()
+    }
+
+    fn empty(status: StatusCode) -> Response<Body> {
+        Response::builder()
+            .status(status)
+            .body(Body::empty())
+            .unwrap()
+    }
+}
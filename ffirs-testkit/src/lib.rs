@@ -0,0 +1,250 @@
+//! Runs the bot against a local imitation of Discord, for end-to-end tests:
+//! messages are handed to the event handler like the gateway would,
+//! and what the bot sends is recorded instead of reaching Discord.
+
+pub mod discord;
+pub mod message;
+
+use discord::{Call, MockDiscord, Recorder};
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use message::MessageBuilder;
+use serenity::{
+    cache::Cache,
+    client::{
+        bridge::gateway::{InterMessage, ShardMessenger},
+        Context, EventHandler,
+    },
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, MessageId, UserId},
+    },
+    prelude::{RwLock, TypeMap},
+};
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+pub const GUILD: GuildId = GuildId(10);
+pub const CHANNEL: ChannelId = ChannelId(20);
+pub const USER: UserId = UserId(30);
+
+/// An event handler plugged to a `MockDiscord`.
+pub struct Harness {
+    discord: MockDiscord,
+    ctx: Context,
+    handler: Box<dyn EventHandler>,
+    next_id: AtomicU64,
+    // Kept so what handlers send to their shard goes somewhere
+    _shard: UnboundedReceiver<InterMessage>,
+}
+
+impl Harness {
+    pub async fn new<H: EventHandler + 'static>(handler: H) -> io::Result<Self> {
+        let discord = MockDiscord::start().await?;
+        let (shard_tx, shard_rx) = mpsc::unbounded();
+
+        let ctx = Context {
+            data: Arc::new(RwLock::new(TypeMap::new())),
+            shard: ShardMessenger::new(shard_tx),
+            shard_id: 0,
+            http: discord.http(),
+            cache: Arc::new(Cache::default()),
+        };
+
+        Ok(Self {
+            discord,
+            ctx,
+            handler: Box::new(handler),
+            next_id: AtomicU64::new(1),
+            _shard: shard_rx,
+        })
+    }
+
+    /// The context given to the handler, its data can be filled before sending messages.
+    pub fn ctx(&self) -> &Context {
+        &self.ctx
+    }
+
+    pub fn recorder(&self) -> &Arc<Recorder> {
+        self.discord.recorder()
+    }
+
+    /// A message of `USER` in `CHANNEL` of `GUILD`, to change before giving it to `send`.
+    pub fn message<S: Into<String>>(&self, content: S) -> MessageBuilder {
+        let id = MessageId(self.next_id.fetch_add(1, Ordering::SeqCst));
+        MessageBuilder::new(id, CHANNEL, USER)
+            .guild(GUILD)
+            .content(content)
+    }
+
+    /// Hand `message` to the handler, giving back what it sent meanwhile.
+    pub async fn send(&self, message: MessageBuilder) -> Vec<Call> {
+        self.send_message(message.build()).await
+    }
+
+    pub async fn send_message(&self, message: Message) -> Vec<Call> {
+        let before = self.recorder().len();
+        self.handler.message(self.ctx.clone(), message).await;
+        self.recorder().since(before)
+    }
+
+    /// Same as `send` for a message only made of `content`.
+    pub async fn say<S: Into<String>>(&self, content: S) -> Vec<Call> {
+        self.send(self.message(content)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::discord::Call;
+    use crate::{Harness, CHANNEL, USER};
+    use ffirs_commands::{
+        cmd_args::CmdArgs, cmd_creator::CmdCreator, cmd_handler::CmdHandler,
+        cmd_manager::CmdManager, cmd_tree::CmdNode, context::CmdContext, error::CmdResult,
+        perms::PermResolver,
+    };
+    use ffirs_core::{
+        awaiter::{Awaiters, MessageFilter},
+        event_handler::{Handler, Services},
+        locale::core_locales,
+        module_manager::ModuleManager,
+        prefix::PrefixResolver,
+        shutdown::{shutdown_commands, Shutdown, ShutdownKind},
+        state::SharedState,
+    };
+    use serenity::{async_trait, model::id::UserId};
+    use std::{sync::Arc, time::Duration};
+
+    const OWNER: UserId = UserId(99);
+
+    struct AddCmd;
+
+    #[async_trait]
+    impl CmdHandler for AddCmd {
+        async fn execute(&self, ctx: &dyn CmdContext, args: &mut CmdArgs) -> CmdResult<()> {
+            let a = args.take::<i64>("a").unwrap_or_default();
+            let b = args.take::<i64>("b").unwrap_or_default();
+            ctx.reply(&(a + b).to_string()).await?;
+            ctx.react("✅").await
+        }
+    }
+
+    /// Asks for a confirmation and repeats it.
+    struct AskCmd {
+        awaiters: Arc<Awaiters>,
+    }
+
+    #[async_trait]
+    impl CmdHandler for AskCmd {
+        async fn execute(&self, ctx: &dyn CmdContext, _args: &mut CmdArgs) -> CmdResult<()> {
+            let pending = self.awaiters.message(MessageFilter::reply_to(ctx));
+            ctx.reply("Sure?").await?;
+            let answer = pending.wait(Duration::from_secs(5)).await?;
+            ctx.reply(&answer.content).await
+        }
+    }
+
+    async fn make_harness() -> (Harness, Arc<Shutdown>, Arc<Awaiters>) {
+        let manager = CmdManager::with_default_mappers()
+            .with_middleware(PermResolver::with_owners(vec![OWNER]));
+        let locales = Arc::new(core_locales().unwrap());
+        let shutdown = Arc::new(Shutdown::default());
+        let awaiters = Arc::new(Awaiters::new());
+
+        let mut commands = shutdown_commands(shutdown.clone(), locales.clone()).unwrap();
+        commands.push(CmdCreator::create_cmd("add <a: Signed> <b: Signed>", AddCmd).unwrap());
+        let ask = AskCmd {
+            awaiters: awaiters.clone(),
+        };
+        commands.push(CmdCreator::create_cmd("ask", ask).unwrap());
+        let handler = Handler::new(Services {
+            manager: Arc::new(manager),
+            tree: Arc::new(CmdNode::root(commands)),
+            prefixes: Arc::new(PrefixResolver::new(vec!["!"])),
+            locales,
+            modules: Arc::new(ModuleManager::new()),
+            shutdown: shutdown.clone(),
+            awaiters: awaiters.clone(),
+            state: SharedState::new(),
+        });
+
+        (Harness::new(handler).await.unwrap(), shutdown, awaiters)
+    }
+
+    #[tokio::test]
+    pub async fn test_command_reply() {
+        let (harness, _, _) = make_harness().await;
+
+        let calls = harness.say("!add 2 -5").await;
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].content(), Some("-3"));
+        assert!(matches!(&calls[1], Call::React { channel, emoji, .. }
+            if *channel == CHANNEL && emoji == "✅"));
+
+        // Without the prefix, or from a bot, nothing is a command
+        assert!(harness.say("add 2 -5").await.is_empty());
+        assert!(harness
+            .send(harness.message("!add 1 1").bot(true))
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn test_command_errors() {
+        let (harness, shutdown, _) = make_harness().await;
+
+        let calls = harness.say("!add 2 two").await;
+        assert_eq!(calls.len(), 1);
+        assert_eq!(
+            calls[0].embed_field("title"),
+            Some("No matching path found")
+        );
+        assert!(calls[0].content().is_none());
+
+        // Messages merely starting with the prefix aren't answered
+        assert!(harness.say("!!").await.is_empty());
+        assert!(harness.say("!hello there").await.is_empty());
+
+        // Permissions are checked before the handler runs
+        let calls = harness.say("!shutdown").await;
+        assert_eq!(calls.len(), 1);
+        let title = calls[0].embed_field("title").unwrap_or_default();
+        assert!(title.starts_with("Missing permission"));
+        assert!(!shutdown.is_shutting_down());
+
+        let calls = harness
+            .send(harness.message("!shutdown").author(OWNER))
+            .await;
+        assert_eq!(calls[0].content(), Some("Shutting down..."));
+        assert!(shutdown.is_shutting_down());
+
+        // Nothing new is accepted once shutting down
+        assert!(harness
+            .send(harness.message("!add 1 1").author(USER))
+            .await
+            .is_empty());
+        assert_eq!(harness.recorder().len(), 3);
+    }
+
+    #[tokio::test]
+    pub async fn test_prompt_answered_while_shutting_down() {
+        let (harness, shutdown, awaiters) = make_harness().await;
+
+        // The command waits for its answer, which a shutdown waits for
+        let answer = async {
+            awaiters.prompted().await;
+            shutdown.request(ShutdownKind::Stop);
+            harness.say("yes").await;
+        };
+        let (calls, _) = tokio::join!(harness.say("!ask"), answer);
+
+        let replies = calls.iter().map(Call::content).collect::<Vec<_>>();
+        assert_eq!(replies, vec![Some("Sure?"), Some("yes")]);
+        assert_eq!(awaiters.waiting(), 0);
+        assert!(harness.say("!ask").await.is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+use serde_json::{json, Value};
+use serenity::model::{
+    channel::Message,
+    id::{ChannelId, GuildId, MessageId, UserId},
+};
+
+const TIMESTAMP: &str = "2020-06-01T12:00:00+00:00";
+
+/// A user as Discord sends it.
+pub fn user_json(id: UserId, name: &str, bot: bool) -> Value {
+    json!({
+        "id": id.0.to_string(),
+        "username": name,
+        "discriminator": "0001",
+        "avatar": null,
+        "bot": bot,
+    })
+}
+
+/// Builds the messages fed to the handler, and the ones the mock answers with.
+#[derive(Debug, Clone)]
+pub struct MessageBuilder {
+    id: MessageId,
+    channel: ChannelId,
+    guild: Option<GuildId>,
+    author: UserId,
+    author_name: String,
+    bot: bool,
+    content: String,
+    replied: Option<MessageId>,
+}
+
+impl MessageBuilder {
+    pub fn new(id: MessageId, channel: ChannelId, author: UserId) -> Self {
+        Self {
+            id,
+            channel,
+            guild: None,
+            author,
+            author_name: format!("user{}", author.0),
+            bot: false,
+            content: String::new(),
+            replied: None,
+        }
+    }
+
+    pub fn content<S: Into<String>>(mut self, content: S) -> Self {
+        self.content = content.into();
+        self
+    }
+
+    /// Sent in `guild` rather than in direct messages.
+    pub fn guild(mut self, guild: GuildId) -> Self {
+        self.guild = Some(guild);
+        self
+    }
+
+    pub fn direct(mut self) -> Self {
+        self.guild = None;
+        self
+    }
+
+    pub fn channel(mut self, channel: ChannelId) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    pub fn author(mut self, author: UserId) -> Self {
+        self.author = author;
+        self.author_name = format!("user{}", author.0);
+        self
+    }
+
+    pub fn bot(mut self, bot: bool) -> Self {
+        self.bot = bot;
+        self
+    }
+
+    /// Answering message `replied` of the same channel.
+    pub fn reply_to(mut self, replied: MessageId) -> Self {
+        self.replied = Some(replied);
+        self
+    }
+
+    pub fn to_json(&self) -> Value {
+        let reference = self.replied.map(|replied| {
+            json!({
+                "message_id": replied.0.to_string(),
+                "channel_id": self.channel.0.to_string(),
+                "guild_id": self.guild.map(|guild| guild.0.to_string()),
+            })
+        });
+
+        json!({
+            "id": self.id.0.to_string(),
+            "channel_id": self.channel.0.to_string(),
+            "guild_id": self.guild.map(|guild| guild.0.to_string()),
+            "author": user_json(self.author, &self.author_name, self.bot),
+            "content": self.content,
+            "timestamp": TIMESTAMP,
+            "edited_timestamp": null,
+            "tts": false,
+            "mention_everyone": false,
+            "mentions": [],
+            "mention_roles": [],
+            "attachments": [],
+            "embeds": [],
+            "reactions": [],
+            "nonce": null,
+            "pinned": false,
+            "webhook_id": null,
+            "type": 0,
+            "message_reference": reference,
+        })
+    }
+
+    pub fn build(&self) -> Message {
+        serde_json::from_value(self.to_json()).expect("Synthetic message rejected by serenity")
+    }
+}